@@ -0,0 +1,299 @@
+//! 调色板：将归一化的逃逸值映射为RGB颜色。
+
+/// 一组均匀分布的颜色节点，相邻节点之间线性插值。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    /// 查找颜色时位置的循环偏移量，范围为0到1，见`rotated`。
+    offset: f64,
+}
+
+impl Palette {
+    /// 由颜色节点创建调色板，至少需要一个颜色。
+    pub fn new(colors: Vec<[u8; 3]>) -> Result<Palette, String> {
+        if colors.is_empty() {
+            return Err("调色板中没有颜色".to_string());
+        }
+        Ok(Palette {
+            colors,
+            offset: 0.0,
+        })
+    }
+
+    /// 按名称取出内置调色板：`gray`、`fire`、`ocean`或`rainbow`。
+    pub fn builtin(name: &str) -> Option<Palette> {
+        let colors = match name {
+            "gray" => vec![[0, 0, 0], [255, 255, 255]],
+            "fire" => vec![
+                [0, 0, 0],
+                [160, 0, 0],
+                [255, 120, 0],
+                [255, 230, 60],
+                [255, 255, 255],
+            ],
+            "ocean" => vec![[0, 0, 30], [0, 40, 120], [0, 150, 200], [170, 240, 255]],
+            "rainbow" => vec![
+                [148, 0, 211],
+                [0, 0, 255],
+                [0, 200, 0],
+                [255, 255, 0],
+                [255, 127, 0],
+                [255, 0, 0],
+            ],
+            _ => return None,
+        };
+        Some(Palette {
+            colors,
+            offset: 0.0,
+        })
+    }
+
+    /// 解析GIMP调色板（`.gpl`）文本。
+    ///
+    /// 每行颜色由三个0到255的整数和可选的名称组成；`GIMP Palette`、`Name:`和`Columns:`
+    /// 开头的行、以`#`开头的注释行以及空行都会被忽略。
+    pub fn parse_gpl(text: &str) -> Result<Palette, String> {
+        let mut colors = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("GIMP Palette")
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+
+            let channels: Vec<Option<u8>> = line
+                .split_whitespace()
+                .take(3)
+                .map(|v| v.parse().ok())
+                .collect();
+            match channels[..] {
+                [Some(r), Some(g), Some(b)] => colors.push([r, g, b]),
+                _ => return Err(format!("调色板第{}行无效: {}", index + 1, line)),
+            }
+        }
+        Palette::new(colors)
+    }
+
+    /// 读取并解析`path`处的`.gpl`调色板文件。
+    pub fn load_gpl(path: &str) -> Result<Palette, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("读取调色板文件'{}'失败: {}", path, e))?;
+        Palette::parse_gpl(&text)
+    }
+
+    /// 由渐变色带图像（PNG等`image`能解码的格式）创建调色板，第一行的像素从左到右依次成为颜色节点。
+    ///
+    /// 色带通常只有一行高；更高的图像只使用最上面一行，透明通道被忽略。
+    pub fn parse_image(data: &[u8]) -> Result<Palette, String> {
+        let strip = image::load_from_memory(data)
+            .map_err(|e| format!("无法解码调色板图像: {}", e))?
+            .to_rgb();
+        let width = strip.dimensions().0 as usize;
+        let colors = strip.into_raw()[..width * 3]
+            .chunks(3)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        Palette::new(colors)
+    }
+
+    /// 读取并解析`path`处的调色板图像，见`parse_image`。
+    pub fn load_image(path: &str) -> Result<Palette, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("读取调色板图像'{}'失败: {}", path, e))?;
+        Palette::parse_image(&data)
+    }
+
+    /// 调色板的颜色节点。
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// 取出位置`t`处的颜色，`t`为0时是第一个颜色，为1时是最后一个颜色，超出范围时截断。
+    pub fn lookup(&self, t: f64) -> [u8; 3] {
+        self.lookup_exact(t).map(|value| value.round() as u8)
+    }
+
+    /// 与`lookup`相同，但不对插值结果取整，各通道的范围为0.0到255.0。
+    pub fn lookup_exact(&self, t: f64) -> [f64; 3] {
+        let last = self.colors.len() - 1;
+        let shifted = t.clamp(0.0, 1.0) + self.offset;
+        let t = if shifted > 1.0 {
+            shifted - 1.0
+        } else {
+            shifted
+        };
+        let position = t * last as f64;
+        let index = (position.floor() as usize).min(last);
+        let next = (index + 1).min(last);
+        let frac = position - index as f64;
+
+        let mut color = [0.0; 3];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let (a, b) = (
+                self.colors[index][channel] as f64,
+                self.colors[next][channel] as f64,
+            );
+            *value = a + (b - a) * frac;
+        }
+        color
+    }
+
+    /// 返回将调色板循环旋转`offset`后的调色板，用于颜色循环动画。
+    ///
+    /// 旋转后位置`t`处的颜色为原调色板中`t + offset`处的颜色，超过1的部分从头开始，
+    /// 因此`offset`为整数时与原调色板相同。
+    pub fn rotated(&self, offset: f64) -> Palette {
+        Palette {
+            colors: self.colors.clone(),
+            offset: (self.offset + offset).rem_euclid(1.0),
+        }
+    }
+
+    /// 在0到1之间均匀取`size`个位置预先计算颜色，返回查找表，`size`小于2时按2计算。
+    ///
+    /// 位置`i / (size - 1)`处的表项与`lookup`在同一位置的结果完全相同。
+    pub fn table(&self, size: usize) -> PaletteTable {
+        let size = size.max(2);
+        let last = (size - 1) as f64;
+        PaletteTable {
+            colors: (0..size).map(|i| self.lookup(i as f64 / last)).collect(),
+        }
+    }
+}
+
+/// 由`Palette::table`预先计算的颜色表，着色时只需读表，不再逐个像素插值。
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaletteTable {
+    colors: Vec<[u8; 3]>,
+}
+
+impl PaletteTable {
+    /// 取出与位置`t`最近的表项，`t`超出0到1的范围时截断。
+    pub fn lookup(&self, t: f64) -> [u8; 3] {
+        let last = self.colors.len() - 1;
+        let index = (t.clamp(0.0, 1.0) * last as f64).round() as usize;
+        self.colors[index.min(last)]
+    }
+}
+
+/// 以`pivot`为中心重新分配归一化逃逸值`t`的对比度，`pivot`处的值映射为0.5。
+///
+/// 使用Schlick的bias曲线`t(1-p) / (p + t(1-2p))`：它平滑、单调，且保持0和1不变。
+/// `pivot`小于0.5时暗处（逃逸慢、靠近集合边界）被拉开，亮处被压缩；大于0.5时相反；
+/// 等于0.5时不做任何改变。`pivot`应在0和1之间（不含两端）。
+pub fn contrast(t: f64, pivot: f64) -> f64 {
+    t * (1.0 - pivot) / (pivot + t * (1.0 - 2.0 * pivot))
+}
+
+#[test]
+fn test_contrast() {
+    for t in [0.0, 0.3, 0.5, 1.0] {
+        assert!((contrast(t, 0.5) - t).abs() < 1e-12);
+    }
+    assert_eq!(contrast(0.0, 0.2), 0.0);
+    assert!((contrast(1.0, 0.2) - 1.0).abs() < 1e-12);
+    assert!((contrast(0.2, 0.2) - 0.5).abs() < 1e-12);
+
+    // 与线性映射相比，暗处的间隔被拉大，亮处的间隔被压缩。
+    let step = |a: f64, b: f64| contrast(b, 0.2) - contrast(a, 0.2);
+    assert!(step(0.05, 0.1) > 0.05);
+    assert!(step(0.8, 0.9) < 0.1);
+    assert!(contrast(0.1, 0.2) > 0.1 && contrast(0.9, 0.2) > 0.9);
+    // 单调递增。
+    for i in 0..100 {
+        assert!(step(i as f64 / 100.0, (i + 1) as f64 / 100.0) > 0.0);
+    }
+}
+
+#[test]
+fn test_palette_table() {
+    let fire = Palette::builtin("fire").unwrap();
+    for palette in [fire.clone(), fire.rotated(0.3)] {
+        // 表的大小减一是255的倍数时，每个整数灰度都落在表项上，结果与直接计算相同。
+        for size in [256, 255 * 16 + 1] {
+            let table = palette.table(size);
+            for shade in 0..=255 {
+                let t = shade as f64 / 255.0;
+                assert_eq!(table.lookup(t), palette.lookup(t));
+            }
+        }
+    }
+    // 其余位置取最近的表项，与直接计算最多差1。
+    let table = fire.table(255 * 16 + 1);
+    for i in 0..=1000 {
+        let t = i as f64 / 1000.0;
+        let (cached, direct) = (table.lookup(t), fire.lookup(t));
+        for channel in 0..3 {
+            assert!(cached[channel].abs_diff(direct[channel]) <= 1);
+        }
+    }
+    assert_eq!(fire.table(0).lookup(2.0), [255, 255, 255]);
+}
+
+#[test]
+fn test_palette_lookup() {
+    let palette = Palette::new(vec![[0, 0, 0], [200, 100, 50]]).unwrap();
+    assert_eq!(palette.lookup(0.0), [0, 0, 0]);
+    assert_eq!(palette.lookup(0.5), [100, 50, 25]);
+    assert_eq!(palette.lookup(1.0), [200, 100, 50]);
+    assert_eq!(palette.lookup(2.0), [200, 100, 50]);
+
+    // 灰度调色板与默认的灰度输出一致。
+    let gray = Palette::builtin("gray").unwrap();
+    for shade in 0..=255u8 {
+        assert_eq!(gray.lookup(shade as f64 / 255.0), [shade; 3]);
+    }
+}
+
+#[test]
+fn test_palette_rotated() {
+    let palette = Palette::new(vec![[0, 0, 0], [200, 100, 50]]).unwrap();
+    let rotated = palette.rotated(0.25);
+    assert_eq!(rotated.lookup(0.0), palette.lookup(0.25));
+    assert_eq!(rotated.lookup(0.5), palette.lookup(0.75));
+    // 超过末尾的位置从头开始。
+    assert_eq!(rotated.lookup(0.875), palette.lookup(0.125));
+    assert_eq!(rotated.rotated(0.75), palette);
+    assert_eq!(palette.rotated(1.0), palette);
+}
+
+#[test]
+fn test_parse_gpl() {
+    let text = "GIMP Palette\nName: test\nColumns: 2\n#\n255   0   0\tRed\n  0 128 255 Sky blue\n";
+    assert_eq!(
+        Palette::parse_gpl(text).unwrap().colors(),
+        &[[255, 0, 0], [0, 128, 255]]
+    );
+    assert!(Palette::parse_gpl("GIMP Palette\n1 2\n").is_err());
+    assert!(Palette::parse_gpl("GIMP Palette\n").is_err());
+}
+
+#[test]
+fn test_parse_image() {
+    use image::png::PNGEncoder;
+    use image::ColorType;
+
+    let strip = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [40, 50, 60]];
+    let mut png = Vec::new();
+    PNGEncoder::new(&mut png)
+        .encode(&strip.concat(), 4, 1, ColorType::RGB(8))
+        .unwrap();
+    let palette = Palette::parse_image(&png).unwrap();
+    assert_eq!(palette.colors(), &strip);
+    assert_eq!(palette.lookup(0.0), strip[0]);
+    assert_eq!(palette.lookup(1.0), strip[3]);
+
+    // 多行的图像只取最上面一行。
+    let mut png = Vec::new();
+    let rows = [strip.concat(), vec![9; 12]].concat();
+    PNGEncoder::new(&mut png)
+        .encode(&rows, 4, 2, ColorType::RGB(8))
+        .unwrap();
+    assert_eq!(Palette::parse_image(&png).unwrap().colors(), &strip);
+
+    assert!(Palette::parse_image(b"not a png").is_err());
+}
@@ -0,0 +1,140 @@
+//! 以二进制格式导出每个像素的原始逃逸次数，便于在其他程序中分析。
+//!
+//! 支持两种格式，都按行优先存放`宽 * 高`个小端`u32`，集合内部的点记为`INTERIOR`：
+//!
+//! - 默认格式：4字节魔数`MBHM`，小端`u32`的宽和高，之后是逃逸次数；
+//! - `.npy`格式：NumPy的1.0版文件，形状为`(高, 宽)`，类型为`<u4`，可直接用`numpy.load`读取。
+//!
+//! 另外可以用`histogram_csv`只导出逃逸次数的分布。
+
+/// 集合内部（在迭代上限内没有逃逸）的点在文件中的值。
+pub const INTERIOR: u32 = u32::MAX;
+
+const MAGIC: &[u8; 4] = b"MBHM";
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// 将`bounds`大小的逃逸次数`counts`编码为默认格式。
+pub fn encode(bounds: (usize, usize), counts: &[Option<usize>]) -> Vec<u8> {
+    assert!(counts.len() == bounds.0 * bounds.1);
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&(bounds.0 as u32).to_le_bytes());
+    data.extend_from_slice(&(bounds.1 as u32).to_le_bytes());
+    push_counts(&mut data, counts);
+    data
+}
+
+/// 从文件中读出的逃逸次数。
+#[derive(Debug, PartialEq)]
+pub struct Heatmap {
+    pub bounds: (usize, usize),
+    /// 按行优先排列的逃逸次数，集合内部的点为`None`。
+    pub counts: Vec<Option<usize>>,
+}
+
+/// 解码`encode`的输出。数据不完整或格式不符时返回`None`。
+pub fn decode(data: &[u8]) -> Option<Heatmap> {
+    let rest = data.strip_prefix(MAGIC)?;
+    let width = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    let height = u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?) as usize;
+    let body = &rest[8..];
+    if body.len() != width * height * 4 {
+        return None;
+    }
+    let counts = body
+        .chunks_exact(4)
+        .map(
+            |bytes| match u32::from_le_bytes(bytes.try_into().unwrap()) {
+                INTERIOR => None,
+                count => Some(count as usize),
+            },
+        )
+        .collect();
+    Some(Heatmap {
+        bounds: (width, height),
+        counts,
+    })
+}
+
+/// 将`bounds`大小的逃逸次数`counts`编码为`.npy`格式。
+pub fn encode_npy(bounds: (usize, usize), counts: &[Option<usize>]) -> Vec<u8> {
+    assert!(counts.len() == bounds.0 * bounds.1);
+    let mut header = format!(
+        "{{'descr': '<u4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        bounds.1, bounds.0
+    );
+    // 魔数、版本和头部长度共10字节，头部以换行结尾，整个前缀须对齐到64字节。
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut data = NPY_MAGIC.to_vec();
+    data.extend_from_slice(&[1, 0]);
+    data.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    data.extend_from_slice(header.as_bytes());
+    push_counts(&mut data, counts);
+    data
+}
+
+/// 统计逃逸次数`counts`的分布，编码为CSV：表头之后每行是一个出现过的逃逸次数及其像素数，
+/// 按逃逸次数从小到大排列，最后一行`interior`是集合内部的像素数（可能为0）。
+pub fn histogram_csv(counts: &[Option<usize>]) -> String {
+    let mut buckets = std::collections::BTreeMap::new();
+    let mut interior = 0;
+    for count in counts {
+        match count {
+            Some(count) => *buckets.entry(*count).or_insert(0usize) += 1,
+            None => interior += 1,
+        }
+    }
+    let mut csv = String::from("escape_count,pixels\n");
+    for (count, pixels) in buckets {
+        csv.push_str(&format!("{},{}\n", count, pixels));
+    }
+    csv.push_str(&format!("interior,{}\n", interior));
+    csv
+}
+
+fn push_counts(data: &mut Vec<u8>, counts: &[Option<usize>]) {
+    for count in counts {
+        let value = count.map_or(INTERIOR, |count| count as u32);
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[test]
+fn test_encode() {
+    let counts = vec![Some(0), None, Some(3), Some(254), None, Some(1)];
+    let data = encode((3, 2), &counts);
+    assert_eq!(&data[..12], b"MBHM\x03\0\0\0\x02\0\0\0");
+    assert_eq!(&data[16..20], &[0xff; 4]);
+    assert_eq!(
+        decode(&data),
+        Some(Heatmap {
+            bounds: (3, 2),
+            counts: counts.clone()
+        })
+    );
+    assert_eq!(decode(&data[..data.len() - 1]), None);
+
+    let npy = encode_npy((3, 2), &counts);
+    assert!(npy.starts_with(b"\x93NUMPY\x01\x00"));
+    let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    let header = std::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+    assert!(header.contains("'shape': (2, 3)") && header.ends_with('\n'));
+    assert_eq!(&npy[10 + header_len..], &data[12..]);
+}
+
+#[test]
+fn test_histogram_csv() {
+    let counts = vec![Some(3), None, Some(0), Some(3), None, Some(254)];
+    assert_eq!(
+        histogram_csv(&counts),
+        "escape_count,pixels\n0,1\n3,2\n254,1\ninterior,2\n"
+    );
+    assert_eq!(
+        histogram_csv(&[Some(1)]),
+        "escape_count,pixels\n1,1\ninterior,0\n"
+    );
+}
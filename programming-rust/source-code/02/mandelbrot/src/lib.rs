@@ -0,0 +1,748 @@
+//! Mandelbrot集的计算，以及解析视图参数所需的辅助函数。
+//!
+//! 渲染和输出图像的部分位于`main.rs`中，这里只包含与像素无关的数学部分，
+//! 以及把逃逸值映射为颜色的`color`模块、在PNG中记录渲染参数的`metadata`模块、
+//! 导出原始逃逸次数的`heatmap`模块、以浮点数导出连续逃逸次数的`potential`模块，
+//! 以及用表达式把逃逸次数映射为灰度的`transfer`模块。
+
+pub mod color;
+pub mod heatmap;
+pub mod metadata;
+pub mod potential;
+pub mod transfer;
+
+use num::Complex;
+use std::str::FromStr;
+
+/// 尝试决定`c`是否位于Mandelbrot集中，最多进行`limit`次来作出决策。
+///
+/// 如果`c`不是成员，返回`Some(i)`，其中`i`为`c`离开以原点为中心半径为2区域所需的次数。
+/// 如果`c`是成员（更确切的说是如果迭代了limit次后还无法证明`c`不是其成员），返回`None`。
+pub fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
+    escape_time_with_norm(c, limit, Norm::L2)
+}
+
+/// 与`escape_time`相同，但“半径”按`norm`所选的范数度量。
+pub fn escape_time_with_norm(c: Complex<f64>, limit: usize, norm: Norm) -> Option<usize> {
+    fast_orbit(c, limit, norm).escape
+}
+
+/// 判断`z`是否已经逃逸时使用的范数。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Norm {
+    /// 欧几里得范数，逃逸区域为圆盘。
+    #[default]
+    L2,
+    /// 切比雪夫范数`max(|re|, |im|)`，逃逸区域为正方形。
+    LInf,
+    /// 曼哈顿范数`|re| + |im|`，逃逸区域为菱形。
+    L1,
+}
+
+impl FromStr for Norm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "l2" => Ok(Norm::L2),
+            "linf" => Ok(Norm::LInf),
+            "l1" => Ok(Norm::L1),
+            _ => Err(format!("未知的范数'{}'，可选l2、linf或l1", s)),
+        }
+    }
+}
+
+impl Norm {
+    /// `z`按这个范数是否超出了半径2。
+    pub fn escaped(self, z: Complex<f64>) -> bool {
+        match self {
+            Norm::L2 => z.norm_sqr() > 4.0,
+            Norm::LInf => z.re.abs().max(z.im.abs()) > 2.0,
+            Norm::L1 => z.re.abs() + z.im.abs() > 2.0,
+        }
+    }
+}
+
+#[test]
+fn test_norm() {
+    // 这个点靠近边界：按L2在第15次迭代后逃逸，但此时的z仍在边长为4的正方形内。
+    let c = Complex { re: -0.8, im: 0.2 };
+    assert_eq!(escape_time(c, 255), Some(15));
+    assert_eq!(escape_time_with_norm(c, 255, Norm::LInf), Some(16));
+
+    // 内部点按哪种范数都不会逃逸。
+    for norm in [Norm::L2, Norm::LInf, Norm::L1] {
+        assert_eq!(
+            escape_time_with_norm(Complex { re: -0.1, im: 0.1 }, 255, norm),
+            None
+        );
+    }
+}
+
+/// 判断`c`是否属于Mandelbrot集。
+///
+/// 等价于`escape_time(c, limit).is_none()`。注意返回`true`只表示`c`在`limit`次迭代内
+/// 没有被证明会逃逸，并不能严格证明它属于集合；`limit`越大，判断越准确，但也越慢。
+pub fn in_set(c: Complex<f64>, limit: usize) -> bool {
+    escape_time(c, limit).is_none()
+}
+
+#[test]
+fn test_in_set() {
+    assert!(in_set(Complex { re: 0.0, im: 0.0 }, 255));
+    assert!(!in_set(Complex { re: 2.0, im: 2.0 }, 255));
+}
+
+/// 尝试决定`z`是否位于常数为`c`的Julia集中：从`z`开始迭代`z = z * z + c`，
+/// 返回逃逸所需的次数，迭代`limit`次仍未逃逸时返回`None`。
+///
+/// 与`escape_time`不同，起点是`z`而常数`c`固定，因此不做心形线测试和周期检测。
+pub fn julia_escape_time(
+    mut z: Complex<f64>,
+    c: Complex<f64>,
+    limit: usize,
+    norm: Norm,
+) -> Option<usize> {
+    for i in 0..limit {
+        if norm.escaped(z) {
+            return Some(i);
+        }
+        z = z * z + c;
+    }
+    None
+}
+
+#[test]
+fn test_julia_escape_time() {
+    // c为0时Julia集是单位圆盘。
+    let zero = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(
+        julia_escape_time(Complex { re: 0.9, im: 0.0 }, zero, 255, Norm::L2),
+        None
+    );
+    assert_eq!(
+        julia_escape_time(Complex { re: 1.5, im: 0.0 }, zero, 255, Norm::L2),
+        Some(1)
+    );
+    // 起点为0时与Mandelbrot集的逃逸次数相同。
+    let c = Complex { re: -0.8, im: 0.2 };
+    assert_eq!(
+        julia_escape_time(zero, c, 255, Norm::L2),
+        escape_time(c, 255)
+    );
+}
+
+/// 与`escape_time`相同，但同时返回迭代停止时`z`的值。
+pub fn escape_time_detail(c: Complex<f64>, limit: usize) -> (Option<usize>, Complex<f64>) {
+    escape_time_detail_with_norm(c, limit, Norm::L2)
+}
+
+/// 与`escape_time_detail`相同，但按`norm`判断逃逸。
+pub fn escape_time_detail_with_norm(
+    c: Complex<f64>,
+    limit: usize,
+    norm: Norm,
+) -> (Option<usize>, Complex<f64>) {
+    let orbit = orbit_with_norm(c, limit, norm);
+    (orbit.escape, orbit.z)
+}
+
+/// 迭代`z = z * z + c`的结果。
+///
+/// 默认值表示尚未迭代的点，与集合内部的点一样没有逃逸次数。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Orbit {
+    /// 与`escape_time`的返回值相同。
+    pub escape: Option<usize>,
+    /// 迭代停止时`z`的值。
+    pub z: Complex<f64>,
+    /// 实际进行的迭代次数。
+    pub iterations: usize,
+}
+
+impl Orbit {
+    /// 不做任何优化、一直迭代到逃逸或`limit`次时需要的迭代次数。
+    pub fn naive_iterations(&self, limit: usize) -> usize {
+        self.escape.unwrap_or(limit)
+    }
+}
+
+/// 按`Norm::L2`迭代`c`的轨道，见`orbit_with_norm`。
+pub fn orbit(c: Complex<f64>, limit: usize) -> Orbit {
+    orbit_with_norm(c, limit, Norm::L2)
+}
+
+/// 逐次迭代`c`的轨道，直到按`norm`逃逸或达到`limit`次。
+///
+/// 不做任何提前判定，内部点的`z`就是迭代`limit`次后的值。
+pub fn orbit_with_norm(c: Complex<f64>, limit: usize, norm: Norm) -> Orbit {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for i in 0..limit {
+        if norm.escaped(z) {
+            return Orbit {
+                escape: Some(i),
+                z,
+                iterations: i,
+            };
+        }
+        z = z * z + c;
+    }
+    Orbit {
+        escape: None,
+        z,
+        iterations: limit,
+    }
+}
+
+/// 与`orbit_with_norm`相同，但尽可能提前判定内部点，供渲染使用。
+///
+/// 位于主心形线或周期2圆盘内的点不需要迭代就能判定为内部点；
+/// 其余的点每迭代到2的幂次时记下当前的`z`，之后若`z`回到这个值附近，说明轨道已进入周期循环，
+/// 同样可以提前判定为内部点。外部点的逃逸次数与逐次迭代完全相同，
+/// 但提前判定的内部点的`z`不是迭代`limit`次后的值。
+///
+/// 内部点的轨道可能离开以`Norm::L1`度量的半径2的区域，此时按L1“逃逸”的点里也有内部点，
+/// 因此使用L1时不做心形线和圆盘测试，以保证结果与逐次迭代一致。
+pub fn fast_orbit(c: Complex<f64>, limit: usize, norm: Norm) -> Orbit {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    if norm != Norm::L1 && (in_main_cardioid(c) || in_period2_bulb(c)) {
+        return Orbit {
+            escape: None,
+            z,
+            iterations: 0,
+        };
+    }
+
+    let mut saved = z;
+    for i in 0..limit {
+        if norm.escaped(z) {
+            return Orbit {
+                escape: Some(i),
+                z,
+                iterations: i,
+            };
+        }
+        z = z * z + c;
+        if (z - saved).norm_sqr() < 1e-20 {
+            return Orbit {
+                escape: None,
+                z,
+                iterations: i + 1,
+            };
+        }
+        if (i + 1) & i == 0 {
+            saved = z;
+        }
+    }
+
+    Orbit {
+        escape: None,
+        z,
+        iterations: limit,
+    }
+}
+
+/// 返回`c`的迭代轨迹`z1 = c, z2 = z1 * z1 + c, ...`，直到逃逸或迭代`limit`次。
+///
+/// 与`orbit`不同，逃逸的那个`z`也包含在内。
+pub fn orbit_points(c: Complex<f64>, limit: usize, norm: Norm) -> Vec<Complex<f64>> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    let mut points = Vec::new();
+    for _ in 0..limit {
+        z = z * z + c;
+        points.push(z);
+        if norm.escaped(z) {
+            break;
+        }
+    }
+    points
+}
+
+#[test]
+fn test_orbit_points() {
+    // i的轨迹为i, -1+i, -i, -1+i, -i, ...
+    let points = orbit_points(Complex { re: 0.0, im: 1.0 }, 4, Norm::L2);
+    assert_eq!(
+        points,
+        vec![
+            Complex { re: 0.0, im: 1.0 },
+            Complex { re: -1.0, im: 1.0 },
+            Complex { re: 0.0, im: -1.0 },
+            Complex { re: -1.0, im: 1.0 },
+        ]
+    );
+
+    // 2的轨迹为2, 6，第二步就逃逸了。
+    let points = orbit_points(Complex { re: 2.0, im: 0.0 }, 255, Norm::L2);
+    assert_eq!(points.len(), 2);
+}
+
+/// 判断`c`是否位于Mandelbrot集的主心形线内。
+pub fn in_main_cardioid(c: Complex<f64>) -> bool {
+    let x = c.re - 0.25;
+    let q = x * x + c.im * c.im;
+    q * (q + x) <= c.im * c.im / 4.0
+}
+
+/// 判断`c`是否位于以`-1`为圆心、半径为1/4的周期2圆盘内。
+pub fn in_period2_bulb(c: Complex<f64>) -> bool {
+    let x = c.re + 1.0;
+    x * x + c.im * c.im <= 1.0 / 16.0
+}
+
+#[test]
+fn test_orbit() {
+    let limit = 255;
+    // 原点和-1分别位于主心形线和周期2圆盘内，不需要迭代。
+    for c in [Complex { re: 0.0, im: 0.0 }, Complex { re: -1.0, im: 0.0 }] {
+        let interior = fast_orbit(c, limit, Norm::L2);
+        assert_eq!((interior.escape, interior.iterations), (None, 0));
+        assert_eq!(interior.naive_iterations(limit), limit);
+    }
+    // 不做提前判定时z是真正迭代后的值：-1的轨道在0和-1之间交替，迭代奇数次后停在-1。
+    let full = orbit(Complex { re: -1.0, im: 0.0 }, limit);
+    assert_eq!((full.escape, full.iterations), (None, limit));
+    assert_eq!(full.z, Complex { re: -1.0, im: 0.0 });
+
+    // 外部点的逃逸次数不受优化影响。
+    let exterior = fast_orbit(Complex { re: 0.5, im: 0.5 }, limit, Norm::L2);
+    assert_eq!(exterior.escape, Some(5));
+    assert_eq!(exterior.iterations, exterior.naive_iterations(limit));
+    assert_eq!(exterior, orbit(Complex { re: 0.5, im: 0.5 }, limit));
+
+    // -1.3位于周期4的圆盘中，由周期检测提前判定。
+    let periodic = fast_orbit(Complex { re: -1.3, im: 0.0 }, limit, Norm::L2);
+    assert_eq!(periodic.escape, None);
+    assert!(periodic.iterations < limit);
+}
+
+/// 求内部点`c`的轨道最终进入的吸引循环的周期，即`c`所在双曲分量的周期。
+///
+/// 主心形线和周期2圆盘内的点直接返回1和2；其余的点先迭代`limit`次使轨道收敛，
+/// 再继续迭代至多`max_period`次，找出`z`第一次回到收敛位置附近的步数。
+/// `c`在此期间逃逸，或者轨道收敛太慢而找不到周期时返回`None`。
+pub fn interior_period(c: Complex<f64>, limit: usize, max_period: usize) -> Option<usize> {
+    if in_main_cardioid(c) {
+        return Some(1);
+    }
+    if in_period2_bulb(c) {
+        return Some(2);
+    }
+
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for _ in 0..limit {
+        z = z * z + c;
+        if z.norm_sqr() > 4.0 {
+            return None;
+        }
+    }
+    let settled = z;
+    for period in 1..=max_period {
+        z = z * z + c;
+        if (z - settled).norm_sqr() < 1e-12 {
+            return Some(period);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_interior_period() {
+    let period = |re: f64, im: f64| interior_period(Complex { re, im }, 1000, 64);
+    assert_eq!(period(0.0, 0.0), Some(1));
+    assert_eq!(period(-1.0, 0.0), Some(2));
+    // 以下的点不在心形线和圆盘的快速测试范围内，需要实际找出周期。
+    assert_eq!(period(-1.31, 0.0), Some(4));
+    assert_eq!(period(-1.7549, 0.0), Some(3));
+    assert_eq!(period(-0.1226, 0.7449), Some(3));
+    assert_eq!(period(0.5, 0.5), None);
+}
+
+/// 返回`c`平滑（连续）的逃逸次数，即归一化迭代次数`i + 1 - log2(ln|z|)`。
+///
+/// 整数逃逸次数会在图像中形成明显的色带，平滑值则在相邻的逃逸次数之间连续变化。
+/// `c`未在`limit`次内逃逸时返回`None`。
+pub fn smooth_escape_time(c: Complex<f64>, limit: usize) -> Option<f64> {
+    let (escape, z) = escape_time_detail(c, limit);
+    escape.map(|i| smooth_count(i, z))
+}
+
+/// 由整数逃逸次数`escape`和逃逸时`z`的值计算平滑逃逸次数。
+pub fn smooth_count(escape: usize, z: Complex<f64>) -> f64 {
+    (escape as f64 + 1.0 - z.norm().ln().log2()).max(0.0)
+}
+
+#[test]
+fn test_smooth_escape_time() {
+    assert_eq!(smooth_escape_time(Complex { re: 0.0, im: 0.0 }, 255), None);
+
+    // 平滑值与整数逃逸次数相差不到2。
+    for re in [-1.9, -0.8, 0.3, 0.5, 1.2] {
+        let c = Complex { re, im: 0.4 };
+        if let (Some(count), Some(smooth)) = (escape_time(c, 255), smooth_escape_time(c, 255)) {
+            assert!((smooth - count as f64).abs() < 2.0);
+        }
+    }
+}
+
+/// 将字符串`s`解析为坐标对，如`"400x600"`或`"1.0,0.5"`。
+///
+/// 具体来说，`s`的形式就为<left><sep><right>，其中<sep>是由`separator`所给定的字符，
+/// <left>和<right> 均是字符串，可由`T::from_str`解析。`separator`必须是ASCII字符。
+///
+/// 如果`s`格式正确，返回`Some<(x, y)>`。如解析错误，返回`None`。
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
+    parse_pair_with_unit(s, separator, None).ok()
+}
+
+/// 解析坐标对失败的原因，指明是哪一侧出了问题。
+#[derive(Debug, PartialEq)]
+pub enum PairError {
+    /// 字符串中找不到分隔符。
+    MissingSeparator(char),
+    /// 分隔符左侧的文本无法解析。
+    Left(String),
+    /// 分隔符右侧的文本无法解析。
+    Right(String),
+}
+
+impl std::fmt::Display for PairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (side, text) = match self {
+            PairError::MissingSeparator(separator) => {
+                return write!(f, "缺少分隔符'{}'", separator);
+            }
+            PairError::Left(text) => ("左侧", text),
+            PairError::Right(text) => ("右侧", text),
+        };
+        write!(f, "{}的值'{}'无效", side, text)?;
+        if text.trim() != text {
+            write!(f, "（含有多余的空白）")?;
+        }
+        Ok(())
+    }
+}
+
+/// 与`parse_pair`相同，但每一侧可以带有可选的单位后缀`unit`，如`"800px x 600px"`。
+///
+/// 带单位的一侧允许在两端留有空白；不带单位时必须是`T::from_str`能直接解析的文本。
+/// 若分隔符是单位的一部分（`px`中的`x`），会继续向后寻找真正的分隔符。
+/// 解析失败时返回的`PairError`会指明是哪一侧出错。
+pub fn parse_pair_with_unit<T: FromStr>(
+    s: &str,
+    separator: char,
+    unit: Option<&str>,
+) -> Result<(T, T), PairError> {
+    let index = s
+        .match_indices(separator)
+        .map(|(index, _)| index)
+        .find(|&index| match unit {
+            Some(unit) => !(s[..index + separator.len_utf8()].ends_with(unit)),
+            None => true,
+        })
+        .ok_or(PairError::MissingSeparator(separator))?;
+
+    let (left, right) = (&s[..index], &s[index + separator.len_utf8()..]);
+    let parse_side = |side: &str| {
+        let value = match unit {
+            Some(unit) => match side.trim().strip_suffix(unit) {
+                Some(value) => value,
+                None => side,
+            },
+            None => side,
+        };
+        T::from_str(value).ok()
+    };
+
+    match (parse_side(left), parse_side(right)) {
+        (Some(l), Some(r)) => Ok((l, r)),
+        (None, _) => Err(PairError::Left(left.to_string())),
+        (_, None) => Err(PairError::Right(right.to_string())),
+    }
+}
+
+#[test]
+fn test_parse_pair() {
+    assert_eq!(parse_pair::<i32>("", ','), None);
+    assert_eq!(parse_pair::<i32>("10,", ','), None);
+    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
+    assert_eq!(parse_pair::<f64>("0.5x", 'x'), None);
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+}
+
+#[test]
+fn test_parse_pair_with_unit() {
+    assert_eq!(
+        parse_pair_with_unit::<usize>("800px x 600px", 'x', Some("px")),
+        Ok((800, 600))
+    );
+    assert_eq!(
+        parse_pair_with_unit::<usize>("800x600px", 'x', Some("px")),
+        Ok((800, 600))
+    );
+    assert_eq!(
+        parse_pair_with_unit::<usize>("800 x600", 'x', Some("px")),
+        Err(PairError::Left("800 ".to_string()))
+    );
+    assert_eq!(
+        PairError::Left("800 ".to_string()).to_string(),
+        "左侧的值'800 '无效（含有多余的空白）"
+    );
+    assert_eq!(
+        parse_pair_with_unit::<usize>("800x60o", 'x', Some("px")),
+        Err(PairError::Right("60o".to_string()))
+    );
+    assert_eq!(
+        parse_pair_with_unit::<usize>("800px", 'x', Some("px")),
+        Err(PairError::MissingSeparator('x'))
+    );
+    assert_eq!(
+        parse_pair_with_unit::<f64>("1.5e-3,-2", ',', None),
+        Ok((1.5e-3, -2.0))
+    );
+}
+
+/// 解析一对以逗号分隔的浮点数值为复数。
+///
+/// 也可以写作极坐标形式`模@辐角`，如`2@0.5`。辐角默认以弧度为单位，
+/// 带有后缀`deg`时以度为单位（如`1@90deg`），后缀`rad`表示弧度。
+pub fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    match s.split_once('@') {
+        Some((r, theta)) => {
+            let r = r.parse::<f64>().ok()?;
+            let theta = match theta.strip_suffix("deg") {
+                Some(degrees) => degrees.parse::<f64>().ok()?.to_radians(),
+                None => theta
+                    .strip_suffix("rad")
+                    .unwrap_or(theta)
+                    .parse::<f64>()
+                    .ok()?,
+            };
+            Some(Complex::from_polar(r, theta))
+        }
+        None => match parse_pair(s, ',') {
+            Some((re, im)) => Some(Complex { re, im }),
+            None => None,
+        },
+    }
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(
+        parse_complex("1.25,-0.0625"),
+        Some(Complex {
+            re: 1.25,
+            im: -0.0625
+        })
+    );
+    assert_eq!(parse_complex(",-0.0625"), None);
+}
+
+#[test]
+fn test_parse_complex_polar() {
+    let close = |c: Option<Complex<f64>>, re: f64, im: f64| {
+        let c = c.unwrap();
+        (c.re - re).abs() < 1e-12 && (c.im - im).abs() < 1e-12
+    };
+    assert!(close(parse_complex("1@90deg"), 0.0, 1.0));
+    assert!(close(parse_complex("2@-180deg"), -2.0, 0.0));
+    assert!(close(
+        parse_complex("2@0.25rad"),
+        2.0 * 0.25f64.cos(),
+        2.0 * 0.25f64.sin()
+    ));
+    assert_eq!(parse_complex("0.5@0"), Some(Complex { re: 0.5, im: 0.0 }));
+
+    for malformed in [
+        "1@", "@90deg", "1@deg", "1@90degs", "1@90,1", "x@1", "1@2@3", "1,2@3",
+    ] {
+        assert_eq!(parse_complex(malformed), None, "{}", malformed);
+    }
+}
+
+/// 给定输出图像中像素的行列，返回复数平面中对应的点。
+///
+/// `bounds`按像素给定图像的宽高。
+/// `pixel`表示图像中具体像素的(column, row)对。
+/// `upper_left`和`lower_right`参数指向指定图像区域的复数平面的点。
+pub fn pixel_to_point(
+    bounds: (usize, usize),
+    pixel: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> Complex<f64> {
+    let (width, height) = (
+        lower_right.re - upper_left.re,
+        upper_left.im - lower_right.im,
+    );
+    Complex {
+        re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
+        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64,
+        // 为什么在这里减？pixel.1越往下越大，但虚部越往上越大。
+    }
+}
+
+#[test]
+fn test_pixel_to_point() {
+    assert_eq!(
+        pixel_to_point(
+            (100, 200),
+            (25, 175),
+            Complex { re: -1.0, im: 1.0 },
+            Complex { re: 1.0, im: -1.0 }
+        ),
+        Complex { re: -0.5, im: 0.75 }
+    );
+}
+
+/// 求复平面上的点`point`所在的像素，是`pixel_to_point`的逆映射。
+///
+/// 像素`(x, y)`覆盖从`pixel_to_point`返回的左上角开始的一个像素大小的矩形。
+/// 浮点误差可能使恰好位于像素左上角的点被算到相邻的像素中，
+/// 因此与整数相差不到`1e-9`个像素的位置视为该整数。
+/// 点不在`upper_left`和`lower_right`围成的视图内时返回`None`。
+pub fn point_to_pixel(
+    bounds: (usize, usize),
+    point: Complex<f64>,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> Option<(usize, usize)> {
+    let (width, height) = (
+        lower_right.re - upper_left.re,
+        upper_left.im - lower_right.im,
+    );
+    let snap = |t: f64| {
+        if (t - t.round()).abs() < 1e-9 {
+            t.round()
+        } else {
+            t.floor()
+        }
+    };
+    let column = snap((point.re - upper_left.re) * bounds.0 as f64 / width);
+    let row = snap((upper_left.im - point.im) * bounds.1 as f64 / height);
+    if column < 0.0 || row < 0.0 || column >= bounds.0 as f64 || row >= bounds.1 as f64 {
+        return None;
+    }
+    Some((column as usize, row as usize))
+}
+
+#[test]
+fn test_point_to_pixel() {
+    let bounds = (100, 75);
+    let upper_left = Complex { re: -1.2, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.2 };
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (x, y), upper_left, lower_right);
+            assert_eq!(
+                point_to_pixel(bounds, point, upper_left, lower_right),
+                Some((x, y))
+            );
+        }
+    }
+
+    // 像素内部的点也落在该像素中。
+    let inside = pixel_to_point(bounds, (10, 20), upper_left, lower_right)
+        + Complex {
+            re: 0.001,
+            im: -0.001,
+        };
+    assert_eq!(
+        point_to_pixel(bounds, inside, upper_left, lower_right),
+        Some((10, 20))
+    );
+
+    // 右下角和视图外的点不属于任何像素。
+    for outside in [
+        lower_right,
+        Complex { re: -1.3, im: 0.3 },
+        Complex { re: -1.1, im: 0.4 },
+    ] {
+        assert_eq!(
+            point_to_pixel(bounds, outside, upper_left, lower_right),
+            None
+        );
+    }
+}
+
+/// 以视图的中心为不动点将视图放大`factor`倍，返回新的左上角和右下角。
+///
+/// `factor`大于1时视图缩小、细节放大，小于1时视图扩大。
+pub fn zoom_view(
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    factor: f64,
+) -> (Complex<f64>, Complex<f64>) {
+    let center = (upper_left + lower_right) / 2.0;
+    let half = (upper_left - lower_right) / (2.0 * factor);
+    (center + half, center - half)
+}
+
+#[test]
+fn test_zoom_view() {
+    let (upper_left, lower_right) = zoom_view(
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 2.0, im: -3.0 },
+        2.0,
+    );
+    assert_eq!(upper_left, Complex { re: -1.0, im: 0.0 });
+    assert_eq!(lower_right, Complex { re: 1.0, im: -2.0 });
+}
+
+/// 绕视图中心对复平面上的点做的仿射变换：先沿实轴方向错切，再逆时针旋转。
+///
+/// 渲染时把每个像素对应的点先交给`apply`，视图因此以倾斜的角度采样，而中心保持不变。
+/// 默认的变换不改变任何点。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ViewTransform {
+    /// 变换的中心，通常是视图的中心。
+    pub center: Complex<f64>,
+    /// 旋转的角度，单位为弧度。
+    pub rotate: f64,
+    /// 错切系数：虚部每增加1，实部增加`shear`。
+    pub shear: f64,
+}
+
+impl ViewTransform {
+    /// 对点`point`做变换。没有旋转和错切时原样返回，不引入任何浮点误差。
+    pub fn apply(&self, point: Complex<f64>) -> Complex<f64> {
+        if self.rotate == 0.0 && self.shear == 0.0 {
+            return point;
+        }
+        let offset = point - self.center;
+        let sheared = Complex {
+            re: offset.re + self.shear * offset.im,
+            im: offset.im,
+        };
+        self.center + sheared * Complex::from_polar(1.0, self.rotate)
+    }
+}
+
+#[test]
+fn test_view_transform() {
+    let point = Complex { re: 3.0, im: 1.0 };
+    assert_eq!(ViewTransform::default().apply(point), point);
+
+    let near = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-12;
+    let center = Complex { re: 1.0, im: 1.0 };
+    let rotate = ViewTransform {
+        center,
+        rotate: std::f64::consts::FRAC_PI_2,
+        shear: 0.0,
+    };
+    // 绕中心逆时针旋转90度，中心不动。
+    assert!(near(rotate.apply(point), Complex { re: 1.0, im: 3.0 }));
+    assert!(near(rotate.apply(center), center));
+
+    let shear = ViewTransform {
+        center,
+        rotate: 0.0,
+        shear: 0.5,
+    };
+    assert!(near(
+        shear.apply(Complex { re: 1.0, im: 3.0 }),
+        Complex { re: 2.0, im: 3.0 }
+    ));
+}
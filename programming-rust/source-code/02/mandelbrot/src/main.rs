@@ -1,186 +1,5761 @@
-use image::png::PNGEncoder;
+use base64::Engine;
+use image::jpeg::JPEGEncoder;
 use image::ColorType;
+use mandelbrot::color::{self, Palette, PaletteTable};
+use mandelbrot::heatmap;
+use mandelbrot::metadata::{embed_params, read_image_params, ImageParams};
+use mandelbrot::potential;
+use mandelbrot::transfer::Transfer;
+use mandelbrot::{
+    escape_time_detail_with_norm, escape_time_with_norm, fast_orbit, in_main_cardioid,
+    interior_period, julia_escape_time, orbit_points, orbit_with_norm, parse_complex, parse_pair,
+    parse_pair_with_unit, pixel_to_point, point_to_pixel, smooth_count, zoom_view, Norm, Orbit,
+    ViewTransform,
+};
 use num::Complex;
+use std::collections::HashMap;
 use std::env;
-use std::fs::File;
-use std::str::FromStr;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// 解析后的命令行参数。
+struct Arguments {
+    filename: String,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    resume: Option<String>,
+    probe: Option<(usize, usize)>,
+    /// 只打印视图的信息，不渲染图像。
+    info: bool,
+    /// 只打印视图内集合面积的估计值，不渲染图像。
+    area: bool,
+    /// 只打印视图中边界最复杂的子区域，作为放大的目标，不渲染图像。
+    auto_interesting: bool,
+    transparent_interior: bool,
+    dither: Dither,
+    options: RenderOptions,
+    animation: Option<Animation>,
+    palette: Option<Palette>,
+    palette_preview: Option<String>,
+    report_savings: bool,
+    /// 在标准错误上显示渲染进度和剩余时间。
+    progress: bool,
+    /// `--timing-log`指定的CSV文件，每次渲染后追加一行耗时记录。
+    timing_log: Option<String>,
+    channels: Option<Channels>,
+    selftest: bool,
+    pyramid: bool,
+    /// 将图像沿右边和下边镜像，输出长宽各为两倍、可以无缝平铺的图像。
+    mirror_tile: bool,
+    /// `--depth`指定的每通道位数，`None`表示自动选择。
+    depth: Option<u8>,
+    /// 传给PNG和JPEG编码器的参数。
+    encoding: Encoding,
+    /// `--orbit`指定的点，渲染后在图像上画出它的迭代轨迹。
+    orbit: Option<Complex<f64>>,
+    /// `--heatmap`指定的文件，用于导出每个像素的原始逃逸次数。
+    heatmap: Option<String>,
+    /// `--histogram`指定的CSV文件，用于导出逃逸次数的分布。
+    histogram: Option<String>,
+    /// `--exr`指定的文件，用于以32位浮点数导出每个像素的平滑逃逸次数。
+    exr: Option<String>,
+    /// 只画出集合内部与外部的分界线，代替填充着色。
+    boundary: bool,
+    /// 在整数逃逸次数变化的地方画出等值线。
+    contours: bool,
+    /// `--adaptive-aa`指定的每个方向上的子采样数，只对边界附近的像素超采样。
+    adaptive_aa: Option<usize>,
+    /// `--aa-threshold`：相邻像素的逃逸次数相差超过它时才超采样，默认为0。
+    aa_threshold: usize,
+    /// `--transfer`指定的传递函数，代替默认的线性灰度。
+    transfer: Option<Transfer>,
+    /// `--contrast-pivot`指定的位置，着色前以它为中心重新分配外部点的亮度。
+    contrast_pivot: Option<f64>,
+    /// 渲染后打印图像的感知哈希。
+    phash: bool,
+    /// 在图像上画出坐标网格和刻度标签。
+    grid: bool,
+    /// 按所在双曲分量的周期为内部点着色。
+    period_color: bool,
+    /// 在图像左下角写出渲染耗时、迭代上限和分辨率。
+    overlay_stats: bool,
+    /// 将PNG图像以base64编码的data URI打印到标准输出，不写入文件。
+    data_uri: bool,
+    /// `--julia-grid`指定的行数和列数，渲染以视图中各点为常数的Julia集网格。
+    julia_grid: Option<(usize, usize)>,
+    /// 除彩色图像外，再把红、绿、蓝通道分别写为灰度图像。
+    split_channels: bool,
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let args = match parse_args(&args[1..]) {
+        Ok(args) => args,
+        Err(e) => {
+            print_usage(&args[0]);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let bounds = args.bounds;
+
+    if args.selftest {
+        std::process::exit(if run_selftest() { 0 } else { 1 });
+    }
+
+    if let Some(path) = &args.palette_preview {
+        let palette = match &args.palette {
+            Some(palette) => palette.clone(),
+            None => Palette::builtin("gray").unwrap(),
+        };
+        write_palette_preview(path, &palette).expect("写入调色板预览出错");
+        return;
+    }
+
+    if args.info {
+        print!("{}", view_info(bounds, args.upper_left, args.lower_right));
+        return;
+    }
+
+    if args.area {
+        let area = estimate_area(bounds, args.upper_left, args.lower_right, args.options);
+        println!("{}", area);
+        return;
+    }
+
+    if args.auto_interesting {
+        let mut counts = vec![None; bounds.0 * bounds.1];
+        render_counts(
+            &mut counts,
+            bounds,
+            args.upper_left,
+            args.lower_right,
+            args.options,
+        );
+        println!(
+            "{}",
+            find_interesting(&counts, bounds, args.upper_left, args.lower_right)
+        );
+        return;
+    }
+
+    if let Some(pixel) = args.probe {
+        print!(
+            "{}",
+            probe_pixel(
+                bounds,
+                pixel,
+                args.upper_left,
+                args.lower_right,
+                args.options.limit,
+                args.options.norm
+            )
+        );
+        return;
+    }
+
+    if let Some(grid) = args.julia_grid {
+        let mut shades = vec![0.0; bounds.0 * bounds.1];
+        render_julia_grid(
+            &mut shades,
+            bounds,
+            args.upper_left,
+            args.lower_right,
+            grid,
+            args.options,
+        );
+        let view = (args.upper_left, args.lower_right);
+        write_shades(&args.filename, &shades, view, &args).expect("写入PNG文件出错");
+        return;
+    }
+
+    if let Some(Channels::ReImIter) = args.channels {
+        let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+        render_channels(
+            &mut pixels,
+            bounds,
+            args.upper_left,
+            args.lower_right,
+            args.options,
+        );
+        let params = ImageParams {
+            bounds,
+            upper_left: args.upper_left,
+            lower_right: args.lower_right,
+        };
+        write_image(
+            &args.filename,
+            &pixels,
+            bounds,
+            ColorType::RGB(8),
+            Some(&params),
+            args.encoding,
+        )
+        .expect("写入PNG文件出错");
+        return;
+    }
+
+    if let Some(animation) = &args.animation {
+        if args.resume.is_some() {
+            eprintln!("--resume不能用于动画");
+            std::process::exit(1);
+        }
+        write_animation(&args, animation).expect("写入动画帧出错");
+        return;
+    }
+
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+
+    // 按下Ctrl-C时各线程渲染完当前行就停下，写出已完成的部分；再按一次则立即退出。
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let cancel = Arc::clone(&cancel);
+        ctrlc::set_handler(move || {
+            if cancel.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+        })
+        .expect("设置Ctrl-C处理函数出错");
+    }
+
+    let new_progress = |done| Progress::new(bounds.1, done, Instant::now());
+    let start = Instant::now();
+
+    // 导出、超采样、等值线和边界检测共用渲染时记下的每个像素的轨道，不再另行计算。
+    let needs_orbits = args.heatmap.is_some()
+        || args.histogram.is_some()
+        || args.exr.is_some()
+        || args.adaptive_aa.is_some()
+        || args.contours
+        || args.boundary;
+    let mut orbits = needs_orbits.then(|| vec![Orbit::default(); bounds.0 * bounds.1]);
+
+    // render(&mut shades, None, bounds, upper_left, lower_right, options);
+    let stats = match &args.resume {
+        None => {
+            let progress = args.progress.then(|| new_progress(0));
+            render_parallel_with(
+                (&mut shades, orbits.as_deref_mut()),
+                bounds,
+                args.upper_left,
+                args.lower_right,
+                args.options,
+                None,
+                RenderControl {
+                    cancel: Some(&cancel),
+                    progress: progress.as_ref(),
+                },
+            )
+        }
+        Some(path) => {
+            let (checkpoint, done) = Checkpoint::open(
+                path,
+                bounds,
+                args.upper_left,
+                args.lower_right,
+                args.options,
+                &mut shades,
+                orbits.as_deref_mut(),
+            )
+            .expect("打开检查点文件出错");
+            let progress = args
+                .progress
+                .then(|| new_progress(done.iter().filter(|&&done| done).count()));
+            render_parallel_with(
+                (&mut shades, orbits.as_deref_mut()),
+                bounds,
+                args.upper_left,
+                args.lower_right,
+                args.options,
+                Some((&checkpoint, &done)),
+                RenderControl {
+                    cancel: Some(&cancel),
+                    progress: progress.as_ref(),
+                },
+            )
+        }
+    };
+    let elapsed = start.elapsed();
+    if args.progress {
+        // 结束进度所在的行。
+        eprintln!();
+    }
+    if let Some(path) = &args.timing_log {
+        let timing = Timing {
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            bounds,
+            iterations: args.options.limit,
+            threads: render_threads(bounds, args.options),
+            elapsed,
+        };
+        append_timing(path, &timing).expect("写入耗时记录出错");
+    }
+    let cancelled = cancel.load(Ordering::Relaxed);
+    if cancelled {
+        eprintln!("渲染已中断，未完成的行保留为集合内部的颜色");
+    }
+
+    if args.report_savings {
+        println!("{}", stats);
+    }
+
+    // 中断后图像不完整，依赖轨道的步骤都不再进行。
+    let orbits = orbits.filter(|_| !cancelled);
+    let counts: Option<Vec<Option<usize>>> = orbits
+        .as_ref()
+        .map(|orbits| orbits.iter().map(|orbit| orbit.escape).collect());
+
+    if let (Some(path), Some(counts)) = (&args.heatmap, &counts) {
+        write_heatmap(path, counts, bounds).expect("写入逃逸次数文件出错");
+    }
+    if let (Some(path), Some(counts)) = (&args.histogram, &counts) {
+        write_histogram(path, counts).expect("写入逃逸次数分布文件出错");
+    }
+    if let (Some(path), Some(orbits)) = (&args.exr, &orbits) {
+        write_potential(path, bounds, orbits).expect("写入EXR文件出错");
+    }
+
+    if let (Some(samples), Some(counts)) = (args.adaptive_aa, &counts) {
+        adaptive_antialias(
+            &mut shades,
+            &aa_targets(counts, bounds, args.aa_threshold),
+            bounds,
+            args.upper_left,
+            args.lower_right,
+            args.options,
+            samples,
+        );
+    }
+
+    if let Some(transfer) = &args.transfer {
+        apply_transfer(&mut shades, transfer, args.options.limit);
+    }
+
+    if let Some(pivot) = args.contrast_pivot {
+        apply_contrast(&mut shades, pivot);
+    }
+
+    if let (true, Some(counts)) = (args.contours, &counts) {
+        draw_contours(&mut shades, counts, bounds);
+    }
+
+    if let (true, Some(counts)) = (args.boundary, &counts) {
+        shades = boundary(counts, bounds);
+    }
+
+    if let Some(c) = args.orbit {
+        let points = orbit_points(c, args.options.limit, args.options.norm);
+        draw_orbit(
+            &mut shades,
+            bounds,
+            args.upper_left,
+            args.lower_right,
+            &points,
+        );
+    }
+
+    if args.grid {
+        draw_grid(&mut shades, bounds, args.upper_left, args.lower_right);
+    }
+
+    if args.overlay_stats {
+        draw_stats(&mut shades, bounds, elapsed, args.options.limit);
+    }
+
+    if args.phash {
+        println!("感知哈希: {:016x}", phash(&shades, bounds));
+    }
+
+    let view = (args.upper_left, args.lower_right);
+    if args.data_uri {
+        println!(
+            "{}",
+            data_uri(&shades, view, &args).expect("编码PNG数据出错")
+        );
+    } else if args.pyramid {
+        write_pyramid(&args.filename, &shades, view, &args).expect("写入PNG文件出错");
+    } else {
+        write_shades(&args.filename, &shades, view, &args).expect("写入PNG文件出错");
+    }
+    if args.split_channels {
+        write_split_channels(&args.filename, &shades, view, &args).expect("写入PNG文件出错");
+    }
+
+    if cancelled {
+        // 保留检查点，之后可以用同样的--resume继续渲染。
+        std::process::exit(130);
+    }
+    if let Some(path) = &args.resume {
+        // 图像已完整写出，检查点不再需要。
+        std::fs::remove_file(path).expect("删除检查点文件出错");
+    }
+}
+
+fn print_usage(program: &str) {
+    eprintln!(
+        "Usage: {} [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT",
+        program
+    );
+    eprintln!(
+        "       {} [OPTIONS] --continue IMAGE [--zoom FACTOR] FILE",
+        program
+    );
+    eprintln!(
+        "       {} [OPTIONS] --info PIXELS UPPERLEFT LOWERRIGHT",
+        program
+    );
+    eprintln!(
+        "       {} [OPTIONS] --area PIXELS UPPERLEFT LOWERRIGHT",
+        program
+    );
+    eprintln!(
+        "       {} [OPTIONS] --auto-interesting PIXELS UPPERLEFT LOWERRIGHT",
+        program
+    );
+    eprintln!(
+        "       {} [OPTIONS] --data-uri PIXELS UPPERLEFT LOWERRIGHT",
+        program
+    );
+    eprintln!(
+        "Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
+        program
+    );
+    eprintln!("复数UPPERLEFT、LOWERRIGHT等也可以写作极坐标“模@辐角”，如1@90deg，辐角默认为弧度");
+    eprintln!("Options:");
+    eprintln!("    --resume CHECKPOINT      将进度记录在检查点文件中，中断后可从中继续渲染");
+    eprintln!("    --probe COL,ROW          打印单个像素的逃逸信息，不渲染图像");
+    eprintln!("    --info                   打印视图的尺寸、每像素的分辨率等信息，不渲染图像");
+    eprintln!("    --auto-interesting       （实验性）将视图分为4x4个子区域，打印逃逸次数变化最多的一个作为放大目标");
+    eprintln!("    --area                   以内部像素的比例估计视图内集合的面积，不渲染图像；PIXELS和--iterations越大越准确");
+    eprintln!("    --transparent-interior   输出RGBA图像，集合内部透明");
+    eprintln!("    --smooth                 使用平滑的逃逸次数着色");
+    eprintln!("    --rotate DEG             绕视图中心将采样的区域逆时针旋转DEG度，图像中的内容因此顺时针转动");
+    eprintln!(
+        "    --shear K                采样前沿实轴方向错切，虚部每增加1实部增加K，视图中心不变"
+    );
+    eprintln!(
+        "    --chunk-rows N           并行渲染时将图像分成每块N行的小条带，由空闲的线程依次领取"
+    );
+    eprintln!("    --norm l2|linf|l1        判断逃逸时使用的范数，默认为l2（圆盘）");
+    eprintln!("    --iterations N           迭代次数的上限，默认为255");
+    eprintln!("    --preset NAME            使用著名位置的角点和迭代上限：seahorse、elephant、triple-spiral或minibrot，");
+    eprintln!(
+        "                             此时可以只给出FILE和PIXELS，明确给出的角点和--iterations优先"
+    );
+    eprintln!("    --depth 8|16             每个通道的位数，默认在平滑着色时用16位");
+    eprintln!("    --compression fast|best  PNG的压缩级别，best的文件更小但更慢");
+    eprintln!("    --quality N              FILE以.jpg或.jpeg结尾时输出JPEG图像，N为1到100的质量");
+    eprintln!("    --dither ordered|fs      量化为8位时使用有序抖动或Floyd–Steinberg抖动");
+    eprintln!("    --frames N               渲染N帧的缩放动画，FILE为输出帧的目录");
+    eprintln!("    --name-template T        动画帧的文件名，可用{{index}}、{{zoom}}、{{re}}和{{im}}，默认为frame_{{index}}.png");
+    eprintln!(
+        "    --zoom FACTOR            动画从首帧到末帧、或--continue时围绕中心放大的倍数，默认为2"
+    );
+    eprintln!("    --color-cycle-offset X   动画的各帧视图相同，第k帧的调色板循环旋转k*X（调色板全长为1），产生流动的颜色");
+    eprintln!("    --gif                    将动画的各帧合成一个GIF动画写入FILE，而不是把各帧写入目录FILE");
+    eprintln!("    --fps N                  GIF动画每秒的帧数，默认为10");
+    eprintln!("    --motion-blur N          动画的每帧取N个子帧的平均，产生运动模糊");
+    eprintln!(
+        "    --cache TOLERANCE        动画各帧共享轨道缓存，坐标相差不超过TOLERANCE的点视为同一点"
+    );
+    eprintln!("    --palette NAME           使用内置调色板着色：gray、fire、ocean或rainbow");
+    eprintln!("    --palette-file FILE      使用GIMP调色板文件（.gpl）着色");
+    eprintln!("    --palette-image FILE     使用渐变色带图像着色，最上面一行的像素从左到右依次为调色板的颜色");
+    eprintln!("    --palette-preview FILE   将调色板写为一条渐变色带，不渲染图像");
+    eprintln!("    --julia-grid ROWSxCOLS   将图像分为ROWS行COLS列，每格画出以视图中对应位置的点为常数c的Julia集");
+    eprintln!("    --channels reim-iter     以最终z的实部、虚部和逃逸次数分别作为红、绿、蓝通道");
+    eprintln!("    --split-channels         使用调色板或--period-color时，另外将红、绿、蓝通道写为灰度图像FILE_r.png、FILE_g.png和FILE_b.png");
+    eprintln!(
+        "    --mirror-tile            将图像沿右边和下边镜像，写出长宽各为两倍、可无缝平铺的图像"
+    );
+    eprintln!("    --data-uri               不写入文件，将PNG图像编码为data:image/png;base64,...打印到标准输出");
+    eprintln!("    --pyramid                写出完整的FILE@2x.png、一半大小的FILE.png和四分之一大小的FILE_thumb.png");
+    eprintln!(
+        "    --selftest               渲染几个固定视图并与内置的SHA-256比对，检查构建是否正确"
+    );
+    eprintln!(
+        "    --adaptive-aa N          只对逃逸次数与相邻像素不同的像素做NxN超采样，平坦区域保持1x"
+    );
+    eprintln!(
+        "    --aa-threshold N         --adaptive-aa只对逃逸次数与相邻像素相差超过N的像素超采样，默认为0；越大越快"
+    );
+    eprintln!(
+        "    --transfer EXPR          用表达式EXPR计算外部点的亮度（0到1），可用变量iter和limit，如\"sin(iter*0.1)\""
+    );
+    eprintln!("    --contrast-pivot P       着色前将亮度P（0到1之间）映射为0.5：P较小时拉开靠近边界的暗处，较大时拉开亮处");
+    eprintln!("    --contours               在整数逃逸次数变化处画出细线，像等高线一样叠加在（平滑）着色之上");
+    eprintln!("    --boundary               只用白线画出集合内部与外部的分界，其余像素为黑色");
+    eprintln!(
+        "    --phash                  打印图像的64位感知哈希（dHash），相近的图像哈希只差几位"
+    );
+    eprintln!("    --orbit RE,IM            在图像上用亮点画出该点的迭代轨迹");
+    eprintln!("    --overlay-stats          在图像左下角写出渲染耗时、迭代上限和分辨率");
+    eprintln!("    --period-color           按吸引循环的周期为集合内部着色，外部点的着色不变");
+    eprintln!(
+        "    --interior-shade         按最后一次迭代时|z|的大小为集合内部着色，外部点的着色不变"
+    );
+    eprintln!(
+        "    --grid                   在图像上画出整齐坐标处的网格线，并在上边和左边标出坐标"
+    );
+    eprintln!(
+        "    --heatmap FILE           将每个像素的原始逃逸次数导出到FILE，以.npy结尾时为NumPy格式"
+    );
+    eprintln!(
+        "    --histogram FILE         将逃逸次数的分布（逃逸次数,像素数）以CSV写入FILE，集合内部的像素数单独在最后一行"
+    );
+    eprintln!(
+        "    --exr FILE               将每个像素的平滑逃逸次数以32位浮点数写入OpenEXR文件FILE"
+    );
+    eprintln!("    --report-savings         打印心形线测试和周期检测节省的迭代次数");
+    eprintln!("    --progress               在标准错误上显示渲染进度和估计的剩余时间");
+    eprintln!(
+        "    --timing-log FILE        每次渲染后向CSV文件FILE追加一行尺寸、迭代上限、线程数和耗时"
+    );
+    eprintln!(
+        "    --continue IMAGE         读取IMAGE中记录的视图，围绕其中心放大--zoom倍后渲染到FILE"
+    );
+}
+
+/// `--preset`可用的著名位置。
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Preset {
+    name: &'static str,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    /// 看清该位置的细节所需的迭代上限。
+    iterations: usize,
+}
+
+/// 各预设位置，视图的宽高比均为4:3。
+const PRESETS: [Preset; 4] = [
+    Preset {
+        name: "seahorse",
+        upper_left: Complex {
+            re: -0.8,
+            im: 0.1375,
+        },
+        lower_right: Complex {
+            re: -0.7,
+            im: 0.0625,
+        },
+        iterations: 500,
+    },
+    Preset {
+        name: "elephant",
+        upper_left: Complex { re: 0.25, im: 0.03 },
+        lower_right: Complex {
+            re: 0.33,
+            im: -0.03,
+        },
+        iterations: 500,
+    },
+    Preset {
+        name: "triple-spiral",
+        upper_left: Complex {
+            re: -0.0935,
+            im: 0.6555,
+        },
+        lower_right: Complex {
+            re: -0.0855,
+            im: 0.6495,
+        },
+        iterations: 1000,
+    },
+    Preset {
+        name: "minibrot",
+        upper_left: Complex {
+            re: -1.7749,
+            im: 0.015,
+        },
+        lower_right: Complex {
+            re: -1.7349,
+            im: -0.015,
+        },
+        iterations: 1000,
+    },
+];
+
+fn find_preset(name: &str) -> Option<Preset> {
+    PRESETS.iter().find(|preset| preset.name == name).copied()
+}
+
+#[test]
+fn test_preset() {
+    let to_args = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    };
+    let args = to_args(&["--preset", "seahorse", "out.png", "400x300"]).unwrap();
+    assert_eq!(args.bounds, (400, 300));
+    assert_eq!(
+        args.upper_left,
+        Complex {
+            re: -0.8,
+            im: 0.1375
+        }
+    );
+    assert_eq!(
+        args.lower_right,
+        Complex {
+            re: -0.7,
+            im: 0.0625
+        }
+    );
+    assert_eq!(args.options.limit, 500);
+
+    // 明确给出的迭代上限和角点优先于预设，与选项的先后无关。
+    let args = to_args(&[
+        "--iterations",
+        "2000",
+        "--preset",
+        "seahorse",
+        "out.png",
+        "400x300",
+    ])
+    .unwrap();
+    assert_eq!(args.options.limit, 2000);
+    assert_eq!(
+        args.upper_left,
+        Complex {
+            re: -0.8,
+            im: 0.1375
+        }
+    );
+    let args = to_args(&["--preset", "minibrot", "out.png", "4x3", "-2,1", "1,-1"]).unwrap();
+    assert_eq!(args.upper_left, Complex { re: -2.0, im: 1.0 });
+    assert_eq!(args.options.limit, 1000);
+
+    assert_eq!(
+        to_args(&["out.png", "4x3", "-2,1", "1,-1"])
+            .unwrap()
+            .options
+            .limit,
+        255
+    );
+    assert!(to_args(&["--preset", "cathedral", "out.png", "4x3"]).is_err());
+    assert!(to_args(&["--iterations", "0", "out.png", "4x3", "-2,1", "1,-1"]).is_err());
+}
+
+/// 解析命令行参数`args`（不含程序名）。
+fn parse_args(args: &[String]) -> Result<Arguments, String> {
+    let mut resume = None;
+    let mut probe = None;
+    let mut info = false;
+    let mut area = false;
+    let mut auto_interesting = false;
+    let mut transparent_interior = false;
+    let mut dither = Dither::None;
+    let mut options = RenderOptions::default();
+    let mut iterations = None;
+    let mut rotate = 0.0;
+    let mut shear = 0.0;
+    let mut preset = None;
+    let mut frames = None;
+    let mut zoom = 2.0;
+    let mut motion_blur = 1;
+    let mut cache = None;
+    let mut color_cycle = None;
+    let mut name_template = None;
+    let mut palette = None;
+    let mut palette_preview = None;
+    let mut report_savings = false;
+    let mut progress = false;
+    let mut timing_log = None;
+    let mut continue_from = None;
+    let mut channels = None;
+    let mut selftest = false;
+    let mut pyramid = false;
+    let mut mirror_tile = false;
+    let mut depth = None;
+    let mut encoding = Encoding::default();
+    let mut orbit = None;
+    let mut heatmap = None;
+    let mut histogram = None;
+    let mut exr = None;
+    let mut boundary = false;
+    let mut contours = false;
+    let mut adaptive_aa = None;
+    let mut aa_threshold = None;
+    let mut transfer = None;
+    let mut contrast_pivot = None;
+    let mut phash = false;
+    let mut grid = false;
+    let mut period_color = false;
+    let mut interior_shade = false;
+    let mut overlay_stats = false;
+    let mut data_uri = false;
+    let mut gif = false;
+    let mut fps = None;
+    let mut julia_grid = None;
+    let mut split_channels = false;
+    let mut positional = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--resume" => resume = Some(flag_value(&mut iter, arg)?.to_string()),
+            "--probe" => {
+                let value = flag_value(&mut iter, arg)?;
+                probe = Some(parse_pair::<usize>(value, ',').ok_or("解析探测像素出错")?);
+            }
+            "--info" => info = true,
+            "--area" => area = true,
+            "--auto-interesting" => auto_interesting = true,
+            "--transparent-interior" => transparent_interior = true,
+            "--smooth" => options.smooth = true,
+            "--rotate" => rotate = parse_flag::<f64>(&mut iter, arg)?,
+            "--shear" => shear = parse_flag::<f64>(&mut iter, arg)?,
+            "--norm" => options.norm = flag_value(&mut iter, arg)?.parse()?,
+            "--iterations" => iterations = Some(parse_flag(&mut iter, arg)?),
+            "--preset" => {
+                let name = flag_value(&mut iter, arg)?;
+                preset = Some(find_preset(name).ok_or_else(|| {
+                    format!(
+                        "未知的预设位置'{}'，可选seahorse、elephant、triple-spiral或minibrot",
+                        name
+                    )
+                })?);
+            }
+            "--chunk-rows" => options.chunk_rows = Some(parse_flag(&mut iter, arg)?),
+            "--dither" => {
+                dither = match flag_value(&mut iter, arg)? {
+                    "ordered" => Dither::Ordered,
+                    "fs" => Dither::FloydSteinberg,
+                    other => return Err(format!("未知的抖动方式'{}'，可选ordered或fs", other)),
+                }
+            }
+            "--frames" => frames = Some(parse_flag(&mut iter, arg)?),
+            "--zoom" => zoom = parse_flag(&mut iter, arg)?,
+            "--motion-blur" => motion_blur = parse_flag(&mut iter, arg)?,
+            "--name-template" => name_template = Some(flag_value(&mut iter, arg)?),
+            "--cache" => cache = Some(parse_flag::<f64>(&mut iter, arg)?),
+            "--color-cycle-offset" => color_cycle = Some(parse_flag::<f64>(&mut iter, arg)?),
+            "--palette" => {
+                let name = flag_value(&mut iter, arg)?;
+                palette =
+                    Some(Palette::builtin(name).ok_or_else(|| format!("未知的调色板'{}'", name))?);
+            }
+            "--palette-file" => palette = Some(Palette::load_gpl(flag_value(&mut iter, arg)?)?),
+            "--palette-image" => palette = Some(Palette::load_image(flag_value(&mut iter, arg)?)?),
+            "--palette-preview" => {
+                palette_preview = Some(flag_value(&mut iter, arg)?.to_string());
+            }
+            "--report-savings" => report_savings = true,
+            "--progress" => progress = true,
+            "--timing-log" => timing_log = Some(flag_value(&mut iter, arg)?.to_string()),
+            "--selftest" => selftest = true,
+            "--pyramid" => pyramid = true,
+            "--mirror-tile" => mirror_tile = true,
+            "--boundary" => boundary = true,
+            "--contours" => contours = true,
+            "--phash" => phash = true,
+            "--grid" => grid = true,
+            "--period-color" => period_color = true,
+            "--interior-shade" => interior_shade = true,
+            "--overlay-stats" => overlay_stats = true,
+            "--data-uri" => data_uri = true,
+            "--gif" => gif = true,
+            "--fps" => fps = Some(parse_flag::<usize>(&mut iter, arg)?),
+            "--julia-grid" => {
+                let value = flag_value(&mut iter, arg)?;
+                julia_grid =
+                    Some(parse_pair::<usize>(value, 'x').ok_or("解析--julia-grid的行列数出错")?);
+            }
+            "--split-channels" => split_channels = true,
+            "--adaptive-aa" => adaptive_aa = Some(parse_flag(&mut iter, arg)?),
+            "--aa-threshold" => aa_threshold = Some(parse_flag(&mut iter, arg)?),
+            "--transfer" => transfer = Some(flag_value(&mut iter, arg)?.parse::<Transfer>()?),
+            "--contrast-pivot" => contrast_pivot = Some(parse_flag::<f64>(&mut iter, arg)?),
+            "--depth" => {
+                depth = match flag_value(&mut iter, arg)? {
+                    "8" => Some(8),
+                    "16" => Some(16),
+                    other => return Err(format!("不支持的位深'{}'，可选8或16", other)),
+                }
+            }
+            "--orbit" => {
+                orbit =
+                    Some(parse_complex(flag_value(&mut iter, arg)?).ok_or("解析--orbit的点出错")?)
+            }
+            "--compression" => {
+                encoding.compression = match flag_value(&mut iter, arg)? {
+                    "fast" => png::Compression::Fast,
+                    "best" => png::Compression::Best,
+                    other => return Err(format!("未知的压缩级别'{}'，可选fast或best", other)),
+                }
+            }
+            "--quality" => {
+                let quality: u8 = parse_flag(&mut iter, arg)?;
+                if !(1..=100).contains(&quality) {
+                    return Err("--quality应在1到100之间".to_string());
+                }
+                encoding.quality = Some(quality);
+            }
+            "--heatmap" => heatmap = Some(flag_value(&mut iter, arg)?.to_string()),
+            "--histogram" => histogram = Some(flag_value(&mut iter, arg)?.to_string()),
+            "--exr" => exr = Some(flag_value(&mut iter, arg)?.to_string()),
+            "--continue" => continue_from = Some(flag_value(&mut iter, arg)?),
+            "--channels" => {
+                channels = match flag_value(&mut iter, arg)? {
+                    "reim-iter" => Some(Channels::ReImIter),
+                    other => return Err(format!("未知的通道映射'{}'，可选reim-iter", other)),
+                }
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    // 预览调色板和自检不涉及任何视图，可以省略全部位置参数。
+    let (filename, bounds, upper_left, lower_right) =
+        if (palette_preview.is_some() || selftest) && positional.is_empty() {
+            let origin = Complex { re: 0.0, im: 0.0 };
+            (String::new(), (0, 0), origin, origin)
+        } else if let Some(path) = continue_from {
+            // 沿用之前渲染的尺寸，围绕同一中心再放大`--zoom`倍。
+            if positional.len() != 1 {
+                return Err(format!(
+                    "参数数量不符：使用--continue时需要1个参数，传入了{}个。",
+                    positional.len()
+                ));
+            }
+            let params = read_image_params(path)?;
+            let (upper_left, lower_right) = zoom_view(params.upper_left, params.lower_right, zoom);
+            (
+                positional[0].clone(),
+                params.bounds,
+                upper_left,
+                lower_right,
+            )
+        } else if info || area || auto_interesting || data_uri {
+            // 只描述视图或输出到标准输出，不需要输出文件。
+            if positional.len() != 3 {
+                let flag = if info {
+                    "--info"
+                } else if area {
+                    "--area"
+                } else if auto_interesting {
+                    "--auto-interesting"
+                } else {
+                    "--data-uri"
+                };
+                return Err(format!(
+                    "参数数量不符：使用{}时需要3个参数，传入了{}个。",
+                    flag,
+                    positional.len()
+                ));
+            }
+            (
+                String::new(),
+                parse_pair_with_unit(positional[0], 'x', Some("px"))
+                    .map_err(|e| format!("解析图像尺寸出错: {}", e))?,
+                parse_complex(positional[1]).ok_or("解析左上角点出错")?,
+                parse_complex(positional[2]).ok_or("解析右下角点出错")?,
+            )
+        } else if let (Some(preset), 2) = (&preset, positional.len()) {
+            (
+                positional[0].clone(),
+                parse_pair_with_unit(positional[1], 'x', Some("px"))
+                    .map_err(|e| format!("解析图像尺寸出错: {}", e))?,
+                preset.upper_left,
+                preset.lower_right,
+            )
+        } else {
+            if positional.len() != 4 {
+                return Err(format!(
+                    "参数数量不符：需要4个参数，传入了{}个。",
+                    positional.len()
+                ));
+            }
+            (
+                positional[0].clone(),
+                parse_pair_with_unit(positional[1], 'x', Some("px"))
+                    .map_err(|e| format!("解析图像尺寸出错: {}", e))?,
+                parse_complex(positional[2]).ok_or("解析左上角点出错")?,
+                parse_complex(positional[3]).ok_or("解析右下角点出错")?,
+            )
+        };
+
+    if let Some(pixel) = probe {
+        if pixel.0 >= bounds.0 || pixel.1 >= bounds.1 {
+            return Err(format!(
+                "探测像素{:?}不在{}x{}的图像内",
+                pixel, bounds.0, bounds.1
+            ));
+        }
+    }
+    if frames == Some(0) || motion_blur == 0 {
+        return Err("--frames和--motion-blur至少为1".to_string());
+    }
+    if gif
+        && (frames.is_none()
+            || name_template.is_some()
+            || depth == Some(16)
+            || transparent_interior
+            || encoding.quality.is_some())
+    {
+        return Err("--gif只能用于动画，且不能与--name-template、--depth 16、--transparent-interior或--quality同时使用".to_string());
+    }
+    if fps == Some(0) || (fps.is_some() && !gif) {
+        return Err("--fps至少为1，且只能与--gif一起使用".to_string());
+    }
+    if gif && (bounds.0 > u16::MAX as usize || bounds.1 > u16::MAX as usize) {
+        return Err(format!("GIF图像的宽和高不能超过{}", u16::MAX));
+    }
+    let name_template = match (frames, name_template) {
+        (Some(frames), template) => {
+            NameTemplate::parse(template.unwrap_or(DEFAULT_NAME_TEMPLATE), frames)?
+        }
+        (None, Some(_)) => return Err("--name-template只能用于动画".to_string()),
+        (None, None) => NameTemplate::parse(DEFAULT_NAME_TEMPLATE, 1)?,
+    };
+    if let Some(tolerance) = cache {
+        if frames.is_none() || tolerance.is_nan() || tolerance <= 0.0 {
+            return Err("--cache只能用于动画，且TOLERANCE必须为正数".to_string());
+        }
+    }
+    if let Some(offset) = color_cycle {
+        if frames.is_none() || palette.is_none() || !offset.is_finite() {
+            return Err(
+                "--color-cycle-offset只能用于使用调色板的动画，且OFFSET必须为有限的数".to_string(),
+            );
+        }
+        if transparent_interior || depth == Some(16) {
+            return Err("--color-cycle-offset的每帧都是8位RGB图像，不能与--transparent-interior或--depth 16同时使用".to_string());
+        }
+    }
+    if depth == Some(16) && is_jpeg(&filename) {
+        return Err("JPEG图像不支持16位".to_string());
+    }
+    if pyramid && (frames.is_some() || channels.is_some()) {
+        return Err("--pyramid不能与--frames或--channels同时使用".to_string());
+    }
+    if mirror_tile && (frames.is_some() || channels.is_some() || pyramid) {
+        return Err("--mirror-tile不能与--frames、--channels或--pyramid同时使用".to_string());
+    }
+    if adaptive_aa.is_some_and(|samples| samples < 2) {
+        return Err("--adaptive-aa至少为2".to_string());
+    }
+    if contours && (frames.is_some() || channels.is_some() || boundary) {
+        return Err("--contours不能与--frames、--channels或--boundary同时使用".to_string());
+    }
+    if adaptive_aa.is_some() && (frames.is_some() || channels.is_some()) {
+        return Err("--adaptive-aa不能与--frames或--channels同时使用".to_string());
+    }
+    if aa_threshold.is_some() && adaptive_aa.is_none() {
+        return Err("--aa-threshold只能与--adaptive-aa一起使用".to_string());
+    }
+    if transfer.is_some() && (frames.is_some() || channels.is_some()) {
+        return Err("--transfer不能与--frames或--channels同时使用".to_string());
+    }
+    if let Some(pivot) = contrast_pivot {
+        if !(pivot > 0.0 && pivot < 1.0) {
+            return Err("--contrast-pivot应在0和1之间（不含两端）".to_string());
+        }
+        if frames.is_some() || channels.is_some() {
+            return Err("--contrast-pivot不能与--frames或--channels同时使用".to_string());
+        }
+    }
+    // 周期着色会覆盖内部点的颜色，不能再按灰度为0表示透明或画分界线。
+    if period_color
+        && (frames.is_some()
+            || channels.is_some()
+            || transparent_interior
+            || boundary
+            || depth == Some(16))
+    {
+        return Err(
+            "--period-color不能与--frames、--channels、--transparent-interior、--boundary或--depth 16同时使用"
+                .to_string(),
+        );
+    }
+    // 内部点在渲染时就已着色，灰度为0不再表示内部点。
+    if interior_shade
+        && (frames.is_some()
+            || channels.is_some()
+            || transparent_interior
+            || boundary
+            || period_color
+            || transfer.is_some()
+            || contrast_pivot.is_some())
+    {
+        return Err(
+            "--interior-shade不能与--frames、--channels、--transparent-interior、--boundary、--period-color、--transfer或--contrast-pivot同时使用"
+                .to_string(),
+        );
+    }
+    options.interior_shade = interior_shade;
+    // Julia集网格单独渲染，不经过Mandelbrot集渲染后的各种处理。
+    if let Some((rows, columns)) = julia_grid {
+        if rows == 0 || columns == 0 || columns > bounds.0 || rows > bounds.1 {
+            return Err("--julia-grid的行列数至少为1，且每格至少有一个像素".to_string());
+        }
+        if frames.is_some()
+            || options.smooth
+            || channels.is_some()
+            || resume.is_some()
+            || pyramid
+            || data_uri
+            || adaptive_aa.is_some()
+            || heatmap.is_some()
+            || histogram.is_some()
+            || exr.is_some()
+            || orbit.is_some()
+            || grid
+            || contours
+            || boundary
+            || transfer.is_some()
+            || contrast_pivot.is_some()
+            || phash
+            || overlay_stats
+            || split_channels
+            || period_color
+            || interior_shade
+            || rotate != 0.0
+            || shear != 0.0
+            || progress
+            || timing_log.is_some()
+            || report_savings
+        {
+            return Err("--julia-grid不能与--frames、--smooth、--channels、--resume、--pyramid、--data-uri、--adaptive-aa、--heatmap、--histogram、--exr、--orbit、--grid、--contours、--boundary、--transfer、--contrast-pivot、--phash、--overlay-stats、--split-channels、--period-color、--interior-shade、--rotate、--shear、--progress、--timing-log或--report-savings同时使用".to_string());
+        }
+    }
+    // data URI只包含单幅PNG图像。
+    if data_uri
+        && (frames.is_some()
+            || channels.is_some()
+            || pyramid
+            || continue_from.is_some()
+            || encoding.quality.is_some())
+    {
+        return Err(
+            "--data-uri不能与--frames、--channels、--pyramid、--continue或--quality同时使用"
+                .to_string(),
+        );
+    }
+    // 只有彩色图像才有可以拆分的通道。
+    if split_channels
+        && ((palette.is_none() && !period_color)
+            || frames.is_some()
+            || channels.is_some()
+            || pyramid
+            || mirror_tile
+            || data_uri)
+    {
+        return Err("--split-channels只能用于使用调色板或--period-color的图像，且不能与--frames、--channels、--pyramid、--mirror-tile或--data-uri同时使用".to_string());
+    }
+    if overlay_stats && (frames.is_some() || channels.is_some()) {
+        return Err("--overlay-stats不能与--frames或--channels同时使用".to_string());
+    }
+    if grid && (frames.is_some() || channels.is_some()) {
+        return Err("--grid不能与--frames或--channels同时使用".to_string());
+    }
+    if phash && (frames.is_some() || channels.is_some()) {
+        return Err("--phash不能与--frames或--channels同时使用".to_string());
+    }
+    if boundary && (frames.is_some() || channels.is_some()) {
+        return Err("--boundary不能与--frames或--channels同时使用".to_string());
+    }
+    if orbit.is_some() && (frames.is_some() || channels.is_some()) {
+        return Err("--orbit不能与--frames或--channels同时使用".to_string());
+    }
+    if heatmap.is_some() && (frames.is_some() || channels.is_some()) {
+        return Err("--heatmap不能与--frames或--channels同时使用".to_string());
+    }
+    if histogram.is_some() && (frames.is_some() || channels.is_some()) {
+        return Err("--histogram不能与--frames或--channels同时使用".to_string());
+    }
+    if exr.is_some() && (frames.is_some() || channels.is_some() || options.norm != Norm::L2) {
+        return Err("--exr不能与--frames或--channels同时使用，且只支持l2范数".to_string());
+    }
+    if timing_log.is_some() && (frames.is_some() || channels.is_some()) {
+        return Err("--timing-log不能与--frames或--channels同时使用".to_string());
+    }
+    if options.chunk_rows == Some(0) {
+        return Err("--chunk-rows至少为1".to_string());
+    }
+    options.limit = iterations
+        .or(preset.map(|preset| preset.iterations))
+        .unwrap_or(options.limit);
+    if options.limit == 0 {
+        return Err("--iterations至少为1".to_string());
+    }
+    if channels.is_some() && (resume.is_some() || frames.is_some() || palette.is_some()) {
+        return Err("--channels不能与--resume、--frames或调色板同时使用".to_string());
+    }
+    if !rotate.is_finite() || !shear.is_finite() {
+        return Err("--rotate和--shear必须是有限的数".to_string());
+    }
+    if rotate != 0.0 || shear != 0.0 {
+        // 这些功能按没有变换的视图计算坐标，或者不记录变换。
+        if frames.is_some()
+            || channels.is_some()
+            || resume.is_some()
+            || period_color
+            || grid
+            || orbit.is_some()
+            || exr.is_some()
+        {
+            return Err("--rotate和--shear不能与--frames、--channels、--resume、--period-color、--grid、--orbit或--exr同时使用".to_string());
+        }
+        options.transform = ViewTransform {
+            center: (upper_left + lower_right) / 2.0,
+            rotate: rotate.to_radians(),
+            shear,
+        };
+    }
+
+    Ok(Arguments {
+        filename,
+        bounds,
+        upper_left,
+        lower_right,
+        resume,
+        probe,
+        info,
+        area,
+        auto_interesting,
+        transparent_interior,
+        dither,
+        options,
+        animation: frames.map(|frames| Animation {
+            frames,
+            zoom,
+            motion_blur,
+            cache,
+            name_template,
+            color_cycle,
+            gif_fps: gif.then_some(fps.unwrap_or(DEFAULT_GIF_FPS)),
+        }),
+        palette,
+        palette_preview,
+        report_savings,
+        progress,
+        timing_log,
+        channels,
+        selftest,
+        pyramid,
+        mirror_tile,
+        depth,
+        encoding,
+        orbit,
+        heatmap,
+        histogram,
+        exr,
+        boundary,
+        contours,
+        adaptive_aa,
+        aa_threshold: aa_threshold.unwrap_or(0),
+        transfer,
+        contrast_pivot,
+        phash,
+        grid,
+        period_color,
+        overlay_stats,
+        data_uri,
+        split_channels,
+        julia_grid,
+    })
+}
+
+/// `--selftest`渲染的固定视图：名称、命令行参数和期望的SHA-256，见`selftest_hash`。
+///
+/// 期望值由已知正确的构建生成。修改了渲染或着色的行为时需要重新生成。
+const SELFTEST_VIEWS: &[(&str, &[&str], &str)] = &[
+    (
+        "gray",
+        &["selftest.png", "64x48", "-2,1.2", "1,-1.2"],
+        "d39928addc443e7a76b0bba64dac86e6bd135452513213d51d31e9a3248e1fb4",
+    ),
+    (
+        "smooth",
+        &["--smooth", "selftest.png", "64x48", "-0.8,0.3", "-0.6,0.15"],
+        "d77e72569a4d97ff8d583cb59f84754ea8b52e3dc71ccd5a652469c4391103d6",
+    ),
+    (
+        "dither",
+        &[
+            "--smooth",
+            "--dither",
+            "fs",
+            "selftest.png",
+            "64x48",
+            "-2,1.2",
+            "1,-1.2",
+        ],
+        "e8f74aeccc1783e898287f675fcd3e8a246685f139415f09486f0da0aaad7146",
+    ),
+    (
+        "palette",
+        &[
+            "--palette",
+            "fire",
+            "selftest.png",
+            "64x48",
+            "-1.2,0.35",
+            "-1,0.2",
+        ],
+        "1493725a874a71b1ff346bbfde729777571cf0dcd90c6c9f21971fe933a4a14e",
+    ),
+    (
+        "transparent",
+        &[
+            "--transparent-interior",
+            "selftest.png",
+            "64x48",
+            "-2,1.2",
+            "1,-1.2",
+        ],
+        "d81c5b02a79524e7d3edae133627e86371d0bc87cce9f521a070901deacc6670",
+    ),
+];
+
+/// 渲染`view`所描述的视图，返回浮点灰度缓冲和由它转换的像素的SHA-256（十六进制）。
+///
+/// 只对原始数据求哈希，不经过PNG编码，因此结果不受编码器版本和压缩设置的影响。
+fn selftest_hash(view: &[&str]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let args: Vec<String> = view.iter().map(|a| a.to_string()).collect();
+    let args = parse_args(&args).expect("自检视图的参数无效");
+    let mut shades = vec![0.0; args.bounds.0 * args.bounds.1];
+    render_parallel(
+        &mut shades,
+        args.bounds,
+        args.upper_left,
+        args.lower_right,
+        args.options,
+        None,
+        RenderControl::default(),
+    );
+    let mut hasher = Sha256::new();
+    for shade in &shades {
+        hasher.update(shade.to_le_bytes());
+    }
+    let (pixels, _) = shades_to_pixels(&shades, &args);
+    hasher.update(&pixels);
+    format!("{:x}", hasher.finalize())
+}
+
+/// 依次检查`SELFTEST_VIEWS`中的每个视图并打印结果，全部通过时返回`true`。
+fn run_selftest() -> bool {
+    let mut passed = true;
+    for &(name, view, expected) in SELFTEST_VIEWS {
+        let actual = selftest_hash(view);
+        if actual == expected {
+            println!("通过  {}", name);
+        } else {
+            println!("失败  {}: 期望{}，实际为{}", name, expected, actual);
+            passed = false;
+        }
+    }
+    passed
+}
+
+#[test]
+fn test_selftest() {
+    assert!(run_selftest());
+}
+
+#[test]
+fn test_continue_zoom() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-continue-{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    let to_args = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    };
+
+    let first = to_args(&[path, "40x30", "-1.5,0.5", "-0.5,-0.25"]).unwrap();
+    let shades = vec![0.0; 40 * 30];
+    write_shades(path, &shades, (first.upper_left, first.lower_right), &first).unwrap();
+
+    let second = to_args(&["--continue", path, "--zoom", "2", "deeper.png"]);
+    std::fs::remove_file(path).unwrap();
+    let second = second.unwrap();
+    assert_eq!(second.filename, "deeper.png");
+    assert_eq!(second.bounds, (40, 30));
+    // 中心仍为-1+0.125i，宽和高都减半。
+    assert_eq!(
+        second.upper_left,
+        Complex {
+            re: -1.25,
+            im: 0.3125
+        }
+    );
+    assert_eq!(
+        second.lower_right,
+        Complex {
+            re: -0.75,
+            im: -0.0625
+        }
+    );
+
+    assert!(to_args(&["--continue", "no-such-image.png", "out.png"]).is_err());
+}
+
+/// 取出选项`flag`后紧跟的值。
+fn flag_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a str, String> {
+    iter.next()
+        .map(|value| value.as_str())
+        .ok_or_else(|| format!("选项{}需要一个值", flag))
+}
+
+/// 取出选项`flag`后紧跟的值并解析为`T`。
+fn parse_flag<'a, T: std::str::FromStr>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<T, String> {
+    let value = flag_value(iter, flag)?;
+    value
+        .parse()
+        .map_err(|_| format!("选项{}的值'{}'无效", flag, value))
+}
+
+/// 将浮点灰度缓冲`shades`按`args`中的调色板、抖动和透明设置着色，写入PNG文件`filename`。
+///
+/// 使用调色板时输出RGB图像，否则输出8位灰度图像；抖动只作用于灰度输出。
+/// `view`为渲染时视图的左上角和右下角，会与图像尺寸一起记录在PNG文件中。
+/// 给定`--mirror-tile`时写出镜像平铺后的图像，它不对应任何视图，因此不记录参数。
+fn write_shades(
+    filename: &str,
+    shades: &[f32],
+    view: (Complex<f64>, Complex<f64>),
+    args: &Arguments,
+) -> Result<(), std::io::Error> {
+    let params = ImageParams {
+        bounds: args.bounds,
+        upper_left: view.0,
+        lower_right: view.1,
+    };
+    let (pixels, color) = shades_to_pixels(shades, args);
+    if args.mirror_tile {
+        let bounds = (args.bounds.0 * 2, args.bounds.1 * 2);
+        let tiled = mirror_tile(&pixels, args.bounds);
+        return write_image(filename, &tiled, bounds, color, None, args.encoding);
+    }
+    write_image(
+        filename,
+        &pixels,
+        args.bounds,
+        color,
+        Some(&params),
+        args.encoding,
+    )
+}
+
+/// 与`write_shades`相同，但总是编码为PNG，返回`data:image/png;base64,...`形式的data URI，
+/// 可以直接嵌入HTML或笔记本中。
+fn data_uri(
+    shades: &[f32],
+    view: (Complex<f64>, Complex<f64>),
+    args: &Arguments,
+) -> Result<String, std::io::Error> {
+    let params = ImageParams {
+        bounds: args.bounds,
+        upper_left: view.0,
+        lower_right: view.1,
+    };
+    let (pixels, color) = shades_to_pixels(shades, args);
+    let png = if args.mirror_tile {
+        let bounds = (args.bounds.0 * 2, args.bounds.1 * 2);
+        let tiled = mirror_tile(&pixels, args.bounds);
+        encode_png(&tiled, bounds, color, None, args.encoding)?
+    } else {
+        encode_png(&pixels, args.bounds, color, Some(&params), args.encoding)?
+    };
+    Ok(format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(png)
+    ))
+}
+
+#[test]
+fn test_data_uri() {
+    let to_args = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    };
+    let args = to_args(&["--data-uri", "40x30", "-2,1.2", "1,-1.2"]).unwrap();
+    assert!(args.filename.is_empty());
+    let mut shades = vec![0.0; args.bounds.0 * args.bounds.1];
+    render(
+        &mut shades,
+        None,
+        args.bounds,
+        args.upper_left,
+        args.lower_right,
+        args.options,
+    );
+
+    let uri = data_uri(&shades, (args.upper_left, args.lower_right), &args).unwrap();
+    let encoded = uri.strip_prefix("data:image/png;base64,").unwrap();
+    let png = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap();
+    let decoded = image::load_from_memory(&png).unwrap().to_luma();
+    assert_eq!(decoded.dimensions(), (40, 30));
+    // 与写入文件的PNG数据完全相同，视图参数也一并嵌入。
+    let (pixels, color) = shades_to_pixels(&shades, &args);
+    let params = ImageParams {
+        bounds: args.bounds,
+        upper_left: args.upper_left,
+        lower_right: args.lower_right,
+    };
+    assert_eq!(
+        png,
+        encode_png(&pixels, args.bounds, color, Some(&params), args.encoding).unwrap()
+    );
+
+    assert!(to_args(&["--data-uri", "out.png", "40x30", "-2,1.2", "1,-1.2"]).is_err());
+    assert!(to_args(&["--data-uri", "--pyramid", "40x30", "-2,1.2", "1,-1.2"]).is_err());
+}
+
+/// 将`bounds`大小的像素缓冲`pixels`沿右边和下边镜像，返回长宽各为两倍的缓冲。
+///
+/// 左上四分之一为原图，右半为左半的水平镜像，下半为上半的垂直镜像，
+/// 因此结果的对边完全相同，可以无缝平铺。每个像素的字节数由缓冲的长度推出，
+/// 灰度、RGB、带透明通道和16位的缓冲都适用。
+fn mirror_tile(pixels: &[u8], bounds: (usize, usize)) -> Vec<u8> {
+    let pixel = pixels.len() / (bounds.0 * bounds.1);
+    let mut tiled = Vec::with_capacity(pixels.len() * 4);
+    let rows: Vec<&[u8]> = pixels.chunks(bounds.0 * pixel).collect();
+    for row in rows.iter().chain(rows.iter().rev()) {
+        tiled.extend_from_slice(row);
+        for color in row.chunks(pixel).rev() {
+            tiled.extend_from_slice(color);
+        }
+    }
+    tiled
+}
+
+#[test]
+fn test_mirror_tile() {
+    let gray = [1, 2, 3, 4, 5, 6];
+    assert_eq!(
+        mirror_tile(&gray, (3, 2)),
+        [1, 2, 3, 3, 2, 1, 4, 5, 6, 6, 5, 4, 4, 5, 6, 6, 5, 4, 1, 2, 3, 3, 2, 1]
+    );
+
+    // RGB像素整体镜像，通道的顺序不变。
+    let bounds = (5, 3);
+    let rgb: Vec<u8> = (0..bounds.0 * bounds.1 * 3).map(|i| i as u8).collect();
+    let tiled = mirror_tile(&rgb, bounds);
+    let row_len = bounds.0 * 2 * 3;
+    assert_eq!(tiled.len(), rgb.len() * 4);
+    for row in tiled.chunks(row_len) {
+        let (left, right) = row.split_at(row_len / 2);
+        let mirrored: Vec<u8> = left.chunks(3).rev().flatten().copied().collect();
+        assert_eq!(right, &mirrored[..]);
+    }
+    assert_eq!(&tiled[..row_len], &tiled[tiled.len() - row_len..]);
+}
+
+/// 输出图像每个通道的位数。
+///
+/// 没有用`--depth`指定时自动选择：平滑着色（且没有要求抖动到8位）时8位输出会损失精度，
+/// 此时使用16位，否则使用8位。调色板本身不会触发16位输出，需要时用`--depth 16`指定。
+/// JPEG图像和GIF动画总是8位。
+fn output_depth(args: &Arguments) -> u8 {
+    args.depth.unwrap_or_else(|| {
+        let gif = args
+            .animation
+            .as_ref()
+            .is_some_and(|animation| animation.gif_fps.is_some());
+        if is_jpeg(&args.filename) || gif || args.period_color {
+            return 8;
+        }
+        if args.options.smooth && args.dither == Dither::None {
+            16
+        } else {
+            8
+        }
+    })
+}
+
+/// 按`args`中的调色板、抖动、透明和位深设置将灰度缓冲`shades`转为像素，并返回像素的格式。
+///
+/// 16位的像素按PNG的要求以大端字节序存放，16位输出不做抖动。
+fn shades_to_pixels(shades: &[f32], args: &Arguments) -> (Vec<u8>, ColorType) {
+    if args.period_color {
+        let pixels = period_colorize(
+            shades,
+            args.bounds,
+            args.upper_left,
+            args.lower_right,
+            args.options,
+            args.palette.as_ref(),
+        );
+        return (pixels, ColorType::RGB(8));
+    }
+    if output_depth(args) == 16 {
+        return shades_to_pixels16(shades, args);
+    }
+    let (pixels, channels) = match &args.palette {
+        Some(palette) => (colorize(shades, palette), 3),
+        None => (quantize(shades, args.bounds, args.dither), 1),
+    };
+    if args.transparent_interior {
+        let rgba = transparent_interior_rgba(shades, &pixels, channels);
+        (rgba, ColorType::RGBA(8))
+    } else if channels == 3 {
+        (pixels, ColorType::RGB(8))
+    } else {
+        (pixels, ColorType::Gray(8))
+    }
+}
+
+/// `shades_to_pixels`的16位版本。
+///
+/// 外部点的灰度至少为1，映射到16位后至少为257，内部点仍然为0。
+fn shades_to_pixels16(shades: &[f32], args: &Arguments) -> (Vec<u8>, ColorType) {
+    let to16 = |v: f64| (v * 257.0).round().clamp(0.0, 65535.0) as u16;
+    let mut samples = Vec::new();
+    for &shade in shades {
+        let (color, alpha) = if shade == 0.0 {
+            ([0.0; 3], 0)
+        } else {
+            let color = match &args.palette {
+                Some(palette) => palette.lookup_exact(shade as f64 / 255.0),
+                None => [shade.clamp(1.0, 255.0) as f64; 3],
+            };
+            (color, 65535)
+        };
+        match (&args.palette, args.transparent_interior) {
+            (None, false) => samples.push(to16(color[0])),
+            (Some(_), false) => samples.extend(color.map(to16)),
+            (_, true) => {
+                samples.extend(color.map(to16));
+                samples.push(alpha);
+            }
+        }
+    }
+
+    let color = match (&args.palette, args.transparent_interior) {
+        (None, false) => ColorType::Gray(16),
+        (Some(_), false) => ColorType::RGB(16),
+        (_, true) => ColorType::RGBA(16),
+    };
+    let pixels = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+    (pixels, color)
+}
+
+#[test]
+fn test_output_depth() {
+    let to_args = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args).unwrap()
+    };
+    let view = ["out.png", "4x3", "-2,1", "1,-1"];
+    let shades = [
+        0.0, 1.0, 100.5, 255.0, 30.0, 0.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0,
+    ];
+
+    let plain = to_args(&view);
+    assert_eq!(shades_to_pixels(&shades, &plain).1, ColorType::Gray(8));
+
+    let smooth = to_args(&[&["--smooth"], &view[..]].concat());
+    let (pixels, color) = shades_to_pixels(&shades, &smooth);
+    assert_eq!(color, ColorType::Gray(16));
+    assert_eq!(&pixels[..8], &[0, 0, 1, 1, 0x64, 0xe5, 0xff, 0xff]);
+
+    // 要求抖动或明确指定了位深时不自动选择16位。
+    let dithered = to_args(&[&["--smooth", "--dither", "fs"], &view[..]].concat());
+    assert_eq!(shades_to_pixels(&shades, &dithered).1, ColorType::Gray(8));
+    let explicit = to_args(&[&["--smooth", "--depth", "8"], &view[..]].concat());
+    assert_eq!(shades_to_pixels(&shades, &explicit).1, ColorType::Gray(8));
+
+    // 调色板只在明确指定时使用16位。
+    let palette = to_args(&[&["--palette", "fire"], &view[..]].concat());
+    assert_eq!(shades_to_pixels(&shades, &palette).1, ColorType::RGB(8));
+    let palette = to_args(&[&["--depth", "16", "--palette", "fire"], &view[..]].concat());
+    assert_eq!(shades_to_pixels(&shades, &palette).1, ColorType::RGB(16));
+}
+
+/// 写出`--pyramid`的三种尺寸：`FILE@2x.png`为完整分辨率，`FILE.png`为一半，
+/// `FILE_thumb.png`为四分之一，后两者由完整图像按块取平均缩小得到。
+fn write_pyramid(
+    filename: &str,
+    shades: &[f32],
+    view: (Complex<f64>, Complex<f64>),
+    args: &Arguments,
+) -> Result<(), std::io::Error> {
+    let (pixels, color) = shades_to_pixels(shades, args);
+    let (channels, depth) = match color {
+        ColorType::RGBA(depth) => (4, depth),
+        ColorType::RGB(depth) => (3, depth),
+        ColorType::Gray(depth) => (1, depth),
+        _ => unreachable!(),
+    };
+    for (factor, suffix) in [(1, "@2x"), (2, ""), (4, "_thumb")] {
+        let (scaled, bounds) =
+            downsample(&pixels, args.bounds, channels, depth as usize / 8, factor);
+        let params = ImageParams {
+            bounds,
+            upper_left: view.0,
+            lower_right: view.1,
+        };
+        write_image(
+            &with_suffix(filename, suffix),
+            &scaled,
+            bounds,
+            color,
+            Some(&params),
+            args.encoding,
+        )?;
+    }
+    Ok(())
+}
+
+/// 在`filename`的扩展名之前加上`suffix`，如`out.png`加上`_r`得到`out_r.png`。
+fn with_suffix(filename: &str, suffix: &str) -> String {
+    let path = std::path::Path::new(filename);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = match path.extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy()),
+        None => String::new(),
+    };
+    let output = path.with_file_name(format!("{}{}{}", stem, suffix, extension));
+    output.to_string_lossy().into_owned()
+}
+
+/// 将彩色图像的红、绿、蓝通道分别写为灰度图像`FILE_r.png`、`FILE_g.png`和`FILE_b.png`，
+/// 位深与彩色图像相同，透明通道被丢弃。
+fn write_split_channels(
+    filename: &str,
+    shades: &[f32],
+    view: (Complex<f64>, Complex<f64>),
+    args: &Arguments,
+) -> Result<(), std::io::Error> {
+    let (pixels, color) = shades_to_pixels(shades, args);
+    let (channels, depth) = match color {
+        ColorType::RGBA(depth) => (4, depth),
+        ColorType::RGB(depth) => (3, depth),
+        _ => {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "只有彩色图像可以拆分通道",
+            ))
+        }
+    };
+    let params = ImageParams {
+        bounds: args.bounds,
+        upper_left: view.0,
+        lower_right: view.1,
+    };
+    let planes = split_channels(&pixels, channels, depth as usize / 8);
+    for (plane, suffix) in planes.iter().zip(["_r", "_g", "_b"]) {
+        write_image(
+            &with_suffix(filename, suffix),
+            plane,
+            args.bounds,
+            ColorType::Gray(depth),
+            Some(&params),
+            args.encoding,
+        )?;
+    }
+    Ok(())
+}
+
+/// 将每像素`channels`个通道、每个通道`sample_bytes`个字节的图像`pixels`拆成各个通道的平面。
+fn split_channels(pixels: &[u8], channels: usize, sample_bytes: usize) -> Vec<Vec<u8>> {
+    (0..channels)
+        .map(|channel| {
+            pixels
+                .chunks(channels * sample_bytes)
+                .flat_map(|pixel| &pixel[channel * sample_bytes..(channel + 1) * sample_bytes])
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_split_channels() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-split-{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    let args: Vec<String> = [
+        "--palette",
+        "fire",
+        "--split-channels",
+        path,
+        "40x30",
+        "-2,1.2",
+        "1,-1.2",
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect();
+    let args = parse_args(&args).unwrap();
+    let mut shades = vec![0.0; 40 * 30];
+    render(
+        &mut shades,
+        None,
+        args.bounds,
+        args.upper_left,
+        args.lower_right,
+        args.options,
+    );
+    let view = (args.upper_left, args.lower_right);
+    write_shades(path, &shades, view, &args).unwrap();
+    write_split_channels(path, &shades, view, &args).unwrap();
+
+    let combined = image::open(path).unwrap().to_rgb().into_raw();
+    let planes: Vec<Vec<u8>> = ["_r", "_g", "_b"]
+        .iter()
+        .map(|suffix| {
+            let file = with_suffix(path, suffix);
+            let plane = image::open(&file).unwrap().to_luma().into_raw();
+            std::fs::remove_file(file).unwrap();
+            plane
+        })
+        .collect();
+    std::fs::remove_file(path).unwrap();
+    let red: Vec<u8> = combined.chunks(3).map(|rgb| rgb[0]).collect();
+    assert_eq!(planes[0], red);
+    assert_eq!(split_channels(&combined, 3, 1), planes);
+
+    // 16位的样本按两个字节整体拆分。
+    assert_eq!(
+        split_channels(&[1, 2, 3, 4, 5, 6], 3, 2),
+        [vec![1, 2], vec![3, 4], vec![5, 6]]
+    );
+    assert_eq!(with_suffix("dir/out.png", "_g"), "dir/out_g.png");
+}
+
+/// 将每像素`channels`个通道的图像`pixels`缩小为原来的`1/factor`，返回缩小后的像素和尺寸。
+///
+/// 每个通道占`sample_bytes`个字节：1表示8位，2表示大端的16位。
+/// 每个输出像素的各个通道分别是对应`factor`x`factor`块的平均值（四舍五入）。
+/// 尺寸不是`factor`的整数倍时，最右侧和最下方不足一块的像素被舍弃。
+fn downsample(
+    pixels: &[u8],
+    bounds: (usize, usize),
+    channels: usize,
+    sample_bytes: usize,
+    factor: usize,
+) -> (Vec<u8>, (usize, usize)) {
+    let sample = |i: usize| {
+        let bytes = &pixels[i * sample_bytes..(i + 1) * sample_bytes];
+        bytes.iter().fold(0, |value, &b| value << 8 | b as u64)
+    };
+    let scaled_bounds = (bounds.0 / factor, bounds.1 / factor);
+    let count = (factor * factor) as u64;
+    let mut scaled =
+        Vec::with_capacity(scaled_bounds.0 * scaled_bounds.1 * channels * sample_bytes);
+    for row in 0..scaled_bounds.1 {
+        for column in 0..scaled_bounds.0 {
+            for channel in 0..channels {
+                let mut sum = 0;
+                for y in row * factor..(row + 1) * factor {
+                    for x in column * factor..(column + 1) * factor {
+                        sum += sample((y * bounds.0 + x) * channels + channel);
+                    }
+                }
+                let mean = (sum + count / 2) / count;
+                scaled.extend_from_slice(&mean.to_be_bytes()[8 - sample_bytes..]);
+            }
+        }
+    }
+    (scaled, scaled_bounds)
+}
+
+#[test]
+fn test_downsample() {
+    let bounds = (5, 4);
+    let pixels: Vec<u8> = (0..bounds.0 * bounds.1 * 3)
+        .map(|i| (i * 37 % 256) as u8)
+        .collect();
+    let (half, half_bounds) = downsample(&pixels, bounds, 3, 1, 2);
+    assert_eq!(half_bounds, (2, 2));
+    for row in 0..2 {
+        for column in 0..2 {
+            for channel in 0..3 {
+                let at = |x: usize, y: usize| pixels[(y * bounds.0 + x) * 3 + channel] as f64;
+                let (x, y) = (column * 2, row * 2);
+                let mean = (at(x, y) + at(x + 1, y) + at(x, y + 1) + at(x + 1, y + 1)) / 4.0;
+                assert_eq!(half[(row * 2 + column) * 3 + channel], mean.round() as u8);
+            }
+        }
+    }
+
+    let (same, same_bounds) = downsample(&pixels, bounds, 3, 1, 1);
+    assert_eq!((same, same_bounds), (pixels, bounds));
+
+    // 16位的样本按大端整体取平均，而不是分别平均高低字节。
+    let pixels: Vec<u8> = [0x00ff_u16, 0x0100, 0x0000, 0x0001]
+        .iter()
+        .flat_map(|s| s.to_be_bytes())
+        .collect();
+    assert_eq!(downsample(&pixels, (2, 2), 1, 2, 2).0, vec![0x00, 0x80]);
+}
+
+/// 用调色板`palette`为灰度缓冲`shades`着色，返回RGB像素。
+///
+/// 集合内部的像素为黑色，外部像素取调色板中`shade / 255`处的颜色，
+/// 因此`gray`调色板的输出与默认的灰度输出相同。
+///
+/// 颜色从预先计算的`PALETTE_TABLE_SIZE`项查找表中读出：整数灰度与直接插值的结果相同，
+/// 平滑着色的小数灰度取最近的表项。
+fn colorize(shades: &[f32], palette: &Palette) -> Vec<u8> {
+    colorize_table(shades, &palette.table(PALETTE_TABLE_SIZE))
+}
+
+/// 着色时调色板查找表的大小，每个灰度级分为16份。
+const PALETTE_TABLE_SIZE: usize = 255 * 16 + 1;
+
+/// 用查找表`table`为`shades`着色，见`colorize`。
+fn colorize_table(shades: &[f32], table: &PaletteTable) -> Vec<u8> {
+    shades
+        .iter()
+        .flat_map(|&shade| {
+            if shade == 0.0 {
+                [0, 0, 0]
+            } else {
+                table.lookup(shade as f64 / 255.0)
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_colorize_table() {
+    let bounds = (80, 60);
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render(
+        &mut shades,
+        None,
+        bounds,
+        Complex { re: -2.0, im: 1.2 },
+        Complex { re: 1.0, im: -1.2 },
+        RenderOptions::default(),
+    );
+    let palette = Palette::builtin("rainbow").unwrap();
+    let direct: Vec<u8> = shades
+        .iter()
+        .flat_map(|&shade| {
+            if shade == 0.0 {
+                [0, 0, 0]
+            } else {
+                palette.lookup(shade as f64 / 255.0)
+            }
+        })
+        .collect();
+    assert_eq!(colorize(&shades, &palette), direct);
+}
+
+/// 比较逐个像素插值与读查找表的着色耗时。
+///
+/// 用`cargo test --release -- --ignored --nocapture bench_palette_table`运行。
+#[test]
+#[ignore]
+fn bench_palette_table() {
+    let bounds = (1600, 1200);
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut shades,
+        bounds,
+        Complex { re: -2.2, im: 1.2 },
+        Complex { re: 1.0, im: -1.2 },
+        RenderOptions {
+            smooth: true,
+            ..Default::default()
+        },
+        None,
+        RenderControl::default(),
+    );
+    let palette = Palette::builtin("rainbow").unwrap();
+
+    let start = std::time::Instant::now();
+    let direct: Vec<u8> = shades
+        .iter()
+        .flat_map(|&shade| palette.lookup(shade as f64 / 255.0))
+        .collect();
+    let direct_elapsed = start.elapsed();
+    let start = std::time::Instant::now();
+    let cached = colorize(&shades, &palette);
+    let cached_elapsed = start.elapsed();
+    assert_eq!(direct.len(), cached.len());
+    println!(
+        "逐像素插值: {:?}，查找表: {:?}",
+        direct_elapsed, cached_elapsed
+    );
+}
+
+/// `--period-color`为周期1到8的内部点使用的颜色，更长的周期循环使用。
+const PERIOD_COLORS: [[u8; 3]; 8] = [
+    [40, 80, 200],
+    [200, 40, 40],
+    [40, 170, 60],
+    [230, 200, 30],
+    [160, 50, 190],
+    [30, 190, 200],
+    [240, 120, 20],
+    [230, 110, 170],
+];
+
+/// 找周期时最多尝试的周期长度。
+const MAX_PERIOD: usize = 64;
+
+/// 为灰度缓冲`shades`着色，内部点取其周期在`PERIOD_COLORS`中对应的颜色，返回RGB像素。
+///
+/// 外部点与`colorize`相同，没有调色板时使用灰度；找不到周期的内部点为黑色。
+/// 内部点需要重新迭代以找出周期，在多个线程中进行。
+fn period_colorize(
+    shades: &[f32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+    palette: Option<&Palette>,
+) -> Vec<u8> {
+    let mut periods = vec![None; bounds.0 * bounds.1];
+    map_points_parallel(
+        &mut periods,
+        bounds,
+        upper_left,
+        lower_right,
+        options.chunk_rows,
+        |point| interior_period(point, options.limit, MAX_PERIOD),
+    );
+
+    let gray = Palette::builtin("gray").unwrap();
+    let exterior = colorize(shades, palette.unwrap_or(&gray));
+    let mut pixels = Vec::with_capacity(exterior.len());
+    for ((&shade, period), rgb) in shades.iter().zip(periods).zip(exterior.chunks(3)) {
+        match period {
+            Some(period) if shade == 0.0 => {
+                pixels.extend_from_slice(&PERIOD_COLORS[(period - 1) % PERIOD_COLORS.len()])
+            }
+            _ => pixels.extend_from_slice(rgb),
+        }
+    }
+    pixels
+}
+
+#[test]
+fn test_period_colorize() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let options = RenderOptions::default();
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut shades,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        None,
+        RenderControl::default(),
+    );
+    let pixels = period_colorize(&shades, bounds, upper_left, lower_right, options, None);
+    let color_at = |re: f64, im: f64| {
+        let (column, row) =
+            point_to_pixel(bounds, Complex { re, im }, upper_left, lower_right).unwrap();
+        let i = (row * bounds.0 + column) * 3;
+        [pixels[i], pixels[i + 1], pixels[i + 2]]
+    };
+
+    // 主心形线为周期1，左边的大圆盘为周期2。
+    assert_eq!(color_at(-0.1, 0.0), PERIOD_COLORS[0]);
+    assert_eq!(color_at(0.1, 0.3), PERIOD_COLORS[0]);
+    assert_eq!(color_at(-1.0, 0.0), PERIOD_COLORS[1]);
+    // 外部点保持灰度着色。
+    let (column, row) = point_to_pixel(
+        bounds,
+        Complex { re: 0.9, im: 1.0 },
+        upper_left,
+        lower_right,
+    )
+    .unwrap();
+    let shade = shades[row * bounds.0 + column];
+    assert!(shade > 0.0);
+    assert_eq!(color_at(0.9, 1.0), [shade.round() as u8; 3]);
+}
+
+/// 将调色板写为一条256x32像素的水平渐变色带，从左到右对应调色板从头到尾的颜色。
+fn write_palette_preview(filename: &str, palette: &Palette) -> Result<(), std::io::Error> {
+    let bounds = (256, 32);
+    let row: Vec<u8> = (0..bounds.0)
+        .flat_map(|column| palette.lookup(column as f64 / (bounds.0 - 1) as f64))
+        .collect();
+    write_image(
+        filename,
+        &row.repeat(bounds.1),
+        bounds,
+        ColorType::RGB(8),
+        None,
+        Encoding::default(),
+    )
+}
+
+#[test]
+fn test_palette_preview() {
+    let palette = Palette::builtin("fire").unwrap();
+    let path = std::env::temp_dir().join(format!("mandelbrot-swatch-{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    write_palette_preview(path, &palette).unwrap();
+
+    let swatch = image::open(path).unwrap().to_rgb();
+    std::fs::remove_file(path).unwrap();
+    let (width, height) = swatch.dimensions();
+    let colors = palette.colors();
+    for row in [0, height - 1] {
+        assert_eq!(swatch.get_pixel(0, row).data, colors[0]);
+        assert_eq!(
+            swatch.get_pixel(width - 1, row).data,
+            colors[colors.len() - 1]
+        );
+    }
+}
+
+/// 缩放动画的参数。
+#[derive(Debug)]
+struct Animation {
+    /// 总帧数。
+    frames: usize,
+    /// 末帧相对首帧围绕视图中心放大的倍数，中间各帧按几何级数插值。
+    zoom: f64,
+    /// 每帧在其时间间隔内均匀采样的子帧数，为1时不产生运动模糊。
+    motion_blur: usize,
+    /// `--cache`的容差，给定时各帧共享一个`OrbitCache`。
+    cache: Option<f64>,
+    /// 各帧的文件名。
+    name_template: NameTemplate,
+    /// `--color-cycle-offset`给定的每帧调色板旋转量，此时各帧视图相同，只有颜色在变化。
+    color_cycle: Option<f64>,
+    /// 给定`--gif`时为每秒的帧数，各帧合成一个GIF动画写入FILE，而不是分别写入目录。
+    gif_fps: Option<usize>,
+}
+
+/// 动画帧默认的文件名。
+const DEFAULT_NAME_TEMPLATE: &str = "frame_{index}.png";
+
+/// `--name-template`给出的文件名模板。
+///
+/// 可用的占位符有：`{index}`为四位的帧序号；`{zoom}`为该帧视图在实轴方向上的宽度，
+/// 以科学计数法表示，如`1.5e-3`；`{re}`和`{im}`为该帧视图中心的实部和虚部。
+#[derive(Debug)]
+struct NameTemplate(String);
+
+impl NameTemplate {
+    const PLACEHOLDERS: [&'static str; 4] = ["{index}", "{zoom}", "{re}", "{im}"];
+
+    /// 解析用于`frames`帧动画的模板。多于一帧时模板必须包含`{index}`，否则各帧会互相覆盖。
+    fn parse(template: &str, frames: usize) -> Result<NameTemplate, String> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let placeholder = Self::PLACEHOLDERS
+                .iter()
+                .find(|p| rest[start..].starts_with(*p))
+                .ok_or_else(|| format!("文件名模板'{}'中有未知的占位符", template))?;
+            rest = &rest[start + placeholder.len()..];
+        }
+        if frames > 1 && !template.contains("{index}") {
+            return Err(format!(
+                "文件名模板'{}'中没有{{index}}，各帧会互相覆盖",
+                template
+            ));
+        }
+        Ok(NameTemplate(template.to_string()))
+    }
+
+    /// 第`index`帧、视图为`view`时的文件名。
+    fn render(&self, index: usize, view: (Complex<f64>, Complex<f64>)) -> String {
+        let center = (view.0 + view.1) / 2.0;
+        self.0
+            .replace("{index}", &format!("{:04}", index))
+            .replace("{zoom}", &format!("{:e}", view.1.re - view.0.re))
+            .replace("{re}", &center.re.to_string())
+            .replace("{im}", &center.im.to_string())
+    }
+}
+
+#[test]
+fn test_name_template() {
+    let animation = Animation {
+        frames: 3,
+        zoom: 2.0,
+        motion_blur: 1,
+        cache: None,
+        name_template: NameTemplate::parse("zoom_{zoom}_{index}@{re},{im}.png", 3).unwrap(),
+        color_cycle: None,
+        gif_fps: None,
+    };
+    let upper_left = Complex { re: -2.0, im: 1.5 };
+    let lower_right = Complex { re: 1.0, im: -1.5 };
+    let names: Vec<String> = (0..2)
+        .map(|frame| {
+            let view = animation.view(upper_left, lower_right, frame as f64);
+            animation.name_template.render(frame, view)
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "zoom_3e0_0000@-0.5,0.png",
+            "zoom_2.1213203435596424e0_0001@-0.5,0.png"
+        ]
+    );
+
+    assert_eq!(
+        NameTemplate::parse(DEFAULT_NAME_TEMPLATE, 2)
+            .unwrap()
+            .render(7, (upper_left, lower_right)),
+        "frame_0007.png"
+    );
+    assert!(NameTemplate::parse("still.png", 2).is_err());
+    assert!(NameTemplate::parse("still.png", 1).is_ok());
+    assert!(NameTemplate::parse("{index}_{frame}.png", 2).is_err());
+}
+
+impl Animation {
+    /// 返回时刻`t`的视图，`t`以帧为单位，可以是小数：第`k`帧覆盖`[k, k + 1)`。
+    fn view(
+        &self,
+        upper_left: Complex<f64>,
+        lower_right: Complex<f64>,
+        t: f64,
+    ) -> (Complex<f64>, Complex<f64>) {
+        if self.frames <= 1 || self.color_cycle.is_some() {
+            return (upper_left, lower_right);
+        }
+        let factor = self.zoom.powf(t / (self.frames - 1) as f64);
+        zoom_view(upper_left, lower_right, factor)
+    }
+
+    /// 渲染第`frame`帧：取该帧时间间隔内`motion_blur`个子帧的平均灰度。
+    ///
+    /// 给定`cache`时各像素的轨道先从缓存中查找，见`OrbitCache`。
+    fn render_frame(
+        &self,
+        frame: usize,
+        bounds: (usize, usize),
+        upper_left: Complex<f64>,
+        lower_right: Complex<f64>,
+        options: RenderOptions,
+        mut cache: Option<&mut OrbitCache>,
+    ) -> Vec<f32> {
+        let mut sum = vec![0.0; bounds.0 * bounds.1];
+        let mut shades = vec![0.0; bounds.0 * bounds.1];
+        for sub in 0..self.motion_blur {
+            let t = frame as f64 + sub as f64 / self.motion_blur as f64;
+            let (frame_upper_left, frame_lower_right) = self.view(upper_left, lower_right, t);
+            match cache.as_deref_mut() {
+                None => {
+                    render_parallel(
+                        &mut shades,
+                        bounds,
+                        frame_upper_left,
+                        frame_lower_right,
+                        options,
+                        None,
+                        RenderControl::default(),
+                    );
+                }
+                Some(cache) => cache.render(
+                    &mut shades,
+                    bounds,
+                    frame_upper_left,
+                    frame_lower_right,
+                    options,
+                ),
+            }
+            for (s, &v) in sum.iter_mut().zip(&shades) {
+                *s += v;
+            }
+        }
+        if self.motion_blur == 1 {
+            return shades;
+        }
+        for s in &mut sum {
+            *s /= self.motion_blur as f32;
+        }
+        sum
+    }
+}
+
+/// 渲染动画的每一帧，依次写入目录`args.filename`下按`--name-template`命名的文件，
+/// 默认为`frame_0000.png`、`frame_0001.png`等。
+fn write_animation(args: &Arguments, animation: &Animation) -> Result<(), std::io::Error> {
+    if let Some(fps) = animation.gif_fps {
+        return write_gif(args, animation, fps);
+    }
+    std::fs::create_dir_all(&args.filename)?;
+    render_frames(args, animation, |frame, view, pixels, color| {
+        let path =
+            std::path::Path::new(&args.filename).join(animation.name_template.render(frame, view));
+        let params = ImageParams {
+            bounds: args.bounds,
+            upper_left: view.0,
+            lower_right: view.1,
+        };
+        write_image(
+            path.to_str().unwrap(),
+            pixels,
+            args.bounds,
+            color,
+            Some(&params),
+            args.encoding,
+        )
+    })
+}
+
+/// 依次渲染动画的每一帧，用`shades_to_pixels`转为像素后交给`write(帧号, 视图, 像素, 像素格式)`。
+///
+/// 给定`--color-cycle-offset`时只渲染一次，第`k`帧用旋转了`k * OFFSET`的调色板着色，视图都是第一帧的视图。
+fn render_frames<W>(
+    args: &Arguments,
+    animation: &Animation,
+    mut write: W,
+) -> Result<(), std::io::Error>
+where
+    W: FnMut(usize, (Complex<f64>, Complex<f64>), &[u8], ColorType) -> Result<(), std::io::Error>,
+{
+    let mut cache = animation.cache.map(OrbitCache::new);
+    let mut render = |frame| {
+        animation.render_frame(
+            frame,
+            args.bounds,
+            args.upper_left,
+            args.lower_right,
+            args.options,
+            cache.as_mut(),
+        )
+    };
+    if let (Some(step), Some(palette)) = (animation.color_cycle, &args.palette) {
+        let shades = render(0);
+        let view = (args.upper_left, args.lower_right);
+        for frame in 0..animation.frames {
+            let pixels = colorize(&shades, &palette.rotated(frame as f64 * step));
+            write(frame, view, &pixels, ColorType::RGB(8))?;
+        }
+        return Ok(());
+    }
+    for frame in 0..animation.frames {
+        let shades = render(frame);
+        let view = animation.view(args.upper_left, args.lower_right, frame as f64);
+        let (pixels, color) = shades_to_pixels(&shades, args);
+        write(frame, view, &pixels, color)?;
+    }
+    Ok(())
+}
+
+/// `--gif`默认的每秒帧数。
+const DEFAULT_GIF_FPS: usize = 10;
+
+/// 与`write_animation`相同，但把各帧量化为256色后合成一个循环播放的GIF动画，写入文件`args.filename`。
+///
+/// GIF的帧间隔以百分之一秒为单位，因此`fps`超过100时按100计算。
+fn write_gif(args: &Arguments, animation: &Animation, fps: usize) -> Result<(), std::io::Error> {
+    let (width, height) = (args.bounds.0 as u16, args.bounds.1 as u16);
+    let file = std::io::BufWriter::new(File::create(&args.filename)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(std::io::Error::other)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(std::io::Error::other)?;
+
+    render_frames(args, animation, |_, _, pixels, color| {
+        // `output_depth`对GIF总是选择8位，`parse_args`也拒绝了透明的内部点。
+        let gray;
+        let rgb = match color {
+            ColorType::RGB(8) => pixels,
+            ColorType::Gray(8) => {
+                gray = pixels
+                    .iter()
+                    .flat_map(|&gray| [gray; 3])
+                    .collect::<Vec<u8>>();
+                &gray
+            }
+            other => {
+                return Err(std::io::Error::other(format!(
+                    "GIF不支持{:?}格式的像素",
+                    other
+                )))
+            }
+        };
+        let mut image = gif::Frame::from_rgb_speed(width, height, rgb, 10);
+        image.delay = gif_delay(fps);
+        encoder.write_frame(&image).map_err(std::io::Error::other)
+    })
+}
+
+/// 每秒`fps`帧时GIF的帧间隔，以百分之一秒为单位，至少为1。
+fn gif_delay(fps: usize) -> u16 {
+    (100.0 / fps as f64).round().max(1.0) as u16
+}
+
+#[test]
+fn test_gif() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-zoom-{}.gif", std::process::id()));
+    let args: Vec<String> = [
+        "--frames",
+        "4",
+        "--gif",
+        "--fps",
+        "20",
+        "--smooth",
+        path.to_str().unwrap(),
+        "32x24",
+        "-2,1.2",
+        "1,-1.2",
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect();
+    let args = parse_args(&args).unwrap();
+    write_animation(&args, args.animation.as_ref().unwrap()).unwrap();
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (32, 24));
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!((frame.width, frame.height), (32, 24));
+        assert_eq!(frame.delay, 5);
+        frames.push(frame.buffer.to_vec());
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(frames.len(), 4);
+    // 每帧都放大了一些，相邻的帧不同。
+    assert_ne!(frames[0], frames[1]);
+
+    assert_eq!(gif_delay(10), 10);
+    assert_eq!(gif_delay(1000), 1);
+    let to_args = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    };
+    assert!(to_args(&["--gif", "out.gif", "32x24", "-2,1.2", "1,-1.2"]).is_err());
+    assert!(to_args(&["--fps", "5", "out", "32x24", "-2,1.2", "1,-1.2"]).is_err());
+}
+
+#[test]
+fn test_color_cycle() {
+    let dir = std::env::temp_dir().join(format!("mandelbrot-cycle-{}", std::process::id()));
+    let args: Vec<String> = [
+        "--frames",
+        "3",
+        "--color-cycle-offset",
+        "0.25",
+        "--palette",
+        "fire",
+        dir.to_str().unwrap(),
+        "32x24",
+        "-2,1.2",
+        "1,-1.2",
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect();
+    let args = parse_args(&args).unwrap();
+    write_animation(&args, args.animation.as_ref().unwrap()).unwrap();
+    let read_frame = |index: usize| {
+        let path = dir.join(format!("frame_{:04}.png", index));
+        image::open(path).unwrap().to_rgb().into_raw()
+    };
+    let frames: Vec<Vec<u8>> = (0..3).map(read_frame).collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // 各帧的几何相同，第N帧等于用旋转了N * 0.25的调色板为第0帧的灰度着色。
+    let mut shades = vec![0.0; 32 * 24];
+    render_parallel(
+        &mut shades,
+        (32, 24),
+        args.upper_left,
+        args.lower_right,
+        args.options,
+        None,
+        RenderControl::default(),
+    );
+    let palette = args.palette.as_ref().unwrap();
+    assert_eq!(frames[0], colorize(&shades, palette));
+    for (index, frame) in frames.iter().enumerate() {
+        assert_eq!(
+            *frame,
+            colorize(&shades, &palette.rotated(index as f64 * 0.25))
+        );
+    }
+    assert_ne!(frames[0], frames[1]);
+}
+
+#[test]
+fn test_motion_blur() {
+    let bounds = (32, 24);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let options = RenderOptions {
+        smooth: true,
+        ..Default::default()
+    };
+    let render_view = |(ul, lr): (Complex<f64>, Complex<f64>)| {
+        let mut shades = vec![0.0; bounds.0 * bounds.1];
+        render_parallel(
+            &mut shades,
+            bounds,
+            ul,
+            lr,
+            options,
+            None,
+            RenderControl::default(),
+        );
+        shades
+    };
+
+    // 只有一个子帧时与不模糊的渲染完全相同。
+    let sharp = Animation {
+        frames: 5,
+        zoom: 8.0,
+        motion_blur: 1,
+        cache: None,
+        name_template: NameTemplate::parse(DEFAULT_NAME_TEMPLATE, 1).unwrap(),
+        color_cycle: None,
+        gif_fps: None,
+    };
+    let frame = sharp.render_frame(2, bounds, upper_left, lower_right, options, None);
+    assert_eq!(frame, render_view(sharp.view(upper_left, lower_right, 2.0)));
+
+    // 两个子帧时等于第2帧起点和中点两次渲染的平均。
+    let blurred = Animation {
+        motion_blur: 2,
+        ..sharp
+    };
+    let frame = blurred.render_frame(2, bounds, upper_left, lower_right, options, None);
+    let first = render_view(blurred.view(upper_left, lower_right, 2.0));
+    let second = render_view(blurred.view(upper_left, lower_right, 2.5));
+    assert_ne!(first, second);
+    let expected: Vec<f32> = first
+        .iter()
+        .zip(&second)
+        .map(|(a, b)| (a + b) / 2.0)
+        .collect();
+    assert_eq!(frame, expected);
+}
+
+/// 在动画的各帧之间共享的轨道缓存。
+///
+/// 每个点先量化到边长为`tolerance`的网格上，落在同一个格子里的点共用最先算出的那个轨道，
+/// 因此像素实际使用的轨道所对应的点与像素本身的点在实部和虚部上都相差小于`tolerance`。
+/// 每个条带有自己的缓存，只由渲染该条带的线程访问，不需要加锁；各帧的条带划分相同，
+/// 同一条带在相邻帧中覆盖的区域相近，围绕中心缩放时仍能命中大部分重复的点。
+/// 缓存在整个动画期间只增不减。
+struct OrbitCache {
+    tolerance: f64,
+    bands: Vec<HashMap<(i64, i64), Orbit>>,
+}
+
+impl OrbitCache {
+    fn new(tolerance: f64) -> OrbitCache {
+        OrbitCache {
+            tolerance,
+            bands: Vec::new(),
+        }
+    }
+
+    /// 与`render_parallel`相同，但各像素的轨道先从所在条带的缓存中查找。
+    ///
+    /// 同一个缓存应始终使用相同的`options`渲染。
+    fn render(
+        &mut self,
+        shades: &mut [f32],
+        bounds: (usize, usize),
+        upper_left: Complex<f64>,
+        lower_right: Complex<f64>,
+        options: RenderOptions,
+    ) {
+        let tolerance = self.tolerance;
+        map_points_parallel_with(
+            shades,
+            bounds,
+            upper_left,
+            lower_right,
+            options.chunk_rows,
+            &mut self.bands,
+            |orbits, point| {
+                let point = options.transform.apply(point);
+                let orbit = cached_orbit(orbits, tolerance, point, options.limit, options.norm);
+                shade(&orbit, options)
+            },
+        );
+    }
+}
+
+/// 返回`point`的轨道，与`fast_orbit(point, limit, norm)`相同，但可能取自`orbits`中的邻近点，
+/// 见`OrbitCache`。
+fn cached_orbit(
+    orbits: &mut HashMap<(i64, i64), Orbit>,
+    tolerance: f64,
+    point: Complex<f64>,
+    limit: usize,
+    norm: Norm,
+) -> Orbit {
+    let scaled = point / tolerance;
+    // 超出i64范围的坐标无法量化，直接计算。
+    if !(scaled.re.abs() < 9e18 && scaled.im.abs() < 9e18) {
+        return fast_orbit(point, limit, norm);
+    }
+    let key = (scaled.re.floor() as i64, scaled.im.floor() as i64);
+    *orbits
+        .entry(key)
+        .or_insert_with(|| fast_orbit(point, limit, norm))
+}
+
+#[test]
+fn test_orbit_cache() {
+    let bounds = (32, 24);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let options = RenderOptions::default();
+    let animation = Animation {
+        frames: 4,
+        zoom: 2.0,
+        motion_blur: 2,
+        cache: None,
+        name_template: NameTemplate::parse(DEFAULT_NAME_TEMPLATE, 1).unwrap(),
+        color_cycle: None,
+        gif_fps: None,
+    };
+
+    // 容差极小时只有几乎重合的点才会共用轨道，输出与不使用缓存时逐像素相同。
+    let mut cache = OrbitCache::new(1e-12);
+    for frame in 0..animation.frames {
+        let cached = animation.render_frame(
+            frame,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            Some(&mut cache),
+        );
+        let uncached =
+            animation.render_frame(frame, bounds, upper_left, lower_right, options, None);
+        assert_eq!(cached, uncached);
+    }
+    // 围绕中心缩放时，中心附近的点在各帧之间重复出现。
+    let cached: usize = cache.bands.iter().map(HashMap::len).sum();
+    assert!(cached < bounds.0 * bounds.1 * animation.frames * animation.motion_blur);
+
+    // 容差较大时相邻的点共用轨道。
+    let mut coarse = HashMap::new();
+    let a = cached_orbit(
+        &mut coarse,
+        0.5,
+        Complex { re: 0.3, im: 0.0 },
+        255,
+        Norm::L2,
+    );
+    let b = cached_orbit(
+        &mut coarse,
+        0.5,
+        Complex { re: 0.2, im: 0.1 },
+        255,
+        Norm::L2,
+    );
+    assert_eq!(a, b);
+    assert_eq!(coarse.len(), 1);
+}
+
+/// 按`render_parallel`逐行渲染时相同的映射求出每个像素对应的点，在多个线程中将`f(点)`写入`pixels`。
+///
+/// 与`render_parallel`一样按`chunk_rows`划分条带，由至多`MAX_THREADS`个线程依次领取。
+fn map_points_parallel<T: Send>(
+    pixels: &mut [T],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    chunk_rows: Option<usize>,
+    f: impl Fn(Complex<f64>) -> T + Sync,
+) {
+    map_points_parallel_with(
+        pixels,
+        bounds,
+        upper_left,
+        lower_right,
+        chunk_rows,
+        &mut Vec::<()>::new(),
+        |_, point| f(point),
+    );
+}
+
+/// 与`map_points_parallel`相同，但第`i`个条带中的点调用`f(&mut states[i], 点)`，
+/// 同一条带的各个点可以共享状态。`states`比条带少时用默认值补足。
+fn map_points_parallel_with<T: Send, S: Send + Default>(
+    pixels: &mut [T],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    chunk_rows: Option<usize>,
+    states: &mut Vec<S>,
+    f: impl Fn(&mut S, Complex<f64>) -> T + Sync,
+) {
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let rows = band_rows(bounds.1, MAX_THREADS, chunk_rows);
+    let bands = split_bands(pixels, bounds.0, &rows);
+    if states.len() < bands.len() {
+        states.resize_with(bands.len(), S::default);
+    }
+    let threads = bands.len().min(MAX_THREADS);
+    let queue = Mutex::new(bands.into_iter().zip(states.iter_mut()));
+    let f = &f;
+    crossbeam::scope(|spawner| {
+        for _ in 0..threads {
+            spawner.spawn(|_| loop {
+                let next = queue.lock().unwrap().next();
+                let ((top, band), state) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                for (offset, line) in band.chunks_mut(bounds.0).enumerate() {
+                    let (row_upper_left, row_lower_right) =
+                        row_view(bounds, top + offset, upper_left, lower_right);
+                    for (column, pixel) in line.iter_mut().enumerate() {
+                        *pixel = f(
+                            state,
+                            pixel_to_point(
+                                (bounds.0, 1),
+                                (column, 0),
+                                row_upper_left,
+                                row_lower_right,
+                            ),
+                        );
+                    }
+                }
+            });
+        }
+    })
+    .unwrap();
+}
+
+/// `render_parallel`最多使用的线程数。
+const MAX_THREADS: usize = 8;
+
+/// `render_parallel`渲染`bounds`大小的图像时使用的线程数。
+fn render_threads(bounds: (usize, usize), options: RenderOptions) -> usize {
+    band_rows(bounds.1, MAX_THREADS, options.chunk_rows)
+        .len()
+        .min(MAX_THREADS)
+}
+
+/// `--timing-log`中的一行记录。
+#[derive(Debug)]
+struct Timing {
+    /// 渲染结束时的Unix时间戳，单位为秒。
+    timestamp: u64,
+    bounds: (usize, usize),
+    /// 迭代次数的上限。
+    iterations: usize,
+    threads: usize,
+    elapsed: Duration,
+}
+
+const TIMING_HEADER: &str = "timestamp,width,height,iterations,threads,elapsed_ms";
+
+/// 向CSV文件`path`追加一行`timing`，文件不存在或为空时先写入表头。
+fn append_timing(path: &str, timing: &Timing) -> Result<(), std::io::Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut text = String::new();
+    if file.metadata()?.len() == 0 {
+        text.push_str(TIMING_HEADER);
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "{},{},{},{},{},{}\n",
+        timing.timestamp,
+        timing.bounds.0,
+        timing.bounds.1,
+        timing.iterations,
+        timing.threads,
+        timing.elapsed.as_millis()
+    ));
+    // 一次写入整行，多个进程同时追加时各行不会交错。
+    file.write_all(text.as_bytes())
+}
+
+#[test]
+fn test_timing_log() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-timing-{}.csv", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let mut timing = Timing {
+        timestamp: 1_700_000_000,
+        bounds: (1000, 750),
+        iterations: 255,
+        threads: render_threads((1000, 750), RenderOptions::default()),
+        elapsed: Duration::from_micros(1_234_567),
+    };
+    append_timing(path, &timing).unwrap();
+    timing.bounds = (3, 2);
+    timing.threads = render_threads((3, 2), RenderOptions::default());
+    timing.elapsed = Duration::from_millis(5);
+    append_timing(path, &timing).unwrap();
+
+    let text = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(
+        text,
+        format!(
+            "{}\n1700000000,1000,750,255,8,1234\n1700000000,3,2,255,2,5\n",
+            TIMING_HEADER
+        )
+    );
+}
+
+/// 将图像按行分成多个条带，在多个线程中并行渲染到`pixels`。
+///
+/// 条带的行数由`options.chunk_rows`给定，默认每个线程一个条带。条带放在共享的队列中，
+/// 空闲的线程从中取出下一个条带，因此条带较小时各线程的负载更均衡。
+///
+/// 给定`checkpoint`时逐行渲染：跳过`done`中标记为已完成的行，
+/// 每渲染完一行就将其追加到检查点文件中。返回所有线程迭代次数统计的总和。
+///
+/// `control.cancel`被置位后不再渲染新的行，尚未渲染的像素保持原来的值；
+/// 给定`control.progress`时每渲染完一行就更新进度。
+fn render_parallel(
+    pixels: &mut [f32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+    checkpoint: Option<(&Checkpoint, &[bool])>,
+    control: RenderControl,
+) -> RenderStats {
+    render_parallel_with(
+        (pixels, None),
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        checkpoint,
+        control,
+    )
+}
+
+/// 与`render_parallel`相同，但给定`orbits`时同时把每个像素的轨道写入其中，
+/// 检查点中也会保存各行的轨道。
+fn render_parallel_with(
+    (pixels, orbits): (&mut [f32], Option<&mut [Orbit]>),
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+    checkpoint: Option<(&Checkpoint, &[bool])>,
+    control: RenderControl,
+) -> RenderStats {
+    let rows = band_rows(bounds.1, MAX_THREADS, options.chunk_rows);
+    let mut orbit_bands = orbits.map(|orbits| split_bands(orbits, bounds.0, &rows).into_iter());
+    let bands: Vec<_> = split_bands(pixels, bounds.0, &rows)
+        .into_iter()
+        .map(|(top, band)| {
+            let orbits = orbit_bands.as_mut().map(|bands| bands.next().unwrap().1);
+            (top, band, orbits)
+        })
+        .collect();
+    let threads = bands.len().min(MAX_THREADS);
+    let queue = Mutex::new(bands.into_iter());
+    crossbeam::scope(|spawner| {
+        let mut handles = Vec::new();
+        for _ in 0..threads {
+            handles.push(spawner.spawn(|_| {
+                let mut stats = RenderStats::default();
+                loop {
+                    let next = queue.lock().unwrap().next();
+                    let band = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    stats += render_band(
+                        band,
+                        bounds,
+                        upper_left,
+                        lower_right,
+                        options,
+                        checkpoint,
+                        control,
+                    );
+                }
+                stats
+            }));
+        }
+
+        let mut stats = RenderStats::default();
+        for handle in handles {
+            stats += handle.join().unwrap();
+        }
+        stats
+    })
+    .unwrap()
+}
+
+/// 将`height`行的图像分成若干连续的条带，返回每个条带的行范围。
+///
+/// 给定`chunk_rows`时每个条带有`chunk_rows`行（最后一个可能更少），否则分成`threads`个条带，
+/// 前`height % threads`个条带比其余的多一行。行数少于`threads`时每行一个条带，不会产生空条带。
+fn band_rows(height: usize, threads: usize, chunk_rows: Option<usize>) -> Vec<Range<usize>> {
+    let mut bands = Vec::new();
+    let mut top = 0;
+    match chunk_rows {
+        Some(rows) => {
+            while top < height {
+                bands.push(top..(top + rows).min(height));
+                top += rows;
+            }
+        }
+        None => {
+            let count = threads.min(height);
+            for i in 0..count {
+                let rows = height / count + usize::from(i < height % count);
+                bands.push(top..top + rows);
+                top += rows;
+            }
+        }
+    }
+    bands
+}
+
+#[test]
+fn test_band_rows() {
+    for height in 0..40 {
+        for threads in 1..12 {
+            for chunk_rows in [None, Some(1), Some(3), Some(16)] {
+                let bands = band_rows(height, threads, chunk_rows);
+                // 条带首尾相接，恰好覆盖0..height，且没有空条带。
+                let mut next = 0;
+                for band in &bands {
+                    assert_eq!(band.start, next);
+                    assert!(band.end > band.start);
+                    next = band.end;
+                }
+                assert_eq!(next, height);
+
+                if chunk_rows.is_none() {
+                    assert_eq!(bands.len(), threads.min(height));
+                    let sizes: Vec<usize> = bands.iter().map(|band| band.len()).collect();
+                    let (min, max) = (sizes.iter().min(), sizes.iter().max());
+                    assert!(max.unwrap_or(&0) - min.unwrap_or(&0) <= 1);
+                }
+            }
+        }
+    }
+}
+
+/// 按`bands`中的行范围切分每行`width`个元素的缓冲`pixels`，返回每个条带的首行和对应的切片。
+fn split_bands<'a, T>(
+    mut pixels: &'a mut [T],
+    width: usize,
+    bands: &[Range<usize>],
+) -> Vec<(usize, &'a mut [T])> {
+    let mut split = Vec::with_capacity(bands.len());
+    for band in bands {
+        let (head, tail) = pixels.split_at_mut(band.len() * width);
+        split.push((band.start, head));
+        pixels = tail;
+    }
+    split
+}
+
+/// 渲染整幅图像中从第`top`行开始的条带`band`，即`split_bands`给出的一项，
+/// 给定`orbits`时同时写入条带中各像素的轨道。其余参数与`render_parallel`相同。
+fn render_band(
+    (top, band, orbits): (usize, &mut [f32], Option<&mut [Orbit]>),
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+    checkpoint: Option<(&Checkpoint, &[bool])>,
+    control: RenderControl,
+) -> RenderStats {
+    let cancelled = || {
+        control
+            .cancel
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    };
+    let row_done = || {
+        if let Some(progress) = control.progress {
+            progress.row_done();
+        }
+    };
+    let mut orbit_lines = orbits.map(|orbits| orbits.chunks_mut(bounds.0));
+    // 逐行渲染，使每个像素对应的点只取决于它所在的行，而与条带的划分无关。
+    let mut stats = RenderStats::default();
+    for (offset, line) in band.chunks_mut(bounds.0).enumerate() {
+        let row = top + offset;
+        let mut orbit_line = orbit_lines.as_mut().map(|lines| lines.next().unwrap());
+        if cancelled() {
+            break;
+        }
+        if checkpoint.is_some_and(|(_, done)| done[row]) {
+            continue;
+        }
+        stats += render_row(
+            line,
+            orbit_line.as_deref_mut(),
+            bounds,
+            row,
+            upper_left,
+            lower_right,
+            options,
+        );
+        if let Some((checkpoint, _)) = checkpoint {
+            checkpoint
+                .record_row(row, line, orbit_line.as_deref())
+                .expect("写入检查点文件出错");
+        }
+        row_done();
+    }
+    stats
+}
+
+#[test]
+fn test_cancel() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-cancel-{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    let args: Vec<String> = [path, "40x30", "-2,1.2", "1,-1.2"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let args = parse_args(&args).unwrap();
+    let (bounds, options) = (args.bounds, args.options);
+    let (upper_left, lower_right) = (args.upper_left, args.lower_right);
+    let mut expected = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut expected,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        None,
+        RenderControl::default(),
+    );
+
+    // 渲染完前12行后按下Ctrl-C，之后的条带一行也不再渲染。
+    let cancel = AtomicBool::new(false);
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    let bands = split_bands(&mut shades, bounds.0, &[0..12, 12..30]);
+    for (top, band) in bands {
+        render_band(
+            (top, band, None),
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            None,
+            RenderControl {
+                cancel: Some(&cancel),
+                ..Default::default()
+            },
+        );
+        cancel.store(true, Ordering::Relaxed);
+    }
+    let computed = 12 * bounds.0;
+    assert_eq!(shades[..computed], expected[..computed]);
+    assert!(shades[computed..].iter().all(|&shade| shade == 0.0));
+
+    // 部分完成的图像仍是合法的PNG，已完成的行与完整渲染的相同。
+    write_shades(path, &shades, (upper_left, lower_right), &args).unwrap();
+    let decoded = image::open(path).unwrap().to_luma().into_raw();
+    std::fs::remove_file(path).unwrap();
+    let (full, _) = shades_to_pixels(&expected, &args);
+    assert_eq!(decoded[..computed], full[..computed]);
+    assert!(decoded[computed..].iter().all(|&value| value == 0));
+
+    // 一开始就取消时不渲染任何像素。
+    let mut pixels = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut pixels,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        None,
+        RenderControl {
+            cancel: Some(&cancel),
+            ..Default::default()
+        },
+    );
+    assert!(pixels.iter().all(|&shade| shade == 0.0));
+}
+
+#[test]
+fn test_chunk_rows() {
+    let bounds = (48, 37);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let render_chunks = |chunk_rows| {
+        let options = RenderOptions {
+            chunk_rows,
+            ..Default::default()
+        };
+        let mut pixels = vec![0.0; bounds.0 * bounds.1];
+        let stats = render_parallel(
+            &mut pixels,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            None,
+            RenderControl::default(),
+        );
+        (pixels, stats)
+    };
+
+    let expected = render_chunks(None);
+    for chunk_rows in [1, 2, 5, 36, 37, 100] {
+        assert_eq!(render_chunks(Some(chunk_rows)), expected);
+    }
+
+    // map_points_parallel按同样的条带划分，结果同样与划分无关。
+    let map_chunks = |chunk_rows| {
+        let mut points = vec![Complex { re: 0.0, im: 0.0 }; bounds.0 * bounds.1];
+        map_points_parallel(
+            &mut points,
+            bounds,
+            upper_left,
+            lower_right,
+            chunk_rows,
+            |point| point,
+        );
+        points
+    };
+    let expected = map_chunks(None);
+    for chunk_rows in [1, 5, 100] {
+        assert_eq!(map_chunks(Some(chunk_rows)), expected);
+    }
+}
+
+/// 比较不同条带大小下各线程的负载。视图中耗时的边界集中在中间几行，
+/// 默认划分下负责这几行的线程最慢，小条带可以把这部分工作分给其他线程。
+///
+/// 除了实际耗时（取决于机器的核数），还按每个条带的迭代次数模拟8个线程依次领取条带，
+/// 打印最忙线程的工作量与平均工作量之比，1.0表示完全均衡。
+///
+/// 用`cargo test --release -- --ignored --nocapture bench_chunk_rows`运行。
+#[test]
+#[ignore]
+fn bench_chunk_rows() {
+    let bounds = (1600, 1200);
+    let upper_left = Complex { re: -2.2, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let threads = 8;
+    let mut pixels = vec![0.0; bounds.0 * bounds.1];
+    for chunk_rows in [None, Some(64), Some(16), Some(4), Some(1)] {
+        let options = RenderOptions {
+            chunk_rows,
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        render_parallel(
+            &mut pixels,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            None,
+            RenderControl::default(),
+        );
+        let elapsed = start.elapsed();
+
+        let mut loads = vec![0; threads];
+        let bands = band_rows(bounds.1, threads, chunk_rows);
+        for (top, band) in split_bands(&mut pixels, bounds.0, &bands) {
+            let stats = render_band(
+                (top, band, None),
+                bounds,
+                upper_left,
+                lower_right,
+                options,
+                None,
+                RenderControl::default(),
+            );
+            // 下一个条带总是被最先空闲的线程领走。
+            *loads.iter_mut().min().unwrap() += stats.iterations;
+        }
+        let mean = loads.iter().sum::<u64>() as f64 / threads as f64;
+        println!(
+            "--chunk-rows {:?}: 耗时{:?}，最忙线程/平均 = {:.2}",
+            chunk_rows,
+            elapsed,
+            *loads.iter().max().unwrap() as f64 / mean
+        );
+    }
+}
+
+/// 将轨道的不同量映射到RGB各通道的方式。
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Channels {
+    /// 红、绿通道分别为最终`z`方向的实部和虚部，蓝通道为逃逸次数的灰度。
+    ReImIter,
+}
+
+/// 按`Channels::ReImIter`映射，在多个线程中并行渲染RGB图像到`pixels`。
+///
+/// 最终`z`先归一化为单位长度，实部和虚部从-1到1线性映射到0到255；
+/// 集合内部的点蓝通道为0，外部的点与灰度输出一样由`count_shade`计算。
+/// 迭代上限、范数和视图变换取自`options`。
+fn render_channels(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+) {
+    assert!(pixels.len() == bounds.0 * bounds.1 * 3);
+
+    let mut colors = vec![[0; 3]; bounds.0 * bounds.1];
+    map_points_parallel(
+        &mut colors,
+        bounds,
+        upper_left,
+        lower_right,
+        options.chunk_rows,
+        |point| {
+            let point = options.transform.apply(point);
+            reim_iter_color(
+                &orbit_with_norm(point, options.limit, options.norm),
+                options.limit,
+            )
+        },
+    );
+    for (rgb, color) in pixels.chunks_mut(3).zip(colors) {
+        rgb.copy_from_slice(&color);
+    }
+}
+
+/// 按`Channels::ReImIter`映射计算迭代上限为`limit`的单个轨道的颜色。
+fn reim_iter_color(orbit: &Orbit, limit: usize) -> [u8; 3] {
+    let norm = orbit.z.norm();
+    let direction = if norm == 0.0 {
+        Complex { re: 0.0, im: 0.0 }
+    } else {
+        orbit.z / norm
+    };
+    let to_byte = |v: f64| ((v + 1.0) * 127.5).round() as u8;
+    let blue = count_shade(orbit.escape, limit).round() as u8;
+    [to_byte(direction.re), to_byte(direction.im), blue]
+}
+
+#[test]
+fn test_render_channels() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    // 迭代上限和范数取自渲染选项。
+    let options = RenderOptions {
+        limit: 1000,
+        norm: Norm::LInf,
+        ..RenderOptions::default()
+    };
+    let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+    render_channels(&mut pixels, bounds, upper_left, lower_right, options);
+
+    let mut exterior = 0;
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            let orbit = orbit_with_norm(point, options.limit, options.norm);
+            let rgb = &pixels[(row * bounds.0 + column) * 3..][..3];
+            assert_eq!(rgb[2] == 0, orbit.escape.is_none());
+            assert_eq!(
+                rgb[2],
+                count_shade(orbit.escape, options.limit).round() as u8
+            );
+            if orbit.escape.is_some() {
+                // 逃逸点的z已离开半径2的区域，红色通道不低于中值当且仅当Re(z)为正。
+                exterior += 1;
+                if orbit.z.re.abs() > 0.01 {
+                    assert_eq!(rgb[0] >= 128, orbit.z.re > 0.0);
+                }
+            }
+        }
+    }
+    assert!(exterior > 0);
+}
+
+/// 单个像素的探测结果，用于调试着色。
+#[derive(Debug)]
+struct Probe {
+    pixel: (usize, usize),
+    point: Complex<f64>,
+    escape: Option<usize>,
+    final_z: Complex<f64>,
+    limit: usize,
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "像素: {},{}", self.pixel.0, self.pixel.1)?;
+        writeln!(f, "坐标: {},{}", self.point.re, self.point.im)?;
+        match self.escape {
+            Some(count) => writeln!(f, "逃逸次数: {}", count)?,
+            None => writeln!(f, "逃逸次数: 无（迭代{}次未逃逸，视为内部点）", self.limit)?,
+        }
+        writeln!(f, "最终z: {},{}", self.final_z.re, self.final_z.im)
+    }
+}
+
+/// 计算图像中像素`pixel`对应的点，并记录其逃逸信息。
+fn probe_pixel(
+    bounds: (usize, usize),
+    pixel: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: usize,
+    norm: Norm,
+) -> Probe {
+    let point = pixel_to_point(bounds, pixel, upper_left, lower_right);
+    let (escape, final_z) = escape_time_detail_with_norm(point, limit, norm);
+    Probe {
+        pixel,
+        point,
+        escape,
+        final_z,
+        limit,
+    }
+}
+
+#[test]
+fn test_probe_pixel() {
+    let upper_left = Complex { re: -2.0, im: 2.0 };
+    let lower_right = Complex { re: 2.0, im: -2.0 };
+
+    // 图像中心对应原点，位于集合深处。
+    let interior = probe_pixel((100, 100), (50, 50), upper_left, lower_right, 255, Norm::L2);
+    assert_eq!(interior.point, Complex { re: 0.0, im: 0.0 });
+    assert_eq!(interior.escape, None);
+    assert!(interior.final_z.norm_sqr() <= 4.0);
+
+    // 左上角的-2+2i很快就会逃逸。
+    let exterior = probe_pixel((100, 100), (0, 0), upper_left, lower_right, 255, Norm::L2);
+    assert_eq!(exterior.escape, Some(1));
+    assert!(exterior.final_z.norm_sqr() > 4.0);
+}
+
+/// 主心形线的外接矩形：实部从`-3/4`到`3/8`，虚部的绝对值不超过`3√3/8`。
+const CARDIOID_BOX: (Complex<f64>, Complex<f64>) = (
+    Complex {
+        re: -0.75,
+        im: 0.649_519_052_838_329,
+    },
+    Complex {
+        re: 0.375,
+        im: -0.649_519_052_838_329,
+    },
+);
+
+/// `--info`打印的视图信息。
+#[derive(Debug)]
+struct ViewInfo {
+    bounds: (usize, usize),
+    /// 视图在复平面上的宽和高。
+    size: (f64, f64),
+    /// 相邻像素在实轴和虚轴方向上相差的距离。
+    resolution: (f64, f64),
+    /// 主心形线是否完整地位于视图内。
+    contains_cardioid: bool,
+    /// 中心落在主心形线内的像素数。
+    cardioid_pixels: usize,
+}
+
+impl ViewInfo {
+    /// 像素是否为正方形，即两个方向的分辨率是否相同。
+    fn square_pixels(&self) -> bool {
+        (self.resolution.0 - self.resolution.1).abs() <= 1e-9 * self.resolution.0.abs()
+    }
+}
+
+impl std::fmt::Display for ViewInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "图像尺寸: {}x{}", self.bounds.0, self.bounds.1)?;
+        writeln!(f, "复平面尺寸: {} x {}", self.size.0, self.size.1)?;
+        writeln!(
+            f,
+            "每像素分辨率: {:e} x {:e}",
+            self.resolution.0, self.resolution.1
+        )?;
+        if self.square_pixels() {
+            writeln!(f, "宽高比: 与图像一致")?;
+        } else {
+            writeln!(
+                f,
+                "宽高比: 不一致，每个像素宽高之比为{}",
+                self.resolution.0 / self.resolution.1
+            )?;
+        }
+        if self.contains_cardioid {
+            writeln!(f, "主心形线: 完整包含（{}个像素）", self.cardioid_pixels)
+        } else if self.cardioid_pixels > 0 {
+            writeln!(f, "主心形线: 部分包含（{}个像素）", self.cardioid_pixels)
+        } else {
+            writeln!(f, "主心形线: 不包含")
+        }
+    }
+}
+
+/// 计算`bounds`大小、角点为`upper_left`和`lower_right`的视图的信息。
+fn view_info(
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> ViewInfo {
+    let (box_ul, box_lr) = CARDIOID_BOX;
+    let cardioid_pixels = (0..bounds.1)
+        .flat_map(|row| (0..bounds.0).map(move |column| (column, row)))
+        .filter(|&pixel| in_main_cardioid(pixel_to_point(bounds, pixel, upper_left, lower_right)))
+        .count();
+    let size = (
+        lower_right.re - upper_left.re,
+        upper_left.im - lower_right.im,
+    );
+    ViewInfo {
+        bounds,
+        size,
+        resolution: (size.0 / bounds.0 as f64, size.1 / bounds.1 as f64),
+        contains_cardioid: upper_left.re <= box_ul.re
+            && upper_left.im >= box_ul.im
+            && lower_right.re >= box_lr.re
+            && lower_right.im <= box_lr.im,
+        cardioid_pixels,
+    }
+}
+
+#[test]
+fn test_view_info() {
+    let upper_left = Complex { re: -2.5, im: 1.5 };
+    let lower_right = Complex { re: 1.5, im: -1.5 };
+    let full = view_info((800, 600), upper_left, lower_right);
+    assert_eq!(full.size, (4.0, 3.0));
+    assert_eq!(full.resolution, (0.005, 0.005));
+    assert!(full.square_pixels());
+    assert!(full.contains_cardioid);
+    assert!(full.cardioid_pixels > 0);
+    let text = full.to_string();
+    assert!(text.contains("每像素分辨率: 5e-3 x 5e-3"));
+    assert!(text.contains("主心形线: 完整包含"));
+
+    // 拉伸的视图，以及只截取到心形线一部分的视图。
+    let stretched = view_info((400, 600), upper_left, lower_right);
+    assert!(!stretched.square_pixels());
+    let part = view_info(
+        (100, 100),
+        Complex { re: 0.0, im: 0.5 },
+        Complex { re: 1.0, im: -0.5 },
+    );
+    assert!(!part.contains_cardioid && part.cardioid_pixels > 0);
+    let outside = view_info(
+        (100, 100),
+        Complex { re: 1.0, im: 2.0 },
+        Complex { re: 2.0, im: 1.0 },
+    );
+    assert_eq!(outside.cardioid_pixels, 0);
+    assert!(outside.to_string().contains("主心形线: 不包含"));
+}
+
+/// `--area`打印的面积估计。
+#[derive(Debug)]
+struct AreaEstimate {
+    /// 在迭代上限内没有逃逸的像素数。
+    interior_pixels: usize,
+    total_pixels: usize,
+    /// 视图在复平面上的面积。
+    view_area: f64,
+}
+
+impl AreaEstimate {
+    /// 视图内集合的面积，即内部像素的比例乘以视图的面积。
+    fn area(&self) -> f64 {
+        self.interior_pixels as f64 / self.total_pixels as f64 * self.view_area
+    }
+}
+
+impl std::fmt::Display for AreaEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "面积估计: {:.6}（内部像素{}/{}，视图面积{}）",
+            self.area(),
+            self.interior_pixels,
+            self.total_pixels,
+            self.view_area
+        )
+    }
+}
+
+/// 在渲染所用的像素网格上数出内部点，估计视图内Mandelbrot集的面积。
+///
+/// 每个像素取其左上角的点，与渲染时一样先做视图变换，再按所选的范数判断是否属于集合。
+/// 没有被证明会逃逸的点都算作内部点，因此迭代上限`options.limit`太小时估计偏大；
+/// 网格越密、上限越大，估计值越接近真实的面积（整个集合约为1.5066）。
+fn estimate_area(
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+) -> AreaEstimate {
+    let mut interior = vec![false; bounds.0 * bounds.1];
+    map_points_parallel(
+        &mut interior,
+        bounds,
+        upper_left,
+        lower_right,
+        options.chunk_rows,
+        |point| {
+            escape_time_with_norm(options.transform.apply(point), options.limit, options.norm)
+                .is_none()
+        },
+    );
+    AreaEstimate {
+        interior_pixels: interior.iter().filter(|&&inside| inside).count(),
+        total_pixels: interior.len(),
+        view_area: (lower_right.re - upper_left.re) * (upper_left.im - lower_right.im),
+    }
+}
+
+#[test]
+fn test_estimate_area() {
+    const KNOWN_AREA: f64 = 1.5066;
+    let upper_left = Complex { re: -2.0, im: 1.5 };
+    let lower_right = Complex { re: 1.0, im: -1.5 };
+    let error = |bounds, limit| {
+        let options = RenderOptions {
+            limit,
+            ..RenderOptions::default()
+        };
+        (estimate_area(bounds, upper_left, lower_right, options).area() - KNOWN_AREA).abs()
+    };
+
+    // 网格越密，估计越接近已知的面积。
+    let coarse = error((30, 30), 1000);
+    let fine = error((120, 120), 1000);
+    assert!(fine < coarse);
+    assert!(fine < 0.02);
+
+    // 迭代上限太小时，许多外部点被误算为内部点。
+    assert!(error((300, 300), 10000) < error((300, 300), 100));
+
+    // 与渲染时一样按变换后的点判断：这个视图本身全在集合之外，绕原点转半圈后覆盖-1附近的圆盘。
+    let (upper_left_out, lower_right_out) =
+        (Complex { re: 1.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 });
+    let flipped = RenderOptions {
+        transform: ViewTransform {
+            center: Complex { re: 0.0, im: 0.0 },
+            rotate: std::f64::consts::PI,
+            shear: 0.0,
+        },
+        ..RenderOptions::default()
+    };
+    let interior =
+        |options| estimate_area((40, 40), upper_left_out, lower_right_out, options).interior_pixels;
+    assert_eq!(interior(RenderOptions::default()), 0);
+    assert!(interior(flipped) > 0);
+
+    let options = RenderOptions::default();
+    let text = estimate_area((2, 2), upper_left, lower_right, options).to_string();
+    assert_eq!(text, "面积估计: 4.500000（内部像素2/4，视图面积9）");
+}
+
+/// `--auto-interesting`在每个方向上将视图分成的子区域数。
+const INTEREST_GRID: usize = 4;
+
+/// `--auto-interesting`找到的子区域。
+#[derive(Debug, PartialEq)]
+struct Interesting {
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    /// 子区域内逃逸次数不同的相邻像素对数，越多说明边界越复杂。
+    transitions: usize,
+}
+
+impl std::fmt::Display for Interesting {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "建议放大: {},{} {},{}（{}处逃逸次数变化）",
+            self.upper_left.re,
+            self.upper_left.im,
+            self.lower_right.re,
+            self.lower_right.im,
+            self.transitions
+        )
+    }
+}
+
+/// 在`bounds`大小的逃逸次数网格`counts`中找出边界最复杂的子区域。
+///
+/// 视图被分为`INTEREST_GRID`x`INTEREST_GRID`个子区域，每个子区域数出其中上下或左右相邻、
+/// 逃逸次数不同（包括一个在集合内部、一个在外部）的像素对，取数目最多的一个，相同时取靠前的。
+/// 平坦的内部区域和远离集合的外部区域几乎没有变化，细小的结构（如小Mandelbrot集）周围则变化最多。
+fn find_interesting(
+    counts: &[Option<usize>],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> Interesting {
+    assert!(counts.len() == bounds.0 * bounds.1);
+    let mut best: Option<Interesting> = None;
+    for region_row in 0..INTEREST_GRID {
+        for region_column in 0..INTEREST_GRID {
+            let columns = region_column * bounds.0 / INTEREST_GRID
+                ..(region_column + 1) * bounds.0 / INTEREST_GRID;
+            let rows =
+                region_row * bounds.1 / INTEREST_GRID..(region_row + 1) * bounds.1 / INTEREST_GRID;
+            let mut transitions = 0;
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    let count = counts[row * bounds.0 + column];
+                    if column + 1 < columns.end && counts[row * bounds.0 + column + 1] != count {
+                        transitions += 1;
+                    }
+                    if row + 1 < rows.end && counts[(row + 1) * bounds.0 + column] != count {
+                        transitions += 1;
+                    }
+                }
+            }
+            if best
+                .as_ref()
+                .is_some_and(|best| best.transitions >= transitions)
+            {
+                continue;
+            }
+            best = Some(Interesting {
+                upper_left: pixel_to_point(
+                    bounds,
+                    (columns.start, rows.start),
+                    upper_left,
+                    lower_right,
+                ),
+                lower_right: pixel_to_point(
+                    bounds,
+                    (columns.end, rows.end),
+                    upper_left,
+                    lower_right,
+                ),
+                transitions,
+            });
+        }
+    }
+    best.unwrap()
+}
+
+#[test]
+fn test_find_interesting() {
+    // 视图中央的实轴上有一个位于-1.7549附近的小Mandelbrot集，其余大部分是外部区域。
+    let bounds = (160, 120);
+    let upper_left = Complex {
+        re: -1.85,
+        im: 0.075,
+    };
+    let lower_right = Complex {
+        re: -1.65,
+        im: -0.075,
+    };
+    let options = RenderOptions {
+        limit: 500,
+        ..Default::default()
+    };
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(&mut counts, bounds, upper_left, lower_right, options);
+    let found = find_interesting(&counts, bounds, upper_left, lower_right);
+
+    // 选中的子区域与小Mandelbrot集的主体重叠。
+    let overlaps = |region: &Interesting| {
+        region.upper_left.re < -1.75
+            && region.lower_right.re > -1.78
+            && region.upper_left.im > -0.01
+            && region.lower_right.im < 0.01
+    };
+    assert!(overlaps(&found), "{}", found);
+
+    // 完全位于主心形线内部的视图没有任何变化，只能选中第一个子区域。
+    let flat_upper_left = Complex { re: -0.2, im: 0.2 };
+    let flat_lower_right = Complex { re: 0.1, im: -0.1 };
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(
+        &mut counts,
+        bounds,
+        flat_upper_left,
+        flat_lower_right,
+        options,
+    );
+    let flat = find_interesting(&counts, bounds, flat_upper_left, flat_lower_right);
+    assert_eq!(flat.transitions, 0);
+    assert_eq!(flat.upper_left, flat_upper_left);
+    assert!(found.transitions > 0);
+}
+
+/// 将轨迹`points`中位于视图内的每个点画为`shades`中最亮的像素。
+fn draw_orbit(
+    shades: &mut [f32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    points: &[Complex<f64>],
+) {
+    for &point in points {
+        if let Some((column, row)) = point_to_pixel(bounds, point, upper_left, lower_right) {
+            shades[row * bounds.0 + column] = 255.0;
+        }
+    }
+}
+
+#[test]
+fn test_draw_orbit() {
+    // 4x4的图像覆盖-2+2i到2-2i，每个像素边长为1。
+    let bounds = (4, 4);
+    let upper_left = Complex { re: -2.0, im: 2.0 };
+    let lower_right = Complex { re: 2.0, im: -2.0 };
+    let mut shades = vec![0.0; 16];
+
+    // i的轨迹在i、-1+i和-i之间循环，分别落在(2,1)、(1,1)和(2,3)。
+    let points = orbit_points(Complex { re: 0.0, im: 1.0 }, 255, Norm::L2);
+    draw_orbit(&mut shades, bounds, upper_left, lower_right, &points);
+    let lit: Vec<(usize, usize)> = (0..16)
+        .filter(|&i| shades[i] == 255.0)
+        .map(|i| (i % 4, i / 4))
+        .collect();
+    assert_eq!(lit, vec![(1, 1), (2, 1), (2, 3)]);
+
+    // 视图外的点被忽略。
+    assert_eq!(
+        point_to_pixel(
+            bounds,
+            Complex { re: 6.0, im: 0.0 },
+            upper_left,
+            lower_right
+        ),
+        None
+    );
+}
+
+/// 网格线的灰度与原灰度按一半混合时使用的灰度，在黑色和白色的区域上都能看清。
+const GRID_SHADE: f32 = 128.0;
+
+/// 3x5像素的点阵字体，只包含坐标标签和渲染统计需要的数字、负号、小数点、空格和几个小写字母。
+///
+/// 每个字符为5行，每行的低3位从高到低对应从左到右的像素。
+fn glyph(c: char) -> Option<[u8; 5]> {
+    let rows = match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ' ' => [0b000; 5],
+        'i' => [0b010, 0b000, 0b010, 0b010, 0b010],
+        'm' => [0b000, 0b110, 0b111, 0b101, 0b101],
+        's' => [0b000, 0b011, 0b110, 0b011, 0b110],
+        'x' => [0b000, 0b101, 0b010, 0b010, 0b101],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// 以`(column, row)`为左上角在`shades`中写出`text`，超出图像的部分被裁掉。
+///
+/// 每个字符3像素宽，字符之间空1像素。笔画在暗处为白色、在亮处为黑色，因此在任何背景上都能看清。
+fn draw_text(
+    shades: &mut [f32],
+    bounds: (usize, usize),
+    (column, row): (usize, usize),
+    text: &str,
+) {
+    for (index, rows) in text.chars().filter_map(glyph).enumerate() {
+        for (dy, bits) in rows.iter().enumerate() {
+            for dx in 0..3 {
+                let (x, y) = (column + index * 4 + dx, row + dy);
+                if bits & (0b100 >> dx) != 0 && x < bounds.0 && y < bounds.1 {
+                    let shade = &mut shades[y * bounds.0 + x];
+                    *shade = if *shade < GRID_SHADE { 255.0 } else { 0.0 };
+                }
+            }
+        }
+    }
+}
+
+/// 在`shades`的左下角写出渲染统计，如`1234ms i255 800x600`，依次为渲染耗时、迭代上限和分辨率。
+///
+/// 文字距图像的左边和下边各2像素，只改变文字笔画所在的像素。
+fn draw_stats(shades: &mut [f32], bounds: (usize, usize), elapsed: Duration, limit: usize) {
+    let text = format!(
+        "{}ms i{} {}x{}",
+        elapsed.as_millis(),
+        limit,
+        bounds.0,
+        bounds.1
+    );
+    draw_text(shades, bounds, (2, bounds.1.saturating_sub(7)), &text);
+}
+
+#[test]
+fn test_draw_stats() {
+    let bounds = (120, 40);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let mut clean = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut clean,
+        bounds,
+        upper_left,
+        lower_right,
+        RenderOptions::default(),
+        None,
+        RenderControl::default(),
+    );
+    let mut shades = clean.clone();
+    draw_stats(&mut shades, bounds, Duration::from_millis(1234), 255);
+
+    // “1234ms i255 120x40”共18个字符，占据左下角宽72、高5像素的区域。
+    let corner = |column: usize, row: usize| (2..74).contains(&column) && (33..38).contains(&row);
+    let mut changed = 0;
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let i = row * bounds.0 + column;
+            if corner(column, row) {
+                changed += (shades[i] != clean[i]) as usize;
+            } else {
+                assert_eq!(shades[i], clean[i]);
+            }
+        }
+    }
+    assert!(changed > 0);
+}
+
+/// 为跨度为`span`的视图选择网格的间距：1、2或5乘以10的整数次幂，使视图中大约有5条网格线。
+fn grid_step(span: f64) -> f64 {
+    let raw = span / 5.0;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let nice = match raw / magnitude {
+        r if r <= 1.0 => 1.0,
+        r if r <= 2.0 => 2.0,
+        r if r <= 5.0 => 5.0,
+        _ => 10.0,
+    };
+    nice * magnitude
+}
+
+/// 在`shades`上画出坐标网格：实部和虚部为网格间距整数倍的位置各画一条淡的直线，
+/// 并在图像上边标出各条竖线的实部、在左边标出各条横线的虚部。
+///
+/// 两个方向使用同一个间距，由视图的宽度决定。
+fn draw_grid(
+    shades: &mut [f32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) {
+    let step = grid_step(lower_right.re - upper_left.re);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let multiples = |from: f64, to: f64| {
+        let (from, to) = (from.min(to), from.max(to));
+        ((from / step).ceil() as i64..=(to / step).floor() as i64).map(|k| k as f64 * step)
+    };
+    let pixel =
+        |re: f64, im: f64| point_to_pixel(bounds, Complex { re, im }, upper_left, lower_right);
+    let blend = |shade: &mut f32| *shade = (*shade + GRID_SHADE) / 2.0;
+
+    let mut labels = Vec::new();
+    for re in multiples(upper_left.re, lower_right.re) {
+        if let Some((column, _)) = pixel(re, upper_left.im) {
+            for row in 0..bounds.1 {
+                blend(&mut shades[row * bounds.0 + column]);
+            }
+            labels.push(((column + 2, 2), format!("{:.*}", decimals, re)));
+        }
+    }
+    for im in multiples(lower_right.im, upper_left.im) {
+        if let Some((_, row)) = pixel(upper_left.re, im) {
+            for column in 0..bounds.0 {
+                blend(&mut shades[row * bounds.0 + column]);
+            }
+            // 太靠上的横线的标签会与上边的实部标签重叠，不再标出。
+            if row >= 6 {
+                labels.push(((2, row + 2), format!("{:.*}", decimals, im)));
+            }
+        }
+    }
+    for (position, text) in labels {
+        draw_text(shades, bounds, position, &text);
+    }
+}
+
+#[test]
+fn test_grid() {
+    assert_eq!(grid_step(4.0), 1.0);
+    assert_eq!(grid_step(3.0), 1.0);
+    assert_eq!(grid_step(1.5), 0.5);
+    assert!((grid_step(0.3) - 0.1).abs() < 1e-12);
+
+    // 100x100的图像覆盖-2+2i到2-2i，网格间距为1，每25个像素一条线。
+    let bounds = (100, 100);
+    let upper_left = Complex { re: -2.0, im: 2.0 };
+    let lower_right = Complex { re: 2.0, im: -2.0 };
+    let mut shades = vec![200.0; bounds.0 * bounds.1];
+    draw_grid(&mut shades, bounds, upper_left, lower_right);
+    let at = |column: usize, row: usize| shades[row * bounds.0 + column];
+    for line in [0, 25, 50, 75] {
+        assert_eq!(at(line, 60), 164.0);
+        assert_eq!(at(60, line), 164.0);
+    }
+    assert_eq!(at(60, 60), 200.0);
+    assert_eq!(at(30, 60), 200.0);
+    // 竖线x=-1右边的标签“-1”，负号是第3行的横线。
+    assert_eq!(at(27, 4), 0.0);
+    assert_eq!(at(29, 4), 0.0);
+    assert_eq!(at(27, 3), 200.0);
+
+    // 暗处的标签为白色。
+    let mut dark = vec![0.0; bounds.0 * bounds.1];
+    draw_grid(&mut dark, bounds, upper_left, lower_right);
+    assert_eq!(dark[4 * bounds.0 + 27], 255.0);
+}
+
+/// 计算`bounds`大小的灰度缓冲`shades`的感知哈希（dHash）。
+///
+/// 先把图像按块平均缩小为9x8的灰度，再逐行比较左右相邻的两格，右边更亮时对应的位为1，
+/// 共64位。浮点误差或个别像素的差异几乎不会改变哈希，两个哈希相差的位数可以衡量图像的差别。
+fn phash(shades: &[f32], bounds: (usize, usize)) -> u64 {
+    // 第`i`格（共`n`格）覆盖的像素范围，图像比格子还小时每格至少一个像素。
+    let cells = |i: usize, n: usize, len: usize| {
+        let start = i * len / n;
+        start..((i + 1) * len / n).max(start + 1)
+    };
+    let mut grid = [[0.0; 9]; 8];
+    for (row, line) in grid.iter_mut().enumerate() {
+        for (column, cell) in line.iter_mut().enumerate() {
+            let (columns, rows) = (cells(column, 9, bounds.0), cells(row, 8, bounds.1));
+            let mut sum = 0.0;
+            for y in rows.clone() {
+                for x in columns.clone() {
+                    sum += shades[y * bounds.0 + x] as f64;
+                }
+            }
+            *cell = sum / (columns.len() * rows.len()) as f64;
+        }
+    }
+
+    let mut hash = 0;
+    for line in &grid {
+        for pair in line.windows(2) {
+            hash = hash << 1 | (pair[0] < pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+#[test]
+fn test_phash() {
+    let bounds = (64, 48);
+    let render_view = |upper_left: Complex<f64>, lower_right: Complex<f64>| {
+        let mut shades = vec![0.0; bounds.0 * bounds.1];
+        render_parallel(
+            &mut shades,
+            bounds,
+            upper_left,
+            lower_right,
+            RenderOptions::default(),
+            None,
+            RenderControl::default(),
+        );
+        shades
+    };
+    let shades = render_view(Complex { re: -2.0, im: 1.2 }, Complex { re: 1.0, im: -1.2 });
+    let hash = phash(&shades, bounds);
+    assert_eq!(phash(&shades, bounds), hash);
+
+    // 只差一个像素的图像哈希相同或几乎相同。
+    let mut changed = shades.clone();
+    changed[20 * bounds.0 + 30] = 255.0;
+    assert!((phash(&changed, bounds) ^ hash).count_ones() <= 2);
+
+    // 明显不同的视图哈希相差很多位。
+    let other = render_view(
+        Complex { re: -0.8, im: 0.3 },
+        Complex { re: -0.6, im: 0.15 },
+    );
+    assert!((phash(&other, bounds) ^ hash).count_ones() >= 16);
+
+    // 比9x8还小的图像也能计算。
+    phash(&[1.0, 2.0, 3.0, 4.0], (2, 2));
+}
+
+/// 对`bounds`大小的逃逸次数缓冲`counts`做边缘检测，返回只含集合边界的灰度缓冲。
+///
+/// 每个像素按是否逃逸分为内部和外部两类，不受灰度变换和等值线等后处理的影响。
+/// 与上下左右任何一个相邻像素类别不同的像素为白色（255），其余为黑色（0），
+/// 分界线两侧的像素都会被标出。图像边缘的像素只与图像内的相邻像素比较。
+fn boundary(counts: &[Option<usize>], bounds: (usize, usize)) -> Vec<f32> {
+    let interior: Vec<bool> = counts.iter().map(Option::is_none).collect();
+    differs_from_neighbor(&interior, bounds)
+        .into_iter()
+        .map(|edge| if edge { 255.0 } else { 0.0 })
+        .collect()
+}
+
+/// 标出`bounds`大小的逃逸次数缓冲`counts`中的等值线像素：外部点的上下左右有逃逸次数
+/// 更大的外部点时，该点位于一条整数逃逸次数的分界上。
+///
+/// 只标出分界线较快逃逸的一侧，线宽为一个像素；与内部点相邻不算分界。
+fn contour_mask(counts: &[Option<usize>], bounds: (usize, usize)) -> Vec<bool> {
+    assert!(counts.len() == bounds.0 * bounds.1);
+    let (width, height) = bounds;
+    let count = |column: usize, row: usize| counts[row * width + column];
+    let mut mask = vec![false; counts.len()];
+    for row in 0..height {
+        for column in 0..width {
+            let Some(own) = count(column, row) else {
+                continue;
+            };
+            let larger = |neighbor: Option<usize>| neighbor.is_some_and(|n| n > own);
+            mask[row * width + column] = (column > 0 && larger(count(column - 1, row)))
+                || (column + 1 < width && larger(count(column + 1, row)))
+                || (row > 0 && larger(count(column, row - 1)))
+                || (row + 1 < height && larger(count(column, row + 1)));
+        }
+    }
+    mask
+}
+
+/// 把`contour_mask`标出的像素的灰度减半，在`shades`上叠加等值线，其余像素保持不变。
+///
+/// 外部点减半后至少为1，因此不会被当作内部点。
+fn draw_contours(shades: &mut [f32], counts: &[Option<usize>], bounds: (usize, usize)) {
+    for (shade, contour) in shades.iter_mut().zip(contour_mask(counts, bounds)) {
+        if contour {
+            *shade = (*shade * 0.5).max(1.0);
+        }
+    }
+}
+
+#[test]
+fn test_contours() {
+    // 每3列逃逸次数加1，最后两行为内部点。
+    let bounds = (9, 4);
+    let counts: Vec<Option<usize>> = (0..36).map(|i| (i / 9 < 2).then_some(i % 9 / 3)).collect();
+    let mask = contour_mask(&counts, bounds);
+    for (i, &contour) in mask.iter().enumerate() {
+        let (column, row) = (i % 9, i / 9);
+        assert_eq!(
+            contour,
+            row < 2 && (column == 2 || column == 5),
+            "{:?}",
+            (column, row)
+        );
+    }
+
+    // 叠加在实际渲染的平滑着色上：只有等值线像素变暗，其中没有内部点。
+    let bounds = (60, 45);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let options = RenderOptions {
+        smooth: true,
+        ..Default::default()
+    };
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render(&mut shades, None, bounds, upper_left, lower_right, options);
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(&mut counts, bounds, upper_left, lower_right, options);
+    let mut contoured = shades.clone();
+    draw_contours(&mut contoured, &counts, bounds);
+    let mask = contour_mask(&counts, bounds);
+    assert!(mask.iter().any(|&contour| contour));
+    for ((before, after), contour) in shades.iter().zip(&contoured).zip(mask) {
+        if contour {
+            assert!(*before != 0.0);
+            assert_eq!(*after, (before * 0.5).max(1.0));
+        } else {
+            assert_eq!(after, before);
+        }
+    }
+}
+
+/// 对`bounds`大小的缓冲`values`，标出与上下左右任何一个相邻像素的值不同的像素。
+fn differs_from_neighbor<T: PartialEq>(values: &[T], bounds: (usize, usize)) -> Vec<bool> {
+    differs_from_neighbor_by(values, bounds, |a, b| a != b)
+}
+
+/// 与`differs_from_neighbor`相同，但由`differs(相邻像素的值, 自身的值)`判断两个值是否算作不同。
+fn differs_from_neighbor_by<T>(
+    values: &[T],
+    bounds: (usize, usize),
+    differs: impl Fn(&T, &T) -> bool,
+) -> Vec<bool> {
+    assert!(values.len() == bounds.0 * bounds.1);
+    let (width, height) = bounds;
+    let value = |column: usize, row: usize| &values[row * width + column];
+    let mut edges = vec![false; values.len()];
+    for row in 0..height {
+        for column in 0..width {
+            let own = value(column, row);
+            edges[row * width + column] = (column > 0 && differs(value(column - 1, row), own))
+                || (column + 1 < width && differs(value(column + 1, row), own))
+                || (row > 0 && differs(value(column, row - 1), own))
+                || (row + 1 < height && differs(value(column, row + 1), own));
+        }
+    }
+    edges
+}
+
+#[test]
+fn test_boundary() {
+    // 7x7的图像中间有一个5x5的内部区域。
+    let bounds = (7, 7);
+    let counts: Vec<Option<usize>> = (0..49)
+        .map(|i| {
+            let (column, row) = (i % 7, i / 7);
+            if (1..6).contains(&column) && (1..6).contains(&row) {
+                None
+            } else {
+                Some(i)
+            }
+        })
+        .collect();
+    let edges = boundary(&counts, bounds);
+    // 中心的内部像素四周都是内部点，保持黑色。
+    assert_eq!(edges[3 * 7 + 3], 0.0);
+    // 紧挨着外部的内部像素和紧挨着内部的外部像素都被标出。
+    assert_eq!(edges[7 + 1], 255.0);
+    assert_eq!(edges[3 * 7], 255.0);
+    assert_eq!(edges[3 * 7 + 5], 255.0);
+    // 只与外部点相邻的角落，以及逃逸次数不同的外部点之间，都没有边界。
+    assert_eq!(edges[0], 0.0);
+    assert_eq!(edges[6], 0.0);
+
+    // 实际渲染的视图中，原点附近是深入内部的点，没有边界。
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(
+        &mut counts,
+        bounds,
+        upper_left,
+        lower_right,
+        RenderOptions::default(),
+    );
+    let edges = boundary(&counts, bounds);
+    let (column, row) = point_to_pixel(
+        bounds,
+        Complex { re: -0.1, im: 0.0 },
+        upper_left,
+        lower_right,
+    )
+    .unwrap();
+    assert_eq!(edges[row * bounds.0 + column], 0.0);
+    assert!(edges.contains(&255.0));
+}
+
+#[test]
+fn test_rotate() {
+    // 以-0.5为中心的正方形视图，旋转90度后的图像等于原图像顺时针转动90度。
+    let bounds = (60, 60);
+    let upper_left = Complex { re: -2.0, im: 1.5 };
+    let lower_right = Complex { re: 1.0, im: -1.5 };
+    let render_with = |options: RenderOptions| {
+        let mut shades = vec![0.0; bounds.0 * bounds.1];
+        render_parallel(
+            &mut shades,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            None,
+            RenderControl::default(),
+        );
+        shades
+    };
+    let plain = render_with(RenderOptions::default());
+    let rotated = render_with(RenderOptions {
+        transform: ViewTransform {
+            center: (upper_left + lower_right) / 2.0,
+            rotate: 90f64.to_radians(),
+            shear: 0.0,
+        },
+        ..RenderOptions::default()
+    });
+
+    // 像素取左上角的点，因此旋转后第`column`列对应原图的第`60 - column`行，第0列落在图像之外。
+    let n = bounds.0;
+    let mut mismatched = 0;
+    for row in 0..n {
+        for column in 1..n {
+            if rotated[row * n + column] != plain[(n - column) * n + row] {
+                mismatched += 1;
+            }
+        }
+    }
+    // 只有浮点误差使少数边界上的点逃逸次数不同。
+    assert!(mismatched * 100 < n * (n - 1));
+    assert_ne!(rotated, plain);
+}
+
+/// 像素`pixel`的`samples`x`samples`超采样灰度：在像素覆盖的矩形内均匀取点，求其灰度的平均。
+fn supersample(
+    bounds: (usize, usize),
+    pixel: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+    samples: usize,
+) -> f32 {
+    let corner = pixel_to_point(bounds, pixel, upper_left, lower_right);
+    let step = (
+        (lower_right.re - upper_left.re) / (bounds.0 * samples) as f64,
+        (upper_left.im - lower_right.im) / (bounds.1 * samples) as f64,
+    );
+    let mut sum = 0.0;
+    for j in 0..samples {
+        for i in 0..samples {
+            let point = Complex {
+                re: corner.re + (i as f64 + 0.5) * step.0,
+                im: corner.im - (j as f64 + 0.5) * step.1,
+            };
+            let point = options.transform.apply(point);
+            sum += shade(&render_orbit(point, options), options);
+        }
+    }
+    sum / (samples * samples) as f32
+}
+
+/// 对1x渲染的`shades`做自适应抗锯齿，返回重新采样的像素数。
+///
+/// 只有`targets`中标出的像素（即边界和色带附近的像素，见`aa_targets`）
+/// 才用`supersample`重新计算，其余区域保持不变。
+fn adaptive_antialias(
+    shades: &mut [f32],
+    targets: &[bool],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+    samples: usize,
+) -> usize {
+    let targets: Vec<usize> = targets
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &edge)| edge.then_some(i))
+        .collect();
+
+    let mut values = vec![0.0; targets.len()];
+    let per_thread = targets.len().div_ceil(MAX_THREADS).max(1);
+    crossbeam::scope(|spawner| {
+        for (values, targets) in values
+            .chunks_mut(per_thread)
+            .zip(targets.chunks(per_thread))
+        {
+            spawner.spawn(move |_| {
+                for (value, &i) in values.iter_mut().zip(targets) {
+                    let pixel = (i % bounds.0, i / bounds.0);
+                    *value = supersample(bounds, pixel, upper_left, lower_right, options, samples);
+                }
+            });
+        }
+    })
+    .unwrap();
+
+    for (&i, value) in targets.iter().zip(values) {
+        shades[i] = value;
+    }
+    targets.len()
+}
+
+/// 由逃逸次数`counts`标出`adaptive_antialias`要超采样的像素：与上下左右某个相邻像素的逃逸次数
+/// 相差超过`threshold`，或者一个是内部点而另一个不是。
+///
+/// `threshold`为0时标出所有与相邻像素不同的像素，越大跳过的平缓色带越多。
+fn aa_targets(counts: &[Option<usize>], bounds: (usize, usize), threshold: usize) -> Vec<bool> {
+    differs_from_neighbor_by(counts, bounds, |a, b| match (a, b) {
+        (Some(a), Some(b)) => a.abs_diff(*b) > threshold,
+        _ => a != b,
+    })
+}
+
+#[test]
+fn test_aa_threshold() {
+    let bounds = (48, 36);
+    let upper_left = Complex { re: -0.8, im: 0.3 };
+    let lower_right = Complex { re: -0.6, im: 0.15 };
+    let options = RenderOptions::default();
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(&mut counts, bounds, upper_left, lower_right, options);
+    let targets = |threshold| {
+        aa_targets(&counts, bounds, threshold)
+            .iter()
+            .filter(|&&edge| edge)
+            .count()
+    };
+
+    // 阈值为0时与所有和相邻像素不同的像素一致。
+    let edges = differs_from_neighbor(&counts, bounds);
+    assert_eq!(aa_targets(&counts, bounds, 0), edges);
+    // 阈值越高，超采样的像素越少，但内部与外部的分界总会超采样。
+    let counted: Vec<usize> = [0, 1, 4, 16].into_iter().map(targets).collect();
+    assert!(counted.windows(2).all(|pair| pair[1] <= pair[0]));
+    assert!(counted[3] < counted[0]);
+    let interior: Vec<bool> = counts.iter().map(Option::is_none).collect();
+    let boundary = differs_from_neighbor(&interior, bounds);
+    let high = aa_targets(&counts, bounds, usize::MAX);
+    assert_eq!(high, boundary);
+
+    // 高阈值时adaptive_antialias重新采样的像素数与之相同。
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    let resampled = adaptive_antialias(
+        &mut shades,
+        &aa_targets(&counts, bounds, 16),
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        2,
+    );
+    assert_eq!(resampled, counted[3]);
+}
+
+#[test]
+fn test_adaptive_antialias() {
+    let bounds = (48, 36);
+    let upper_left = Complex { re: -0.8, im: 0.3 };
+    let lower_right = Complex { re: -0.6, im: 0.15 };
+    let options = RenderOptions::default();
+    let samples = 3;
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut shades,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        None,
+        RenderControl::default(),
+    );
+    let plain = shades.clone();
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(&mut counts, bounds, upper_left, lower_right, options);
+    let resampled = adaptive_antialias(
+        &mut shades,
+        &aa_targets(&counts, bounds, 0),
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        samples,
+    );
+    assert!(resampled > 0 && resampled < shades.len());
+
+    let edges = differs_from_neighbor(&counts, bounds);
+    assert_eq!(edges.iter().filter(|&&edge| edge).count(), resampled);
+
+    // 独立的参照：以3倍的分辨率完整渲染，每个像素取对应的3x3个子像素的平均。
+    // 整个视图平移半个子像素，使每个子像素取其中心的点，与`supersample`的取点相同。
+    let fine_bounds = (bounds.0 * samples, bounds.1 * samples);
+    let offset = Complex {
+        re: (lower_right.re - upper_left.re) / fine_bounds.0 as f64 / 2.0,
+        im: -(upper_left.im - lower_right.im) / fine_bounds.1 as f64 / 2.0,
+    };
+    let mut fine = vec![0.0; fine_bounds.0 * fine_bounds.1];
+    render_parallel(
+        &mut fine,
+        fine_bounds,
+        upper_left + offset,
+        lower_right + offset,
+        options,
+        None,
+        RenderControl::default(),
+    );
+    let reference = |i: usize| {
+        let (column, row) = (i % bounds.0 * samples, i / bounds.0 * samples);
+        let mut sum = 0.0;
+        for j in 0..samples {
+            for k in 0..samples {
+                sum += fine[(row + j) * fine_bounds.0 + column + k];
+            }
+        }
+        sum / (samples * samples) as f32
+    };
+
+    let mut difference = 0.0;
+    for i in 0..shades.len() {
+        let full = reference(i);
+        if edges[i] {
+            // 边界像素与完整的超采样一致。
+            assert!((shades[i] - full).abs() < 1e-3);
+        } else {
+            // 平坦区域没有被改动。
+            assert_eq!(shades[i], plain[i]);
+        }
+        difference += (shades[i] - full).abs();
+    }
+    // 整幅图像与完整的超采样平均只差一小部分灰度级。
+    assert!(difference / (shades.len() as f32) < 0.1);
+}
+
+/// 在多个线程中计算每个像素的原始逃逸次数，集合内部的点为`None`。
+///
+/// 每个像素对应的点与`render_parallel`逐行渲染时完全相同，
+/// 因此用`count_shade`为结果着色可以重现非平滑模式下的灰度。
+fn render_counts(
+    counts: &mut [Option<usize>],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+) {
+    map_points_parallel(
+        counts,
+        bounds,
+        upper_left,
+        lower_right,
+        options.chunk_rows,
+        |point| fast_orbit(options.transform.apply(point), options.limit, options.norm).escape,
+    );
+}
+
+/// 将`bounds`大小的逃逸次数`counts`写入`--heatmap`指定的文件`path`，格式由扩展名决定。
+fn write_heatmap(
+    path: &str,
+    counts: &[Option<usize>],
+    bounds: (usize, usize),
+) -> Result<(), std::io::Error> {
+    let data = if path.ends_with(".npy") {
+        heatmap::encode_npy(bounds, counts)
+    } else {
+        heatmap::encode(bounds, counts)
+    };
+    std::fs::write(path, data)
+}
+
+/// 把逃逸次数`counts`的分布以CSV写入`--histogram`指定的文件`path`。
+fn write_histogram(path: &str, counts: &[Option<usize>]) -> Result<(), std::io::Error> {
+    std::fs::write(path, heatmap::histogram_csv(counts))
+}
+
+#[test]
+fn test_histogram() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-hist-{}.csv", std::process::id()));
+    let path = path.to_str().unwrap();
+    let args: Vec<String> = ["--histogram", path, "out.png", "40x30", "-2,1.2", "1,-1.2"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let args = parse_args(&args).unwrap();
+    let mut counts = vec![None; 40 * 30];
+    render_counts(
+        &mut counts,
+        args.bounds,
+        args.upper_left,
+        args.lower_right,
+        args.options,
+    );
+    write_histogram(path, &counts).unwrap();
+    let csv = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("escape_count,pixels"));
+    let rows: Vec<(&str, usize)> = lines
+        .map(|line| {
+            let (bucket, pixels) = line.split_once(',').unwrap();
+            (bucket, pixels.parse().unwrap())
+        })
+        .collect();
+    // 各行的像素数加起来是整幅图像。
+    assert_eq!(
+        rows.iter().map(|(_, pixels)| pixels).sum::<usize>(),
+        40 * 30
+    );
+    // 内部点单独在最后一行，与逃逸次数为None的像素数相同。
+    let interior = counts.iter().filter(|count| count.is_none()).count();
+    assert!(interior > 0);
+    assert_eq!(rows.last(), Some(&("interior", interior)));
+    assert!(rows[..rows.len() - 1]
+        .iter()
+        .all(|(bucket, _)| bucket.parse::<usize>().is_ok()));
+}
+
+#[test]
+fn test_heatmap() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    let options = RenderOptions::default();
+    render_parallel(
+        &mut shades,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        None,
+        RenderControl::default(),
+    );
+
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(&mut counts, bounds, upper_left, lower_right, options);
+    let decoded = heatmap::decode(&heatmap::encode(bounds, &counts)).unwrap();
+    assert_eq!(decoded.bounds, bounds);
+    let recolored: Vec<f32> = decoded
+        .counts
+        .into_iter()
+        .map(|escape| count_shade(escape, options.limit))
+        .collect();
+    assert_eq!(recolored, shades);
+}
+
+/// 由`bounds`大小的轨道`orbits`计算每个像素的平滑逃逸次数（势），写入`--exr`指定的文件`path`。
+///
+/// 集合内部的点为`potential::INTERIOR`。
+fn write_potential(path: &str, bounds: (usize, usize), orbits: &[Orbit]) -> exr::error::UnitResult {
+    let values: Vec<f32> = orbits
+        .iter()
+        .map(|orbit| {
+            orbit.escape.map_or(potential::INTERIOR, |escape| {
+                smooth_count(escape, orbit.z) as f32
+            })
+        })
+        .collect();
+    potential::write_exr(path, bounds, &values)
+}
 
-    if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
-        eprintln!(
-            "Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
-            args[0]
-        );
-        std::process::exit(1);
+#[test]
+fn test_potential() {
+    let path = std::env::temp_dir().join(format!("mandelbrot-exr-{}.exr", std::process::id()));
+    let path = path.to_str().unwrap();
+    let args: Vec<String> = ["out.png", "8x6", "-2,1.5", "1,-1.5", "--exr", path]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let args = parse_args(&args).unwrap();
+    let mut shades = vec![0.0; 8 * 6];
+    let mut orbits = vec![Orbit::default(); 8 * 6];
+    render_parallel_with(
+        (&mut shades, Some(&mut orbits)),
+        args.bounds,
+        args.upper_left,
+        args.lower_right,
+        args.options,
+        None,
+        RenderControl::default(),
+    );
+    write_potential(path, args.bounds, &orbits).unwrap();
+    let (bounds, values) = potential::read_exr(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(bounds, (8, 6));
+
+    // 第1行第6列的点会逃逸，读回的值与直接计算的平滑逃逸次数一致。
+    let point = pixel_to_point(bounds, (6, 1), args.upper_left, args.lower_right);
+    let expected = mandelbrot::smooth_escape_time(point, 255).unwrap();
+    assert!((values[bounds.0 + 6] as f64 - expected).abs() < 1e-4);
+    // 图像中心的原点位于集合内部。
+    assert_eq!(values[3 * bounds.0 + 5], potential::INTERIOR);
+}
+
+/// 将Mandelbrot集的矩形渲染为像素缓冲。
+///
+/// `bounds`参数给定了`pixels`缓冲的宽和高，缓冲中按字节存储了相素灰度。
+/// `upper_left`和`lower_right`指定与像素缓冲左上角和右下角对应的复数平面。
+///
+/// 给定`orbits`时同时把每个像素的轨道写入其中。返回本次渲染的迭代次数统计。
+fn render(
+    pixels: &mut [f32],
+    mut orbits: Option<&mut [Orbit]>,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    options: RenderOptions,
+) -> RenderStats {
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let mut stats = RenderStats::default();
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            let orbit = render_orbit(options.transform.apply(point), options);
+            stats.iterations += orbit.iterations as u64;
+            stats.naive_iterations += orbit.naive_iterations(options.limit) as u64;
+            pixels[row * bounds.0 + column] = shade(&orbit, options);
+            if let Some(orbits) = orbits.as_deref_mut() {
+                orbits[row * bounds.0 + column] = orbit;
+            }
+        }
     }
+    stats
+}
 
-    let bounds = parse_pair(&args[2], 'x').expect("解析图像尺寸出错");
-    let upper_left = parse_complex(&args[3]).expect("解析左上角点出错");
-    let lower_right = parse_complex(&args[4]).expect("解析右下角点出错");
+/// 渲染过程中的迭代次数统计，用于衡量心形线测试和周期检测等优化的效果。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct RenderStats {
+    /// 实际进行的迭代次数。
+    iterations: u64,
+    /// 不做任何优化时需要的迭代次数。
+    naive_iterations: u64,
+}
 
-    let mut pixels = vec![0; bounds.0 * bounds.1];
+impl RenderStats {
+    /// 优化节省的迭代次数。
+    fn saved(&self) -> u64 {
+        self.naive_iterations - self.iterations
+    }
+}
 
-    // render(&mut pixels, bounds, upper_left, lower_right);
-    let threads = 8;
-    let rows_per_band = bounds.1 / threads + 1;
+impl std::ops::AddAssign for RenderStats {
+    fn add_assign(&mut self, other: RenderStats) {
+        self.iterations += other.iterations;
+        self.naive_iterations += other.naive_iterations;
+    }
+}
 
-    {
-        let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|spawner| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
-                let band_lower_right =
-                    pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
-
-                spawner.spawn(move |_| {
-                    render(band, band_bounds, band_upper_left, band_lower_right);
-                });
-            }
-        })
-        .unwrap();
+impl std::fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let percent = if self.naive_iterations == 0 {
+            0.0
+        } else {
+            self.saved() as f64 * 100.0 / self.naive_iterations as f64
+        };
+        write!(
+            f,
+            "实际迭代{}次，不做优化需要{}次，节省了{}次（{:.1}%）",
+            self.iterations,
+            self.naive_iterations,
+            self.saved(),
+            percent
+        )
     }
+}
+
+#[test]
+fn test_render_stats() {
+    // 两个像素分别是原点（主心形线内）和2（第1次迭代后就逃逸）。
+    let mut pixels = [0.0; 2];
+    let stats = render(
+        &mut pixels,
+        None,
+        (2, 1),
+        Complex { re: 0.0, im: 0.0 },
+        Complex { re: 4.0, im: 0.0 },
+        RenderOptions::default(),
+    );
+    assert_eq!(
+        stats,
+        RenderStats {
+            iterations: 2,
+            naive_iterations: 255 + 2,
+        }
+    );
+    assert_eq!(stats.saved(), 255);
 
-    write_image(&args[1], &pixels, bounds).expect("写入PNG文件出错");
+    // 覆盖整个集合的视图中有大量内部点，节省的迭代次数可观。
+    let bounds = (60, 40);
+    let mut pixels = vec![0.0; bounds.0 * bounds.1];
+    let stats = render_parallel(
+        &mut pixels,
+        bounds,
+        Complex { re: -2.0, im: 1.2 },
+        Complex { re: 1.0, im: -1.2 },
+        RenderOptions::default(),
+        None,
+        RenderControl::default(),
+    );
+    assert!(stats.saved() > 0);
+    assert!(stats.saved() * 4 > stats.naive_iterations);
 }
 
-/// 尝试决定`c`是否位于Mandelbrot集中，最多进行`limit`次来作出决策。
-///
-/// 如果`c`不是成员，返回`Some(i)`，其中`i`为`c`离开以原点为中心半径为2区域所需的次数。
-/// 如果`c`是成员（更确切的说是如果迭代了limit次后还无法证明`c`不是其成员），返回`None`。
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+/// 渲染过程中由外部控制或观察的状态，不影响输出的像素。
+#[derive(Clone, Copy, Default)]
+struct RenderControl<'a> {
+    /// 按下Ctrl-C时被置位的取消标志。
+    cancel: Option<&'a AtomicBool>,
+    /// `--progress`显示的进度。
+    progress: Option<&'a Progress>,
+}
+
+/// 两次刷新进度之间的最短间隔。
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 按已完成的行数估计的渲染进度，在标准错误的同一行上刷新显示。
+struct Progress {
+    total_rows: usize,
+    /// 开始时已经完成的行数，如从检查点恢复的行。这些行不是本次渲染的，不计入速度。
+    initial_rows: usize,
+    done_rows: AtomicUsize,
+    start: Instant,
+    /// 上次显示的时间。
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl Progress {
+    fn new(total_rows: usize, initial_rows: usize, start: Instant) -> Progress {
+        Progress {
+            total_rows,
+            initial_rows,
+            done_rows: AtomicUsize::new(initial_rows),
+            start,
+            last_report: Mutex::new(None),
         }
-        z = z * z + c;
     }
 
-    None
+    /// 记录完成了一行，距上次显示足够久或全部完成时刷新显示。
+    fn row_done(&self) {
+        let done = self.done_rows.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let mut last = self.last_report.lock().unwrap();
+        if done == self.total_rows || last.is_none_or(|last| now - last >= PROGRESS_INTERVAL) {
+            *last = Some(now);
+            eprint!("\r{}", self.status(done, now));
+        }
+    }
+
+    /// 在时刻`now`完成了`done`行时显示的进度。
+    fn status(&self, done: usize, now: Instant) -> String {
+        let percent = done as f64 * 100.0 / self.total_rows.max(1) as f64;
+        let remaining = estimate_remaining(
+            now - self.start,
+            done - self.initial_rows,
+            self.total_rows - done,
+        );
+        match remaining {
+            Some(remaining) => format!(
+                "渲染进度: {:.1}%，剩余约{}    ",
+                percent,
+                format_duration(remaining)
+            ),
+            None => format!("渲染进度: {:.1}%    ", percent),
+        }
+    }
 }
 
-/// 将字符串`s`解析为坐标对，如`"400x600"`或`"1.0,0.5"`。
+/// 按用时`elapsed`内完成`rows_done`行的平均速度，估计剩下的`rows_left`行还需要的时间。
 ///
-/// 具体来说，`s`的形式就为<left><sep><right>，其中<sep>是由`separator`所给定的字符，
-/// <left>和<right> 均是字符串，可由`T::from_str`解析。`separator`必须是ASCII字符。
-///
-/// 如果`s`格式正确，返回`Some<(x, y)>`。如解析错误，返回`None`。
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-            (Ok(l), Ok(r)) => Some((l, r)),
-            _ => None,
+/// 按从开始到现在的平均速度估计，完成的行越多，个别耗时的行对估计的影响越小。
+/// 还没有完成任何一行时无法估计，返回`None`。
+fn estimate_remaining(elapsed: Duration, rows_done: usize, rows_left: usize) -> Option<Duration> {
+    if rows_done == 0 {
+        return None;
+    }
+    Some(elapsed.mul_f64(rows_left as f64 / rows_done as f64))
+}
+
+/// 将时长显示为`42秒`、`3分05秒`或`1小时20分`。
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64().ceil() as u64;
+    match seconds {
+        0..=59 => format!("{}秒", seconds),
+        60..=3599 => format!("{}分{:02}秒", seconds / 60, seconds % 60),
+        _ => format!("{}小时{:02}分", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+#[test]
+fn test_progress() {
+    let seconds = Duration::from_secs;
+    assert_eq!(estimate_remaining(seconds(10), 0, 100), None);
+    // 10秒完成了25行，每秒2.5行，剩下的75行还需要30秒。
+    assert_eq!(estimate_remaining(seconds(10), 25, 75), Some(seconds(30)));
+    assert_eq!(estimate_remaining(seconds(40), 100, 0), Some(seconds(0)));
+
+    // 用固定的起始时刻模拟时钟：从检查点恢复了20行，10秒内又完成了25行。
+    let start = Instant::now();
+    let progress = Progress::new(100, 20, start);
+    assert_eq!(progress.status(20, start), "渲染进度: 20.0%    ");
+    assert_eq!(
+        progress.status(45, start + seconds(10)),
+        "渲染进度: 45.0%，剩余约22秒    "
+    );
+    assert_eq!(
+        progress.status(45, start + seconds(100)),
+        "渲染进度: 45.0%，剩余约3分40秒    "
+    );
+
+    assert_eq!(format_duration(Duration::from_millis(1500)), "2秒");
+    assert_eq!(format_duration(seconds(3 * 3600 + 65)), "3小时01分");
+
+    // 渲染时每完成一行计数一次。
+    let bounds = (8, 6);
+    let progress = Progress::new(bounds.1, 0, start);
+    let mut pixels = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut pixels,
+        bounds,
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 1.0, im: -1.0 },
+        RenderOptions::default(),
+        None,
+        RenderControl {
+            progress: Some(&progress),
+            ..Default::default()
         },
+    );
+    assert_eq!(progress.done_rows.load(Ordering::Relaxed), bounds.1);
+}
+
+/// 影响每个像素如何着色，以及如何划分并行任务的渲染选项。
+#[derive(Clone, Copy, Debug)]
+struct RenderOptions {
+    /// 使用`smooth_escape_time`的平滑逃逸次数代替整数逃逸次数。
+    smooth: bool,
+    /// 判断逃逸时使用的范数。
+    norm: Norm,
+    /// 并行渲染时每个条带的行数，`None`表示每个线程一个条带。只影响调度，不改变输出。
+    chunk_rows: Option<usize>,
+    /// 迭代次数的上限，默认为255。
+    limit: usize,
+    /// 对每个像素对应的点做的旋转和错切，默认不做变换。
+    transform: ViewTransform,
+    /// 按最后一次迭代的`|z|`为内部点着色，而不是一律为黑色。
+    interior_shade: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            smooth: false,
+            norm: Norm::default(),
+            chunk_rows: None,
+            limit: 255,
+            transform: ViewTransform::default(),
+            interior_shade: false,
+        }
+    }
+}
+
+/// 按`options`计算渲染`point`所用的轨道，`point`已经做过视图变换。
+///
+/// 通常使用`fast_orbit`；内部着色需要内部点迭代到上限后真正的`z`，此时不做提前判定。
+fn render_orbit(point: Complex<f64>, options: RenderOptions) -> Orbit {
+    if options.interior_shade {
+        orbit_with_norm(point, options.limit, options.norm)
+    } else {
+        fast_orbit(point, options.limit, options.norm)
+    }
+}
+
+/// 根据点的轨道`orbit`计算其灰度，范围为0.0到255.0。
+///
+/// 集合内部的点灰度为0，外部点的灰度为`255 - 逃逸次数`，且至少为1，
+/// 因此灰度为0的像素一定是内部点。迭代上限不是255时逃逸次数先按比例缩放到0到255。
+/// `options.interior_shade`时内部点改用`magnitude_shade`着色。
+fn shade(orbit: &Orbit, options: RenderOptions) -> f32 {
+    match orbit.escape {
+        Some(count) if options.smooth => {
+            let scale = 255.0 / options.limit as f32;
+            (255.0 - smooth_count(count, orbit.z) as f32 * scale).clamp(1.0, 255.0)
+        }
+        None if options.interior_shade => magnitude_shade(orbit.z.norm()),
+        escape => count_shade(escape, options.limit),
     }
 }
 
+/// 内部着色的最大灰度。内部点只带上淡淡的纹理，整体仍然是暗色。
+const INTERIOR_SHADE_MAX: f32 = 40.0;
+
+/// 内部点最后一次迭代的模`magnitude`对应的灰度：原点处为0，半径2处为`INTERIOR_SHADE_MAX`。
+fn magnitude_shade(magnitude: f64) -> f32 {
+    (magnitude / 2.0).clamp(0.0, 1.0) as f32 * INTERIOR_SHADE_MAX
+}
+
 #[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("", ','), None);
-    assert_eq!(parse_pair::<i32>("10,", ','), None);
-    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x", 'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+fn test_shade_interior() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let render_with = |options: RenderOptions| {
+        let mut shades = vec![0.0; bounds.0 * bounds.1];
+        render_parallel(
+            &mut shades,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            None,
+            RenderControl::default(),
+        );
+        shades
+    };
+    let options = RenderOptions {
+        interior_shade: true,
+        ..RenderOptions::default()
+    };
+    let plain = render_with(RenderOptions::default());
+    let shaded = render_with(options);
+
+    let mut interior = Vec::new();
+    for (i, (&before, &after)) in plain.iter().zip(&shaded).enumerate() {
+        if before != 0.0 {
+            // 外部点的灰度不变。
+            assert_eq!(after, before);
+            continue;
+        }
+        let pixel = (i % bounds.0, i / bounds.0);
+        let point = pixel_to_point(bounds, pixel, upper_left, lower_right);
+        let magnitude = orbit_with_norm(point, options.limit, options.norm).z.norm();
+        // 逐行渲染时像素对应的点与整幅图像计算的可能差一点舍入误差。
+        assert!((after - magnitude_shade(magnitude)).abs() < 1e-3);
+        interior.push((magnitude, after));
+    }
+    assert!(interior.len() > 50);
+    // 内部点不再是一片黑色，灰度各不相同，随|z|增大而变亮，但都比较暗。
+    assert!(interior
+        .iter()
+        .all(|&(_, shade)| shade > 0.0 && shade <= INTERIOR_SHADE_MAX));
+    let mut distinct: Vec<u32> = interior.iter().map(|(_, shade)| shade.to_bits()).collect();
+    distinct.sort();
+    distinct.dedup();
+    assert!(distinct.len() > interior.len() / 2);
+    interior.sort_by(|a, b| a.0.total_cmp(&b.0));
+    assert!(interior
+        .windows(2)
+        .all(|pair| pair[0].1 <= pair[1].1 + 1e-3));
+    assert_eq!(magnitude_shade(0.0), 0.0);
+    assert_eq!(magnitude_shade(5.0), INTERIOR_SHADE_MAX);
 }
 
-/// 解析一对以逗号分隔的浮点数值为复数。
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None,
+/// 不使用平滑着色时由逃逸次数`escape`计算的灰度，规则与`shade`相同。
+fn count_shade(escape: Option<usize>, limit: usize) -> f32 {
+    match escape {
+        None => 0.0,
+        // 上限为255时比例恰好为1，与不缩放时完全相同。
+        Some(count) => (255.0 - count as f32 * (255.0 / limit as f32)).max(1.0),
     }
 }
 
 #[test]
-fn test_parse_complex() {
-    assert_eq!(
-        parse_complex("1.25,-0.0625"),
-        Some(Complex {
-            re: 1.25,
-            im: -0.0625
-        })
-    );
-    assert_eq!(parse_complex(",-0.0625"), None);
+fn test_count_shade() {
+    assert_eq!(count_shade(None, 1000), 0.0);
+    assert_eq!(count_shade(Some(0), 255), 255.0);
+    assert_eq!(count_shade(Some(254), 255), 1.0);
+    assert_eq!(count_shade(Some(500), 1000), 127.5);
+    assert_eq!(count_shade(Some(999), 1000), 1.0);
 }
 
-/// 给定输出图像中像素的行列，返回复数平面中对应的点。
+/// 用传递函数`transfer`重新计算外部像素的灰度。
 ///
-/// `bounds`按像素给定图像的宽高。
-/// `pixel`表示图像中具体像素的(column, row)对。
-/// `upper_left`和`lower_right`参数指向指定图像区域的复数平面的点。
-fn pixel_to_point(
-    bounds: (usize, usize),
-    pixel: (usize, usize),
+/// 逃逸次数由灰度按`shade`的规则反推，平滑着色时带小数。表达式的值截断到0到1后乘以255，
+/// 且至少为1，因此内部点仍然是唯一灰度为0的像素，调色板也会按新的灰度取色。
+fn apply_transfer(shades: &mut [f32], transfer: &Transfer, limit: usize) {
+    let limit = limit as f64;
+    for shade in shades.iter_mut().filter(|shade| **shade != 0.0) {
+        let iter = (255.0 - *shade as f64) * limit / 255.0;
+        let value = transfer.eval(iter, limit);
+        // 表达式的值为NaN时按0处理。
+        let value = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(0.0, 1.0)
+        };
+        *shade = (value * 255.0).max(1.0) as f32;
+    }
+}
+
+#[test]
+fn test_apply_transfer() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let options = RenderOptions {
+        limit: 100,
+        ..RenderOptions::default()
+    };
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render_parallel(
+        &mut shades,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        None,
+        RenderControl::default(),
+    );
+
+    // 线性表达式重现默认的灰度。
+    let mut linear = shades.clone();
+    apply_transfer(
+        &mut linear,
+        &"1 - iter/limit".parse().unwrap(),
+        options.limit,
+    );
+    for (a, b) in linear.iter().zip(&shades) {
+        assert!((a - b).abs() < 1e-3);
+    }
+
+    // 常量表达式使所有外部像素的灰度相同，内部点保持为0。
+    let mut flat = shades.clone();
+    apply_transfer(&mut flat, &"0.5".parse().unwrap(), options.limit);
+    for (a, b) in flat.iter().zip(&shades) {
+        assert_eq!(*a, if *b == 0.0 { 0.0 } else { 127.5 });
+    }
+    assert!(flat.contains(&0.0));
+}
+
+/// 用`color::contrast`以`pivot`为中心重新分配外部像素的灰度，内部点保持为0，
+/// 外部点至少为1。
+fn apply_contrast(shades: &mut [f32], pivot: f64) {
+    for shade in shades.iter_mut().filter(|shade| **shade != 0.0) {
+        let value = color::contrast(*shade as f64 / 255.0, pivot);
+        *shade = (value * 255.0).max(1.0) as f32;
+    }
+}
+
+/// `--julia-grid`中每格Julia集的视图高度的一半，宽度按格子的宽高比决定。
+const JULIA_HALF_HEIGHT: f64 = 1.5;
+
+/// `--julia-grid`各格使用的常数：把视图均分为`grid`（行数，列数）个小矩形，取各自中心的点，
+/// 按先行后列的顺序返回。
+fn julia_grid_constants(
+    grid: (usize, usize),
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
-) -> Complex<f64> {
+) -> Vec<Complex<f64>> {
+    let (rows, columns) = grid;
     let (width, height) = (
         lower_right.re - upper_left.re,
         upper_left.im - lower_right.im,
     );
-    Complex {
-        re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64,
-        // 为什么在这里减？pixel.1越往下越大，但虚部越往上越大。
+    (0..rows * columns)
+        .map(|i| Complex {
+            re: upper_left.re
+                + (i % columns) as f64 * width / columns as f64
+                + width / columns as f64 / 2.0,
+            im: upper_left.im
+                - (i / columns) as f64 * height / rows as f64
+                - height / rows as f64 / 2.0,
+        })
+        .collect()
+}
+
+/// 将`bounds`大小的`shades`分为`grid`（行数，列数）个格子，每格用`julia_escape_time`
+/// 渲染以`julia_grid_constants`中对应的点为常数的Julia集，灰度规则与非平滑模式相同。
+///
+/// 每格的视图以原点为中心，高为`2 * JULIA_HALF_HEIGHT`。图像的尺寸不能被行列数整除时，
+/// 右边和下边剩余的像素保持不变。
+fn render_julia_grid(
+    shades: &mut [f32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    grid: (usize, usize),
+    options: RenderOptions,
+) {
+    let (rows, columns) = grid;
+    let cell = (bounds.0 / columns, bounds.1 / rows);
+    let half_width = JULIA_HALF_HEIGHT * cell.0 as f64 / cell.1 as f64;
+    let cell_upper_left = Complex {
+        re: -half_width,
+        im: JULIA_HALF_HEIGHT,
+    };
+    let cell_lower_right = Complex {
+        re: half_width,
+        im: -JULIA_HALF_HEIGHT,
+    };
+    let constants = julia_grid_constants(grid, upper_left, lower_right);
+    for (i, &c) in constants.iter().enumerate() {
+        let mut pixels = vec![0.0; cell.0 * cell.1];
+        map_points_parallel(
+            &mut pixels,
+            cell,
+            cell_upper_left,
+            cell_lower_right,
+            options.chunk_rows,
+            |z| {
+                count_shade(
+                    julia_escape_time(z, c, options.limit, options.norm),
+                    options.limit,
+                )
+            },
+        );
+        let (left, top) = (i % columns * cell.0, i / columns * cell.1);
+        for (row, line) in pixels.chunks(cell.0).enumerate() {
+            let start = (top + row) * bounds.0 + left;
+            shades[start..start + cell.0].copy_from_slice(line);
+        }
     }
 }
 
 #[test]
-fn test_pixel_to_point() {
+fn test_julia_grid() {
+    let bounds = (40, 40);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let constants = julia_grid_constants((2, 2), upper_left, lower_right);
     assert_eq!(
-        pixel_to_point(
-            (100, 200),
-            (25, 175),
-            Complex { re: -1.0, im: 1.0 },
-            Complex { re: 1.0, im: -1.0 }
-        ),
-        Complex { re: -0.5, im: 0.75 }
+        constants,
+        [
+            Complex { re: -1.25, im: 0.6 },
+            Complex { re: 0.25, im: 0.6 },
+            Complex {
+                re: -1.25,
+                im: -0.6
+            },
+            Complex { re: 0.25, im: -0.6 },
+        ]
+    );
+
+    let options = RenderOptions::default();
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render_julia_grid(
+        &mut shades,
+        bounds,
+        upper_left,
+        lower_right,
+        (2, 2),
+        options,
     );
+    let cells: Vec<Vec<f32>> = (0..4)
+        .map(|i| {
+            let (left, top) = (i % 2 * 20, i / 2 * 20);
+            (top..top + 20)
+                .flat_map(|row| shades[row * 40 + left..row * 40 + left + 20].to_vec())
+                .collect()
+        })
+        .collect();
+    // 每格都是以对应常数单独渲染的Julia集，四格互不相同。
+    for (cell, &c) in cells.iter().zip(&constants) {
+        let expected: Vec<f32> = (0..20 * 20)
+            .map(|i| {
+                let z = pixel_to_point(
+                    (20, 20),
+                    (i % 20, i / 20),
+                    Complex { re: -1.5, im: 1.5 },
+                    Complex { re: 1.5, im: -1.5 },
+                );
+                count_shade(julia_escape_time(z, c, 255, Norm::L2), 255)
+            })
+            .collect();
+        assert_eq!(*cell, expected);
+    }
+    for i in 0..4 {
+        for j in i + 1..4 {
+            assert_ne!(cells[i], cells[j]);
+        }
+    }
+
+    // 网格不经过渲染后的处理，相应的选项都被拒绝。
+    let to_args = |flags: &[&str]| {
+        let mut args = vec!["--julia-grid", "2x2"];
+        args.extend(flags);
+        args.extend(["j.png", "40x40", "-2,1.2", "1,-1.2"]);
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    };
+    assert!(to_args(&[]).is_ok());
+    for flags in [
+        &["--boundary"][..],
+        &["--transfer", "iter"],
+        &["--contrast-pivot", "0.5"],
+        &["--phash"],
+        &["--overlay-stats"],
+        &["--split-channels"],
+        &["--smooth"],
+        &["--progress"],
+        &["--timing-log", "t.log"],
+        &["--report-savings"],
+    ] {
+        assert!(to_args(flags).is_err(), "{:?}", flags);
+    }
 }
 
-/// 将Mandelbrot集的矩形渲染为像素缓冲。
+/// 将浮点灰度量化为8位时使用的抖动方式。
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dither {
+    /// 直接四舍五入。
+    None,
+    /// 4x4 Bayer矩阵有序抖动。
+    Ordered,
+    /// Floyd–Steinberg误差扩散。
+    FloydSteinberg,
+}
+
+/// 4x4 Bayer矩阵，取值0到15。
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// 将`render`得到的浮点灰度缓冲`shades`量化为8位灰度。
 ///
-/// `bounds`参数给定了`pixels`缓冲的宽和高，缓冲中按字节存储了相素灰度。
-/// `upper_left`和`lower_right`指定与像素缓冲左上角和右下角对应的复数平面。
-fn render(
-    pixels: &mut [u8],
+/// 抖动把量化误差分散到相邻像素上，每个像素与四舍五入的结果最多相差1。
+/// 灰度恰为0的内部像素始终输出0，也不参与误差扩散，外部像素则至少输出1，
+/// 所以量化后灰度为0仍然表示内部点。
+fn quantize(shades: &[f32], bounds: (usize, usize), dither: Dither) -> Vec<u8> {
+    assert!(shades.len() == bounds.0 * bounds.1);
+
+    let clamp = |v: f32, exact: f32| {
+        if exact == 0.0 {
+            0
+        } else {
+            v.round().clamp(1.0, 255.0) as u8
+        }
+    };
+    match dither {
+        Dither::None => shades.iter().map(|&v| clamp(v, v)).collect(),
+        Dither::Ordered => shades
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let (column, row) = (i % bounds.0, i / bounds.0);
+                let threshold = BAYER_4X4[row % 4][column % 4] as f32 / 16.0 - 15.0 / 32.0;
+                clamp(v + threshold, v)
+            })
+            .collect(),
+        Dither::FloydSteinberg => {
+            let (width, height) = bounds;
+            let mut values = shades.to_vec();
+            let mut pixels = vec![0; shades.len()];
+            for row in 0..height {
+                for column in 0..width {
+                    let i = row * width + column;
+                    if shades[i] == 0.0 {
+                        continue;
+                    }
+                    pixels[i] = clamp(values[i], shades[i]);
+                    let error = values[i] - pixels[i] as f32;
+                    let mut spread = |column: usize, row: usize, weight: f32| {
+                        if column < width && row < height && shades[row * width + column] != 0.0 {
+                            values[row * width + column] += error * weight;
+                        }
+                    };
+                    spread(column + 1, row, 7.0 / 16.0);
+                    if column > 0 {
+                        spread(column - 1, row + 1, 3.0 / 16.0);
+                    }
+                    spread(column, row + 1, 5.0 / 16.0);
+                    spread(column + 1, row + 1, 1.0 / 16.0);
+                }
+            }
+            pixels
+        }
+    }
+}
+
+#[test]
+fn test_quantize_dither() {
+    // 一段从10到14缓慢变化的水平渐变，四舍五入后只剩下几条宽色带。
+    let bounds = (256, 16);
+    let shades: Vec<f32> = (0..bounds.0 * bounds.1)
+        .map(|i| 10.0 + 4.0 * (i % bounds.0) as f32 / bounds.0 as f32)
+        .collect();
+    let plain = quantize(&shades, bounds, Dither::None);
+
+    for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+        let dithered = quantize(&shades, bounds, dither);
+        assert_ne!(dithered, plain);
+
+        // 与未抖动的输出只在最低位上有差别。
+        for (&d, &p) in dithered.iter().zip(&plain) {
+            assert!((d as i32 - p as i32).abs() <= 1);
+        }
+
+        // 每个8x8块的平均灰度都贴近真实的渐变值，不会出现色带那样的整级跳变。
+        for top in (0..bounds.1).step_by(8) {
+            for left in (0..bounds.0).step_by(8) {
+                let (mut sum, mut expected) = (0.0, 0.0);
+                for row in top..top + 8 {
+                    for column in left..left + 8 {
+                        sum += dithered[row * bounds.0 + column] as f32;
+                        expected += shades[row * bounds.0 + column];
+                    }
+                }
+                assert!((sum - expected).abs() / 64.0 < 0.3);
+            }
+        }
+    }
+
+    // 内部像素保持为0，外部像素不会被抖动成0。
+    let shades = [0.0, 1.2, 0.0, 1.4, 1.0, 0.0];
+    for dither in [Dither::None, Dither::Ordered, Dither::FloydSteinberg] {
+        let pixels = quantize(&shades, (3, 2), dither);
+        for (&p, &s) in pixels.iter().zip(&shades) {
+            assert_eq!(p == 0, s == 0.0);
+        }
+    }
+}
+
+/// 渲染整幅图像中的第`row`行到`line`，`line`的长度即为图像宽度。
+///
+/// 给定`orbits`时同时写入该行各像素的轨道。
+fn render_row(
+    line: &mut [f32],
+    orbits: Option<&mut [Orbit]>,
     bounds: (usize, usize),
+    row: usize,
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
-) {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    options: RenderOptions,
+) -> RenderStats {
+    let (row_upper_left, row_lower_right) = row_view(bounds, row, upper_left, lower_right);
+    render(
+        line,
+        orbits,
+        (bounds.0, 1),
+        row_upper_left,
+        row_lower_right,
+        options,
+    )
+}
 
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
-            pixels[row * bounds.0 + column] = match escape_time(point, 255) {
-                None => 0,
-                Some(count) => 255 - count as u8,
-            };
+/// 整幅图像中第`row`行像素所覆盖的视图，返回其左上角和右下角。
+fn row_view(
+    bounds: (usize, usize),
+    row: usize,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> (Complex<f64>, Complex<f64>) {
+    (
+        pixel_to_point(bounds, (0, row), upper_left, lower_right),
+        pixel_to_point(bounds, (bounds.0, row + 1), upper_left, lower_right),
+    )
+}
+
+/// 用于中断后继续渲染的检查点文件。
+///
+/// 文件以记录图像尺寸、两个角点、渲染选项以及是否保存轨道的文件头开始，之后每渲染完一行就追加一条记录：
+/// 小端`u64`行号，紧跟该行各像素的小端`f32`灰度。保存轨道时再接上各像素的轨道，
+/// 每个像素依次是逃逸次数（内部点为`u64::MAX`）、`z`的实部和虚部以及迭代次数，都按小端存储。
+/// 进程在写记录时被中断的话，最后一条记录可能不完整，下次打开时会将其丢弃。
+struct Checkpoint {
+    file: Mutex<File>,
+}
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"MANDCKP4";
+
+/// 检查点中每个像素的轨道所占的字节数。
+const CHECKPOINT_ORBIT_LEN: usize = 32;
+
+impl Checkpoint {
+    /// 打开`path`处的检查点，文件不存在时新建。
+    ///
+    /// 已存在的检查点必须与`bounds`、`upper_left`、`lower_right`和`options`描述的视图一致，
+    /// 且同样保存（给定`orbits`时）或不保存轨道。
+    /// 其中已完成的行会被写入`pixels`和`orbits`，返回值中的`Vec<bool>`标记了每一行是否已完成。
+    fn open(
+        path: &str,
+        bounds: (usize, usize),
+        upper_left: Complex<f64>,
+        lower_right: Complex<f64>,
+        options: RenderOptions,
+        pixels: &mut [f32],
+        mut orbits: Option<&mut [Orbit]>,
+    ) -> Result<(Checkpoint, Vec<bool>), std::io::Error> {
+        let mut header = CHECKPOINT_MAGIC.to_vec();
+        for n in [bounds.0 as u64, bounds.1 as u64] {
+            header.extend_from_slice(&n.to_le_bytes());
+        }
+        for f in [upper_left.re, upper_left.im, lower_right.re, lower_right.im] {
+            header.extend_from_slice(&f.to_bits().to_le_bytes());
+        }
+        header.push(options.smooth as u8);
+        header.push(options.norm as u8);
+        header.push(options.interior_shade as u8);
+        header.extend_from_slice(&(options.limit as u64).to_le_bytes());
+        header.push(orbits.is_some() as u8);
+
+        let mut done = vec![false; bounds.1];
+        let existing = match std::fs::read(path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+        match existing {
+            Some(data) if !data.is_empty() => {
+                if !data.starts_with(&header) {
+                    return Err(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "检查点与当前的图像尺寸、角点、渲染选项或导出选项不一致",
+                    ));
+                }
+
+                let shades_len = 8 + bounds.0 * 4;
+                let record_len = if orbits.is_some() {
+                    shades_len + bounds.0 * CHECKPOINT_ORBIT_LEN
+                } else {
+                    shades_len
+                };
+                let mut valid_len = header.len();
+                for record in data[header.len()..].chunks_exact(record_len) {
+                    let row = u64::from_le_bytes(record[..8].try_into().unwrap()) as usize;
+                    if row >= bounds.1 {
+                        break;
+                    }
+                    let line = row * bounds.0..(row + 1) * bounds.0;
+                    let shades = record[8..shades_len].chunks_exact(4);
+                    for (pixel, bytes) in pixels[line.clone()].iter_mut().zip(shades) {
+                        *pixel = f32::from_le_bytes(bytes.try_into().unwrap());
+                    }
+                    if let Some(orbits) = orbits.as_deref_mut() {
+                        let records = record[shades_len..].chunks_exact(CHECKPOINT_ORBIT_LEN);
+                        for (orbit, bytes) in orbits[line].iter_mut().zip(records) {
+                            let field = |i: usize| {
+                                u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap())
+                            };
+                            *orbit = Orbit {
+                                escape: (field(0) != u64::MAX).then(|| field(0) as usize),
+                                z: Complex {
+                                    re: f64::from_bits(field(1)),
+                                    im: f64::from_bits(field(2)),
+                                },
+                                iterations: field(3) as usize,
+                            };
+                        }
+                    }
+                    done[row] = true;
+                    valid_len += record_len;
+                }
+
+                file.set_len(valid_len as u64)?;
+                file.seek(SeekFrom::End(0))?;
+            }
+            _ => {
+                file.set_len(0)?;
+                file.write_all(&header)?;
+            }
+        }
+
+        Ok((
+            Checkpoint {
+                file: Mutex::new(file),
+            },
+            done,
+        ))
+    }
+
+    /// 将已渲染完成的第`row`行追加到检查点文件，打开时给定了`orbits`的检查点还要给出该行的轨道`orbits`。
+    fn record_row(
+        &self,
+        row: usize,
+        line: &[f32],
+        orbits: Option<&[Orbit]>,
+    ) -> Result<(), std::io::Error> {
+        let mut record = (row as u64).to_le_bytes().to_vec();
+        for pixel in line {
+            record.extend_from_slice(&pixel.to_le_bytes());
+        }
+        for orbit in orbits.unwrap_or_default() {
+            let escape = orbit.escape.map_or(u64::MAX, |escape| escape as u64);
+            for field in [
+                escape,
+                orbit.z.re.to_bits(),
+                orbit.z.im.to_bits(),
+                orbit.iterations as u64,
+            ] {
+                record.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        self.file.lock().unwrap().write_all(&record)
+    }
+}
+
+#[test]
+fn test_checkpoint_resume() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let options = RenderOptions {
+        smooth: true,
+        ..Default::default()
+    };
+    let dir = std::env::temp_dir();
+    let full_path = dir.join(format!("mandelbrot-full-{}.ckpt", std::process::id()));
+    let resumed_path = dir.join(format!("mandelbrot-resumed-{}.ckpt", std::process::id()));
+    let full_path = full_path.to_str().unwrap();
+    let resumed_path = resumed_path.to_str().unwrap();
+
+    // 不中断地渲染一次。
+    let mut expected = vec![0.0; bounds.0 * bounds.1];
+    let (checkpoint, done) = Checkpoint::open(
+        full_path,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        &mut expected,
+        None,
+    )
+    .unwrap();
+    render_parallel(
+        &mut expected,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        Some((&checkpoint, &done)),
+        RenderControl::default(),
+    );
+    drop(checkpoint);
+
+    // 只渲染部分行后“中断”，并在文件末尾留下半条记录。
+    {
+        let mut partial = vec![0.0; bounds.0 * bounds.1];
+        let (checkpoint, _) = Checkpoint::open(
+            resumed_path,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            &mut partial,
+            None,
+        )
+        .unwrap();
+        for row in [0, 1, 2, 17] {
+            let line = &mut partial[row * bounds.0..(row + 1) * bounds.0];
+            render_row(line, None, bounds, row, upper_left, lower_right, options);
+            checkpoint.record_row(row, line, None).unwrap();
+        }
+        checkpoint
+            .file
+            .lock()
+            .unwrap()
+            .write_all(&[5, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3])
+            .unwrap();
+    }
+
+    let mut pixels = vec![0.0; bounds.0 * bounds.1];
+    let (checkpoint, done) = Checkpoint::open(
+        resumed_path,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        &mut pixels,
+        None,
+    )
+    .unwrap();
+    assert_eq!(done.iter().filter(|&&d| d).count(), 4);
+    assert!(done[17] && !done[5]);
+    render_parallel(
+        &mut pixels,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        Some((&checkpoint, &done)),
+        RenderControl::default(),
+    );
+    assert_eq!(pixels, expected);
+
+    // 视图或渲染选项不一致的检查点应当被拒绝。
+    let other = Complex { re: 0.0, im: 0.0 };
+    assert!(Checkpoint::open(
+        resumed_path,
+        bounds,
+        other,
+        lower_right,
+        options,
+        &mut pixels,
+        None
+    )
+    .is_err());
+    let plain = RenderOptions::default();
+    assert!(Checkpoint::open(
+        resumed_path,
+        bounds,
+        upper_left,
+        lower_right,
+        plain,
+        &mut pixels,
+        None
+    )
+    .is_err());
+    // 不保存轨道的检查点也不能用于需要轨道的渲染。
+    let mut orbits = vec![Orbit::default(); bounds.0 * bounds.1];
+    assert!(Checkpoint::open(
+        resumed_path,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        &mut pixels,
+        Some(&mut orbits)
+    )
+    .is_err());
+    std::fs::remove_file(resumed_path).unwrap();
+
+    // 保存轨道的检查点在继续渲染时恢复已完成各行的轨道，结果与一次渲染完的相同。
+    let mut counts = vec![None; bounds.0 * bounds.1];
+    render_counts(&mut counts, bounds, upper_left, lower_right, options);
+    {
+        let mut partial = vec![0.0; bounds.0 * bounds.1];
+        let mut partial_orbits = vec![Orbit::default(); bounds.0 * bounds.1];
+        let (checkpoint, _) = Checkpoint::open(
+            resumed_path,
+            bounds,
+            upper_left,
+            lower_right,
+            options,
+            &mut partial,
+            Some(&mut partial_orbits),
+        )
+        .unwrap();
+        for row in [3, 20] {
+            let range = row * bounds.0..(row + 1) * bounds.0;
+            let line = &mut partial[range.clone()];
+            let orbit_line = &mut partial_orbits[range];
+            render_row(
+                line,
+                Some(orbit_line),
+                bounds,
+                row,
+                upper_left,
+                lower_right,
+                options,
+            );
+            checkpoint.record_row(row, line, Some(orbit_line)).unwrap();
         }
     }
+    let mut pixels = vec![0.0; bounds.0 * bounds.1];
+    let (checkpoint, done) = Checkpoint::open(
+        resumed_path,
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        &mut pixels,
+        Some(&mut orbits),
+    )
+    .unwrap();
+    assert_eq!(done.iter().filter(|&&d| d).count(), 2);
+    assert!(orbits[20 * bounds.0..21 * bounds.0]
+        .iter()
+        .any(|orbit| orbit.escape.is_some()));
+    render_parallel_with(
+        (&mut pixels, Some(&mut orbits)),
+        bounds,
+        upper_left,
+        lower_right,
+        options,
+        Some((&checkpoint, &done)),
+        RenderControl::default(),
+    );
+    assert_eq!(pixels, expected);
+    let escapes: Vec<Option<usize>> = orbits.iter().map(|orbit| orbit.escape).collect();
+    assert_eq!(escapes, counts);
+
+    std::fs::remove_file(full_path).unwrap();
+    std::fs::remove_file(resumed_path).unwrap();
+}
+
+/// 将着色后的缓冲`pixels`转换为RGBA，集合内部的像素完全透明，其余像素不透明。
+///
+/// `pixels`的每个像素占`channels`个字节：1表示灰度，3表示RGB。
+/// `shades`是`render`输出的浮点灰度：只有内部点的灰度为0，外部点的灰度至少为1。
+fn transparent_interior_rgba(shades: &[f32], pixels: &[u8], channels: usize) -> Vec<u8> {
+    shades
+        .iter()
+        .zip(pixels.chunks(channels))
+        .flat_map(|(&shade, color)| {
+            let alpha = if shade == 0.0 { 0 } else { 255 };
+            match color {
+                &[gray] => [gray, gray, gray, alpha],
+                _ => [color[0], color[1], color[2], alpha],
+            }
+        })
+        .collect()
 }
 
 /// 写缓冲`pixels`，大小由`bounds`指定, 文件名为`filename`。
+///
+/// `color`给定缓冲中像素的格式，如`ColorType::Gray(8)`或`ColorType::RGBA(8)`。
+/// 给定`params`时将其嵌入PNG文件，之后可用`--continue`读出。
+/// `filename`以`.jpg`或`.jpeg`结尾时写出JPEG图像，此时不记录`params`，透明通道也会被丢弃。
 fn write_image(
     filename: &str,
     pixels: &[u8],
     bounds: (usize, usize),
+    color: ColorType,
+    params: Option<&ImageParams>,
+    encoding: Encoding,
 ) -> Result<(), std::io::Error> {
-    let output = File::create(filename)?;
+    let data = if is_jpeg(filename) {
+        encode_jpeg(pixels, bounds, color, encoding)?
+    } else {
+        encode_png(pixels, bounds, color, params, encoding)?
+    };
+    std::fs::write(filename, data)
+}
 
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::Gray(8))?;
+/// 写出图像时传给编码器的参数。
+#[derive(Clone, Copy, Debug, Default)]
+struct Encoding {
+    /// PNG的压缩级别。
+    compression: png::Compression,
+    /// JPEG的质量，`None`表示使用编码器的默认值。
+    quality: Option<u8>,
+}
 
-    Ok(())
+/// 判断`filename`是否应该写为JPEG图像。
+fn is_jpeg(filename: &str) -> bool {
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    matches!(extension.as_deref(), Some("jpg" | "jpeg"))
+}
+
+/// 与`write_image`相同，但返回PNG数据而不写入文件。
+fn encode_png(
+    pixels: &[u8],
+    bounds: (usize, usize),
+    color: ColorType,
+    params: Option<&ImageParams>,
+    encoding: Encoding,
+) -> Result<Vec<u8>, std::io::Error> {
+    let (png_color, depth) = match color {
+        ColorType::Gray(depth) => (png::ColorType::Grayscale, depth),
+        ColorType::GrayA(depth) => (png::ColorType::GrayscaleAlpha, depth),
+        ColorType::RGB(depth) => (png::ColorType::Rgb, depth),
+        ColorType::RGBA(depth) => (png::ColorType::Rgba, depth),
+        _ => {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("不支持的像素格式{:?}", color),
+            ))
+        }
+    };
+    let depth = match depth {
+        8 => png::BitDepth::Eight,
+        16 => png::BitDepth::Sixteen,
+        _ => {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("不支持的位深{}", depth),
+            ))
+        }
+    };
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, bounds.0 as u32, bounds.1 as u32);
+    encoder.set_color(png_color);
+    encoder.set_depth(depth);
+    encoder.set_compression(encoding.compression);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+
+    if let Some(params) = params {
+        png = embed_params(&png, params);
+    }
+    Ok(png)
+}
+
+/// 将像素编码为JPEG数据，只支持8位的像素。
+fn encode_jpeg(
+    pixels: &[u8],
+    bounds: (usize, usize),
+    color: ColorType,
+    encoding: Encoding,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut jpeg = Vec::new();
+    let mut encoder = match encoding.quality {
+        Some(quality) => JPEGEncoder::new_with_quality(&mut jpeg, quality),
+        None => JPEGEncoder::new(&mut jpeg),
+    };
+    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, color)?;
+    Ok(jpeg)
+}
+
+#[test]
+fn test_compression() {
+    let bounds = (200, 150);
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render(
+        &mut shades,
+        None,
+        bounds,
+        Complex { re: -2.0, im: 1.2 },
+        Complex { re: 1.0, im: -1.2 },
+        RenderOptions::default(),
+    );
+    let pixels = quantize(&shades, bounds, Dither::None);
+
+    let encode = |compression| {
+        let encoding = Encoding {
+            compression,
+            ..Default::default()
+        };
+        encode_png(&pixels, bounds, ColorType::Gray(8), None, encoding).unwrap()
+    };
+    let fast = encode(png::Compression::Fast);
+    let best = encode(png::Compression::Best);
+    assert!(best.len() <= fast.len());
+    for png in [fast, best] {
+        let decoded = image::load_from_memory(&png).unwrap().to_luma();
+        assert_eq!(decoded.into_raw(), pixels);
+    }
+
+    // 质量越低，JPEG文件越小。
+    let jpeg = |quality| {
+        let encoding = Encoding {
+            quality: Some(quality),
+            ..Default::default()
+        };
+        encode_jpeg(&pixels, bounds, ColorType::Gray(8), encoding)
+            .unwrap()
+            .len()
+    };
+    assert!(jpeg(20) < jpeg(95));
+    assert!(is_jpeg("out.JPG") && !is_jpeg("out.png"));
+}
+
+#[test]
+fn test_transparent_interior() {
+    let bounds = (60, 40);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let mut shades = vec![0.0; bounds.0 * bounds.1];
+    render(
+        &mut shades,
+        None,
+        bounds,
+        upper_left,
+        lower_right,
+        RenderOptions::default(),
+    );
+    let pixels = quantize(&shades, bounds, Dither::None);
+
+    let path = std::env::temp_dir().join(format!("mandelbrot-rgba-{}.png", std::process::id()));
+    let path = path.to_str().unwrap();
+    let rgba = transparent_interior_rgba(&shades, &pixels, 1);
+    write_image(
+        path,
+        &rgba,
+        bounds,
+        ColorType::RGBA(8),
+        None,
+        Encoding::default(),
+    )
+    .unwrap();
+
+    let decoded = image::open(path).unwrap().to_rgba();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(decoded.dimensions(), (bounds.0 as u32, bounds.1 as u32));
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            let pixel = decoded.get_pixel(column as u32, row as u32);
+            let expected_alpha = match mandelbrot::escape_time(point, 255) {
+                None => 0,
+                Some(_) => 255,
+            };
+            assert_eq!(pixel.data[3], expected_alpha);
+            assert_eq!(pixel.data[0], pixels[row * bounds.0 + column]);
+        }
+    }
 }
 
 // fn square_loop(mut x: f64) {
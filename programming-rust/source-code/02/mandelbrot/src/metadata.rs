@@ -0,0 +1,154 @@
+//! 在PNG文件中嵌入和读取渲染参数。
+//!
+//! 参数以`tEXt`块的形式紧跟在`IHDR`块之后写入，关键字为`mandelbrot`，
+//! 内容与命令行参数的写法相同，如`1000x750 -1.2,0.35 -1,0.2`。
+
+use crate::{parse_complex, parse_pair};
+use num::Complex;
+use std::fmt;
+
+/// `tEXt`块的关键字。
+const KEYWORD: &[u8] = b"mandelbrot";
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// 一次渲染的图像尺寸和视图的两个角点。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageParams {
+    pub bounds: (usize, usize),
+    pub upper_left: Complex<f64>,
+    pub lower_right: Complex<f64>,
+}
+
+impl fmt::Display for ImageParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {},{} {},{}",
+            self.bounds.0,
+            self.bounds.1,
+            self.upper_left.re,
+            self.upper_left.im,
+            self.lower_right.re,
+            self.lower_right.im
+        )
+    }
+}
+
+impl ImageParams {
+    /// 解析`Display`输出的格式。
+    pub fn parse(s: &str) -> Option<ImageParams> {
+        let mut fields = s.split(' ');
+        let params = ImageParams {
+            bounds: parse_pair(fields.next()?, 'x')?,
+            upper_left: parse_complex(fields.next()?)?,
+            lower_right: parse_complex(fields.next()?)?,
+        };
+        match fields.next() {
+            None => Some(params),
+            Some(_) => None,
+        }
+    }
+}
+
+/// 在PNG数据`png`的`IHDR`块之后插入记录`params`的`tEXt`块，返回新的PNG数据。
+///
+/// `png`必须是以`IHDR`块开头的合法PNG数据，如`PNGEncoder`的输出。
+pub fn embed_params(png: &[u8], params: &ImageParams) -> Vec<u8> {
+    // 文件签名8字节，IHDR块为4字节长度、4字节类型、13字节数据和4字节CRC。
+    let ihdr_end = PNG_SIGNATURE.len() + 4 + 4 + 13 + 4;
+    assert!(png.starts_with(PNG_SIGNATURE) && &png[12..16] == b"IHDR");
+
+    let mut data = KEYWORD.to_vec();
+    data.push(0);
+    data.extend_from_slice(params.to_string().as_bytes());
+
+    let mut output = png[..ihdr_end].to_vec();
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let chunk_start = output.len();
+    output.extend_from_slice(b"tEXt");
+    output.extend_from_slice(&data);
+    let crc = crc32(&output[chunk_start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+    output.extend_from_slice(&png[ihdr_end..]);
+    output
+}
+
+/// 从PNG数据`png`中找出`embed_params`写入的参数。
+pub fn parse_params(png: &[u8]) -> Option<ImageParams> {
+    let mut rest = png.strip_prefix(PNG_SIGNATURE)?;
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind = &rest[4..8];
+        let data = rest.get(8..8 + length)?;
+        if kind == b"tEXt" {
+            if let Some(text) = data
+                .strip_prefix(KEYWORD)
+                .and_then(|t| t.strip_prefix(b"\0"))
+            {
+                return ImageParams::parse(std::str::from_utf8(text).ok()?);
+            }
+        }
+        if kind == b"IDAT" || kind == b"IEND" {
+            // 参数块总是位于图像数据之前。
+            return None;
+        }
+        rest = rest.get(8 + length + 4..)?;
+    }
+    None
+}
+
+/// 读取PNG文件`path`中嵌入的渲染参数。
+pub fn read_image_params(path: &str) -> Result<ImageParams, String> {
+    let png = std::fs::read(path).map_err(|e| format!("读取图像文件'{}'失败: {}", path, e))?;
+    parse_params(&png).ok_or_else(|| format!("图像文件'{}'中没有渲染参数", path))
+}
+
+/// PNG块使用的CRC-32校验值（多项式`0xEDB88320`）。
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn test_embed_params() {
+    use image::png::PNGEncoder;
+    use image::ColorType;
+
+    let mut png = Vec::new();
+    PNGEncoder::new(&mut png)
+        .encode(&[0, 128, 255, 64], 2, 2, ColorType::Gray(8))
+        .unwrap();
+    assert_eq!(parse_params(&png), None);
+
+    let params = ImageParams {
+        bounds: (2, 2),
+        upper_left: Complex {
+            re: -1.25,
+            im: 0.35,
+        },
+        lower_right: Complex { re: -1.0, im: 0.2 },
+    };
+    let embedded = embed_params(&png, &params);
+    assert_eq!(parse_params(&embedded), Some(params));
+
+    // 插入参数块后仍然是可以解码的PNG，像素不变。
+    let decoded = image::load_from_memory(&embedded).unwrap().to_luma();
+    assert_eq!(decoded.into_raw(), vec![0, 128, 255, 64]);
+}
@@ -0,0 +1,61 @@
+//! 将每个像素连续的逃逸次数（势）以32位浮点数写入OpenEXR文件，不做任何量化。
+//!
+//! 文件只有一个名为`Y`的`f32`通道，按行优先存放`smooth_escape_time`的值，
+//! 集合内部的点（在迭代上限内没有逃逸）记为正无穷。
+
+use exr::prelude::{
+    read_first_flat_layer_from_file, AnyChannel, AnyChannels, FlatSamples, Image, Layer,
+    LayerAttributes, SmallVec, WritableImage,
+};
+
+/// 通道的名字。
+const CHANNEL: &str = "Y";
+
+/// 集合内部的点在文件中的值。
+pub const INTERIOR: f32 = f32::INFINITY;
+
+/// 将`bounds`大小的势`values`写入EXR文件`path`。
+pub fn write_exr(path: &str, bounds: (usize, usize), values: &[f32]) -> exr::error::UnitResult {
+    assert!(values.len() == bounds.0 * bounds.1);
+    let channel = AnyChannel::new(CHANNEL, FlatSamples::F32(values.to_vec()));
+    let mut channels = SmallVec::new();
+    channels.push(channel);
+    let layer = Layer::new(
+        bounds,
+        LayerAttributes::named("potential"),
+        exr::image::Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer).write().to_file(path)
+}
+
+/// 读取`write_exr`写入的文件，返回图像尺寸和按行优先排列的势。
+pub fn read_exr(path: &str) -> exr::error::Result<((usize, usize), Vec<f32>)> {
+    let image = read_first_flat_layer_from_file(path)?;
+    let layer = image.layer_data;
+    let bounds = (layer.size.0, layer.size.1);
+    let values = layer
+        .channel_data
+        .list
+        .into_iter()
+        .find(|channel| channel.name.eq(CHANNEL))
+        .map(|channel| channel.sample_data);
+    match values {
+        Some(FlatSamples::F32(values)) => Ok((bounds, values)),
+        Some(samples) => Ok((bounds, samples.values_as_f32().collect())),
+        None => Err(exr::error::Error::Invalid("没有势的通道".into())),
+    }
+}
+
+#[test]
+fn test_exr() {
+    let path =
+        std::env::temp_dir().join(format!("mandelbrot-potential-{}.exr", std::process::id()));
+    let path = path.to_str().unwrap();
+    let values = [0.5, 1.25, INTERIOR, 17.0625, 254.75, 3.0e-4];
+    write_exr(path, (3, 2), &values).unwrap();
+    let (bounds, decoded) = read_exr(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(bounds, (3, 2));
+    assert_eq!(decoded, values);
+}
@@ -0,0 +1,218 @@
+//! 传递函数：用一个简单的算术表达式把逃逸次数映射为灰度，用来得到程序化的着色。
+//!
+//! 表达式中可以使用变量`iter`（逃逸次数，平滑着色时带小数）和`limit`（迭代上限），
+//! 常量`pi`，运算符`+ - * / ^`和括号，以及函数`sin`、`cos`、`tan`、`exp`、`ln`、
+//! `sqrt`、`abs`和`floor`。例如`1 - iter/limit`得到与默认着色相同的线性灰度。
+
+use std::str::FromStr;
+
+/// 解析后的传递函数表达式。
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Iter,
+    Limit,
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(fn(f64) -> f64, Box<Expr>),
+}
+
+impl Transfer {
+    /// 计算逃逸次数为`iter`、迭代上限为`limit`时表达式的值。
+    pub fn eval(&self, iter: f64, limit: f64) -> f64 {
+        self.expr.eval(iter, limit)
+    }
+}
+
+impl Expr {
+    fn eval(&self, iter: f64, limit: f64) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Iter => iter,
+            Expr::Limit => limit,
+            Expr::Neg(expr) => -expr.eval(iter, limit),
+            Expr::Binary(op, left, right) => {
+                let (a, b) = (left.eval(iter, limit), right.eval(iter, limit));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Expr::Call(function, arg) => function(arg.eval(iter, limit)),
+        }
+    }
+}
+
+fn function(name: &str) -> Option<fn(f64) -> f64> {
+    let function: fn(f64) -> f64 = match name {
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "exp" => f64::exp,
+        "ln" => f64::ln,
+        "sqrt" => f64::sqrt,
+        "abs" => f64::abs,
+        "floor" => f64::floor,
+        _ => return None,
+    };
+    Some(function)
+}
+
+impl FromStr for Transfer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        parser.skip_spaces();
+        match parser.peek() {
+            None => Ok(Transfer { expr }),
+            Some(c) => Err(format!("传递函数'{}'中有多余的字符'{}'", s, c)),
+        }
+    }
+}
+
+/// 递归下降解析器，`^`优先级最高且右结合，一元负号的优先级低于`^`。
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let next = self.peek();
+        let start = self.pos;
+        match next {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                if !self.eat(')') {
+                    return Err("传递函数中缺少')'".to_string());
+                }
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                while self
+                    .chars
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+                {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("传递函数中的数字'{}'无效", number))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                while self
+                    .chars
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric())
+                {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                match name.as_str() {
+                    "iter" => Ok(Expr::Iter),
+                    "limit" => Ok(Expr::Limit),
+                    "pi" => Ok(Expr::Number(std::f64::consts::PI)),
+                    _ => {
+                        let function = function(&name)
+                            .ok_or_else(|| format!("传递函数中有未知的名称'{}'", name))?;
+                        if !self.eat('(') {
+                            return Err(format!("函数'{}'后缺少'('", name));
+                        }
+                        let arg = self.expr()?;
+                        if !self.eat(')') {
+                            return Err("传递函数中缺少')'".to_string());
+                        }
+                        Ok(Expr::Call(function, Box::new(arg)))
+                    }
+                }
+            }
+            Some(c) => Err(format!("传递函数中第{}个字符'{}'无效", start + 1, c)),
+            None => Err("传递函数不完整".to_string()),
+        }
+    }
+}
+
+#[test]
+fn test_transfer() {
+    let eval = |s: &str, iter: f64| s.parse::<Transfer>().unwrap().eval(iter, 100.0);
+    assert_eq!(eval("0.5", 3.0), 0.5);
+    assert_eq!(eval("1 - iter/limit", 25.0), 0.75);
+    assert_eq!(eval("2 * (iter + 1) ^ 2", 2.0), 18.0);
+    assert_eq!(eval("2 ^ 3 ^ 2", 0.0), 512.0);
+    assert_eq!(eval("-2 ^ 2", 0.0), -4.0);
+    assert_eq!(eval("abs(sin(iter * pi))", 0.0), 0.0);
+    assert!((eval("sin(iter*0.1)", 5.0) - 0.5f64.sin()).abs() < 1e-12);
+
+    assert!("".parse::<Transfer>().is_err());
+    assert!("iter +".parse::<Transfer>().is_err());
+    assert!("(iter".parse::<Transfer>().is_err());
+    assert!("foo(iter)".parse::<Transfer>().is_err());
+    assert!("iter iter".parse::<Transfer>().is_err());
+    assert!("1..2".parse::<Transfer>().is_err());
+}
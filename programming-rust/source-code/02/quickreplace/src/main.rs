@@ -1,84 +1,4630 @@
-use regex::Regex;
+use encoding_rs::Encoding;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::{Captures, Regex, Replacer};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
+use std::io::IsTerminal;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use text_colorizer::*;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct Arguments {
     target: String,
     replacement: String,
     filename: String,
     output: String,
+    group: Option<usize>,
+    transform: Option<Transform>,
+    recursive: bool,
+    paths: Vec<String>,
+    /// 递归模式下要跳过的路径，由各个`--exclude`组成。
+    exclude: GlobSet,
+    /// 递归模式下是否跟随指向目录的符号链接。
+    follow_symlinks: bool,
+    max_file_size: Option<u64>,
+    /// 递归模式下只处理在这个时刻之后修改过的文件，由`--since`换算而来。
+    since: Option<SystemTime>,
+    /// 递归模式下遇到二进制文件时报错，而不是跳过。
+    fail_on_binary: bool,
+    /// `--if-contains`给出的正则表达式，递归模式下内容不匹配它的文件原样跳过。
+    /// 按字节匹配，因此同样适用于`--binary`。
+    if_contains: Option<regex::bytes::Regex>,
+    /// 整次运行中所有文件的匹配总数上限，超过时不写入任何文件。
+    max_total: Option<usize>,
+    /// `--report`指定的文件，替换完成后向其中写入JSON格式的汇总。
+    report: Option<String>,
+    /// 递归模式下不就地修改，而是把每个文件的结果按相对路径写入这个目录下。
+    output_dir: Option<String>,
+    atomic: bool,
+    /// 用临时文件替换原文件时，改名前后把文件和所在目录刷到磁盘。
+    sync: bool,
+    /// 单文件模式下允许OUTPUT与INPUT是同一个文件。
+    in_place: bool,
+    /// 单文件模式下OUTPUT已经存在时先询问是否覆盖。
+    prompt_before_overwrite: bool,
+    /// 不询问，直接覆盖已经存在的OUTPUT。
+    force: bool,
+    dry_run: bool,
+    /// `--dry-run`时以匹配的总次数（最大255）作为退出状态。
+    exit_count: bool,
+    json: bool,
+    abort_on_error: bool,
+    /// 递归模式下同时处理文件的线程数，0和1都表示逐个处理。
+    jobs: usize,
+    fail_if_no_match: bool,
+    /// 只报告每行的匹配次数，不做替换。
+    count_lines: bool,
+    /// 只报告匹配到的每个不同的字符串及其出现次数，不做替换。
+    distinct: bool,
+    /// 只列出至少有一处匹配的文件，不做替换。
+    files_with_matches: bool,
+    /// 只检查是否有文件需要修改，不写入任何文件。
+    check: bool,
+    /// 从标准输入逐行读取，每行替换后立即写到标准输出。
+    stdin_lines: bool,
+    /// `--dry-run`时在每处替换前后显示的上下文行数。
+    context: usize,
+    /// `--dry-run`时只报告每个文件的匹配次数。
+    summary_only: bool,
+    /// 将文件作为字节而不是UTF-8文本处理。
+    binary: bool,
+    /// 文本文件的编码，替换后按原来的编码写回。
+    encoding: TextEncoding,
+    /// 逐行替换时每行最多替换的次数。
+    per_line_limit: Option<usize>,
+    /// 逐行替换，不限制每行的替换次数。
+    line_mode: bool,
+    /// 每个输入只替换第一处匹配。
+    once: bool,
+    /// 只替换紧接在这段文本之后的匹配，这段文本本身不被替换。
+    before: Option<String>,
+    /// 只替换后面紧跟着这段文本的匹配，这段文本本身不被替换。
+    after: Option<String>,
+    /// 跳过这种语言的注释中的匹配。
+    skip_comments: Option<CommentStyle>,
+    /// 递归模式下替换文件名而不是文件内容。
+    rename: bool,
+    /// `--pair`给出的多组替换，此时`target`为合并后的正则表达式。
+    pairs: Option<Pairs>,
+    /// `parse_args`按`replacement`检查过的替换模板，使用`--pair`、`--transform`或`--binary`时为`None`。
+    template: Option<Template>,
+    /// 日志详细程度：每个`-v`加1，`-q`为-1。
+    verbosity: i32,
+}
+
+impl Arguments {
+    /// 替换模板：优先使用`parse_args`检查过的模板，没有时（直接构造`Arguments`时）按`replacement`检查。
+    fn template(&self) -> Result<Cow<'_, Template>, Error> {
+        match &self.template {
+            Some(template) => Ok(Cow::Borrowed(template)),
+            None => Template::checked(&self.replacement, &self.target).map(Cow::Owned),
+        }
+    }
+}
+
+/// 代替替换模板、作用于匹配文本本身的内置变换。
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transform {
+    Upper,
+    Lower,
+    Trim,
+}
+
+impl std::str::FromStr for Transform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(Transform::Upper),
+            "lower" => Ok(Transform::Lower),
+            "trim" => Ok(Transform::Trim),
+            _ => Err(format!("未知的变换'{}'，可选upper、lower或trim", s)),
+        }
+    }
+}
+
+impl Transform {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Transform::Upper => text.to_uppercase(),
+            Transform::Lower => text.to_lowercase(),
+            Transform::Trim => text.trim().to_string(),
+        }
+    }
+}
+
+/// `--skip-comments`支持的注释风格。
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommentStyle {
+    /// C、C++、Rust、Java、JavaScript等语言的`//`行注释和`/* */`块注释。
+    C,
+    /// shell、Python、Ruby等语言的`#`行注释。
+    Hash,
+}
+
+impl std::str::FromStr for CommentStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" | "cpp" | "rust" | "java" | "js" => Ok(CommentStyle::C),
+            "shell" | "python" | "ruby" => Ok(CommentStyle::Hash),
+            _ => Err(format!(
+                "未知的语言'{}'，可选c、cpp、rust、java、js、shell、python或ruby",
+                s
+            )),
+        }
+    }
+}
+
+impl CommentStyle {
+    /// 用一个简单的词法分析找出`text`中所有注释的字节范围，范围包括注释的定界符。
+    ///
+    /// 双引号字符串（`#`风格还有单引号字符串）中的注释符号不算注释，字符串中可以用`\`转义。
+    /// `C`风格跳过`'"'`、`'\''`这样的字符字面量，其余的单引号（如Rust的生命周期）不影响分析。
+    /// 没有结束的块注释一直延续到文本末尾。不识别Rust的原始字符串等更复杂的字面量。
+    fn comments(self, text: &str) -> Vec<Range<usize>> {
+        let bytes = text.as_bytes();
+        let mut comments = Vec::new();
+        let mut quote = None;
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            if let Some(q) = quote {
+                match rest[0] {
+                    b'\\' => i += 1,
+                    c if c == q => quote = None,
+                    _ => {}
+                }
+                i += 1;
+                continue;
+            }
+            let end = match (self, rest) {
+                (_, [b'"', ..]) | (CommentStyle::Hash, [b'\'', ..]) => {
+                    quote = Some(rest[0]);
+                    i += 1;
+                    continue;
+                }
+                (CommentStyle::C, [b'\'', ..]) => {
+                    i += char_literal_len(&text[i..]);
+                    continue;
+                }
+                (CommentStyle::C, [b'/', b'/', ..]) | (CommentStyle::Hash, [b'#', ..]) => rest
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(bytes.len(), |n| i + n),
+                (CommentStyle::C, [b'/', b'*', ..]) => rest[2..]
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .map_or(bytes.len(), |n| i + 2 + n + 2),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            comments.push(i..end);
+            i = end;
+        }
+        comments
+    }
+}
+
+/// `rest`以字符字面量开头时返回它的字节长度，否则返回1，即只跳过开头的单引号。
+fn char_literal_len(rest: &str) -> usize {
+    let body = &rest[1..];
+    let len = if let Some(escaped) = body.strip_prefix('\\') {
+        // 转义序列如`\'`、`\x41`、`\u{263a}`：从转义的第一个字符之后到下一个单引号为止。
+        let skip = escaped.chars().next().map_or(0, char::len_utf8);
+        escaped[skip..]
+            .find(['\'', '\n'])
+            .filter(|&n| escaped[skip + n..].starts_with('\''))
+            .map(|n| 1 + skip + n)
+    } else {
+        body.chars()
+            .next()
+            .filter(|&c| c != '\'' && c != '\n')
+            .map(char::len_utf8)
+            .filter(|&n| body[n..].starts_with('\''))
+    };
+    // 加上两端的单引号。
+    len.map_or(1, |n| n + 2)
+}
+
+#[test]
+fn test_comment_ranges() {
+    let text = "a // x \"\nb /* y\n */ \"// no\" c";
+    let comments: Vec<&str> = CommentStyle::C
+        .comments(text)
+        .into_iter()
+        .map(|range| &text[range])
+        .collect();
+    assert_eq!(comments, ["// x \"", "/* y\n */"]);
+
+    let text = "echo '#no' \"a\\\"#no\" # yes\n# last";
+    let comments: Vec<&str> = CommentStyle::Hash
+        .comments(text)
+        .into_iter()
+        .map(|range| &text[range])
+        .collect();
+    assert_eq!(comments, ["# yes", "# last"]);
+
+    // 没有结束的块注释延续到末尾。
+    assert_eq!(CommentStyle::C.comments("x /* y"), vec![2..6]);
+
+    // 字符字面量中的引号不开始字符串，生命周期的单引号不影响分析。
+    let text = "let q = '\"'; // a\nlet e = '\\''; fn f<'a>() {} /* b */ '中' // c";
+    let comments: Vec<&str> = CommentStyle::C
+        .comments(text)
+        .into_iter()
+        .map(|range| &text[range])
+        .collect();
+    assert_eq!(comments, ["// a", "/* b */", "// c"]);
+}
+
+/// `--encoding`指定的文本文件编码。
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum TextEncoding {
+    /// 只接受UTF-8。
+    #[default]
+    Utf8,
+    /// 合法的UTF-8按UTF-8处理，否则按Latin-1处理。
+    Auto,
+    /// 按给定的编码读取和写回。
+    Fixed(&'static Encoding),
+}
+
+impl std::str::FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(TextEncoding::Auto);
+        }
+        match Encoding::for_label(s.as_bytes()) {
+            Some(encoding) if encoding == encoding_rs::UTF_8 => Ok(TextEncoding::Utf8),
+            // UTF-16等编码只能解码，encoding_rs无法按原编码写回。
+            Some(encoding) if encoding.output_encoding() != encoding => {
+                Err(format!("不支持写回{}编码", encoding.name()))
+            }
+            Some(encoding) => Ok(TextEncoding::Fixed(encoding)),
+            None => Err(format!("未知的编码'{}'", s)),
+        }
+    }
+}
+
+impl TextEncoding {
+    /// 将从`path`读到的`bytes`解码为文本，同时返回写回时应使用的编码。
+    ///
+    /// 解码时不处理BOM：UTF-8的BOM作为`\u{feff}`留在文本开头，由`replace_text`原样保留。
+    fn decode(self, path: &Path, bytes: Vec<u8>) -> Result<(String, &'static Encoding), Error> {
+        let encoding = match self {
+            TextEncoding::Utf8 => encoding_rs::UTF_8,
+            TextEncoding::Auto if std::str::from_utf8(&bytes).is_ok() => encoding_rs::UTF_8,
+            // WHATWG中的latin1即windows-1252，每个字节都能解码，并能原样编码回去。
+            TextEncoding::Auto => encoding_rs::WINDOWS_1252,
+            TextEncoding::Fixed(encoding) => encoding,
+        };
+        let invalid = |message: String| {
+            Error::Read(
+                path.to_path_buf(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, message),
+            )
+        };
+        if encoding == encoding_rs::UTF_8 {
+            let text = String::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+            return Ok((text, encoding));
+        }
+        match encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+            Some(text) => Ok((text.into_owned(), encoding)),
+            None => Err(invalid(format!("不是有效的{}文本", encoding.name()))),
+        }
+    }
+}
+
+/// 将替换后的`text`按`encoding`编码，其中有无法用该编码表示的字符时返回`Error::Unencodable`。
+fn encode_text(path: &Path, text: String, encoding: &'static Encoding) -> Result<Vec<u8>, Error> {
+    if encoding == encoding_rs::UTF_8 {
+        return Ok(text.into_bytes());
+    }
+    let (bytes, _, unmappable) = encoding.encode(&text);
+    if unmappable {
+        return Err(Error::Unencodable(path.to_path_buf(), encoding.name()));
+    }
+    Ok(bytes.into_owned())
+}
+
+#[test]
+fn test_encoding() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("latin1.txt");
+    // Latin-1的"café crème"，é和è各占一个字节，不是合法的UTF-8。
+    fs::write(&path, b"caf\xe9 cr\xe8me").unwrap();
+    let mut args = Arguments {
+        target: "crème".to_string(),
+        replacement: "brûlée".to_string(),
+        recursive: true,
+        paths: vec![path.to_str().unwrap().to_string()],
+        encoding: "latin1".parse().unwrap(),
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"caf\xe9 br\xfbl\xe9e");
+
+    // 自动检测同样按Latin-1写回；合法的UTF-8文件仍按UTF-8处理。
+    args.encoding = TextEncoding::Auto;
+    args.target = "café".to_string();
+    args.replacement = "thé".to_string();
+    run(&args).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"th\xe9 br\xfbl\xe9e");
+    fs::write(&path, "café").unwrap();
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "thé");
+
+    // 替换后的文本无法用Latin-1表示时报错，不写入文件。
+    fs::write(&path, b"caf\xe9").unwrap();
+    args.encoding = "latin1".parse().unwrap();
+    args.replacement = "茶".to_string();
+    assert!(matches!(
+        run(&args),
+        Err(Error::Unencodable(_, "windows-1252"))
+    ));
+    assert_eq!(fs::read(&path).unwrap(), b"caf\xe9");
+
+    assert_eq!("utf8".parse(), Ok(TextEncoding::Utf8));
+    assert!("utf-16le".parse::<TextEncoding>().is_err());
+    assert!("klingon".parse::<TextEncoding>().is_err());
+}
+
+/// 替换模板，在`Captures::expand`的基础上支持`${N:-默认值}`形式的条件引用。
+///
+/// `${N:-默认值}`在第`N`个（或名为`N`的）捕获组非空时展开为该组的文本，
+/// 为空或未参与匹配时展开为默认值，默认值中同样可以使用`$1`等引用。
+/// 其余部分与普通模板完全相同，如`$$`表示字面的`$`。
+#[derive(Debug, Clone, PartialEq)]
+struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    /// 交给`Captures::expand`展开的普通模板文本。
+    Text(String),
+    /// `${group:-default}`。
+    Fallback { group: String, default: String },
+}
+
+impl Template {
+    fn new(template: &str) -> Template {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            text.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if rest.starts_with("$$") {
+                text.push_str("$$");
+                rest = &rest[2..];
+                continue;
+            }
+            let fallback = rest
+                .strip_prefix("${")
+                .and_then(|inner| inner.split_once('}'))
+                .and_then(|(inner, after)| {
+                    let (group, default) = inner.split_once(":-")?;
+                    Some((group, default, after))
+                });
+            match fallback {
+                Some((group, default, after)) => {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Fallback {
+                        group: group.to_string(),
+                        default: default.to_string(),
+                    });
+                    rest = after;
+                }
+                None => {
+                    text.push('$');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Template { pieces }
+    }
+
+    /// 与`new`相同，但要求模板中按名称引用的每个捕获组都在`target`中存在。
+    ///
+    /// `Captures::expand`把不存在的组展开为空字符串，名字拼错时会悄悄删掉文本，
+    /// 因此在替换之前就返回`Error::NoSuchName`。按序号的引用不做检查。
+    fn checked(template: &str, target: &str) -> Result<Template, Error> {
+        let regex = Regex::new(target)?;
+        let template = Template::new(template);
+        for name in template.names() {
+            if !regex.capture_names().flatten().any(|n| n == name) {
+                return Err(Error::NoSuchName(name.to_string()));
+            }
+        }
+        Ok(template)
+    }
+
+    /// 模板中按名称引用的捕获组，包括默认值中的引用。
+    fn names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => names.extend(references(text)),
+                Piece::Fallback { group, default } => {
+                    names.push(group.as_str());
+                    names.extend(references(default));
+                }
+            }
+        }
+        names.retain(|name| name.parse::<usize>().is_err());
+        names
+    }
+}
+
+/// 按`Captures::expand`的规则找出模板文本`text`中`$name`和`${name}`引用的组名或序号。
+///
+/// `$name`中的名字尽可能长地由字母、数字和下划线组成，因此`$1a`引用的是名为`1a`的组。
+fn references(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+        } else if let Some((name, after)) = rest
+            .strip_prefix('{')
+            .and_then(|inner| inner.split_once('}'))
+        {
+            names.push(name);
+            rest = after;
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if end > 0 {
+                names.push(&rest[..end]);
+            }
+            rest = &rest[end..];
+        }
+    }
+    names
+}
+
+#[test]
+fn test_template_names() {
+    let args = Arguments {
+        target: r"(?P<year>\d{4})-(?P<mon>\d{2})".to_string(),
+        replacement: "${mon}/${year}".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "2024-03, 1999-12").unwrap(),
+        ("03/2024, 12/1999".to_string(), 2)
+    );
+
+    // 引用不存在的组名时报错，而不是替换为空字符串。
+    let args = Arguments {
+        replacement: "${month}/${year}".to_string(),
+        ..args
+    };
+    assert!(matches!(
+        replace_text(&args, "2024-03"),
+        Err(Error::NoSuchName(name)) if name == "month"
+    ));
+    let args = Arguments {
+        replacement: "$mon_$year".to_string(),
+        ..args
+    };
+    assert!(matches!(
+        replace_text(&args, "2024-03"),
+        Err(Error::NoSuchName(name)) if name == "mon_"
+    ));
+
+    // `parse_args`检查过的模板直接使用，不再重新检查。
+    let args = Arguments {
+        template: Some(Template::checked("${mon}/${year}", &args.target).unwrap()),
+        ..args
+    };
+    assert_eq!(
+        replace_text(&args, "2024-03").unwrap(),
+        ("03/2024".to_string(), 1)
+    );
+
+    assert_eq!(references("$$a ${b}c $1 $d-e $"), vec!["b", "1", "d"]);
+    assert_eq!(Template::new("$1 ${x:-$y} $$z").names(), vec!["x", "y"]);
+}
+
+impl Replacer for &Template {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => caps.expand(text, dst),
+                Piece::Fallback { group, default } => {
+                    let value = match group.parse::<usize>() {
+                        Ok(index) => caps.get(index),
+                        Err(_) => caps.name(group),
+                    };
+                    match value {
+                        Some(m) if !m.as_str().is_empty() => dst.push_str(m.as_str()),
+                        _ => caps.expand(default, dst),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 由多个`--pair 目标=替换文本`组成、一遍完成的替换。
+///
+/// 各目标合并为一个正则表达式`(目标1)|(目标2)|...`，每处匹配只替换一次，
+/// 替换后的文本不会再被其他目标匹配，因此`a=b`和`b=a`可以交换`a`和`b`。
+/// 替换文本按字面使用，不展开`$1`等引用。
+#[derive(Debug, Default)]
+struct Pairs {
+    /// 合并后的正则表达式。
+    target: String,
+    /// 每个目标外层捕获组的序号及其替换文本，按`--pair`的顺序排列。
+    branches: Vec<(usize, String)>,
+}
+
+impl Pairs {
+    fn new(pairs: &[(String, String)]) -> Result<Pairs, regex::Error> {
+        let mut branches = Vec::new();
+        let mut alternatives = Vec::new();
+        let mut group = 1;
+        for (target, replacement) in pairs {
+            // 目标自身的捕获组排在外层捕获组之后，使后面目标的序号依次后移。
+            let inner = Regex::new(target)?.captures_len() - 1;
+            branches.push((group, replacement.clone()));
+            alternatives.push(format!("({})", target));
+            group += 1 + inner;
+        }
+        Ok(Pairs {
+            target: alternatives.join("|"),
+            branches,
+        })
+    }
+}
+
+impl Replacer for &Pairs {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        // 交替中最先匹配的分支即为匹配到的目标。
+        if let Some((_, replacement)) = self
+            .branches
+            .iter()
+            .find(|(group, _)| caps.get(*group).is_some())
+        {
+            dst.push_str(replacement);
+        }
+    }
+}
+
+/// 将`--pair`的值在第一个未转义的`=`处分为目标和替换文本。目标中的`=`可以写作`\=`。
+fn split_pair(value: &str) -> Option<(String, String)> {
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '=' if !escaped => return Some((value[..i].to_string(), value[i + 1..].to_string())),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+#[test]
+fn test_pairs() {
+    let pairs = Pairs::new(&[split_pair("a=b").unwrap(), split_pair("b=a").unwrap()]).unwrap();
+    let args = Arguments {
+        target: pairs.target.clone(),
+        pairs: Some(pairs),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "abba cab").unwrap(),
+        ("baab cba".to_string(), 6)
+    );
+
+    // 目标自身的捕获组不影响后面目标的分派。
+    let pairs = Pairs::new(&[
+        ("(x)(y)".to_string(), "1".to_string()),
+        (r"(?P<z>z)\=".to_string(), "2".to_string()),
+        ("w".to_string(), "$3".to_string()),
+    ])
+    .unwrap();
+    assert_eq!(
+        pairs.branches,
+        [
+            (1, "1".to_string()),
+            (4, "2".to_string()),
+            (6, "$3".to_string())
+        ]
+    );
+    assert_eq!(
+        Regex::new(&pairs.target)
+            .unwrap()
+            .replace_all("xy z= w", &pairs),
+        "1 2 $3"
+    );
+
+    assert_eq!(
+        split_pair(r"a\==b=c"),
+        Some((r"a\=".to_string(), "b=c".to_string()))
+    );
+    assert_eq!(
+        split_pair(r"a\\=b"),
+        Some((r"a\\".to_string(), "b".to_string()))
+    );
+    assert_eq!(split_pair("ab"), None);
+    assert!(Pairs::new(&[("(".to_string(), String::new())]).is_err());
+}
+
+#[test]
+fn test_template_fallback() {
+    let template = Template::new("${1:-anonymous}@${host:-localhost}");
+    let target = Regex::new(r"(\w*)@(?P<host>[\w.]*)").unwrap();
+    assert_eq!(
+        target.replace_all("alice@example.com", &template),
+        "alice@example.com"
+    );
+    assert_eq!(target.replace_all("@", &template), "anonymous@localhost");
+
+    // 默认值中可以引用其他捕获组，`$$`仍表示字面的`$`。
+    let template = Template::new("$$${2:-$1}");
+    assert_eq!(
+        Regex::new(r"(\w+)=(\w*)")
+            .unwrap()
+            .replace_all("a=b c=", &template),
+        "$b $c"
+    );
+}
+
+#[derive(Debug)]
+enum Error {
+    Regex(regex::Error),
+    NoSuchGroup(usize),
+    /// 替换模板按名称引用了正则表达式中不存在的捕获组。
+    NoSuchName(String),
+    Read(PathBuf, std::io::Error),
+    Write(PathBuf, std::io::Error),
+    /// 递归模式下有`failed`个文件处理失败，各自的错误已经打印。
+    Failed {
+        failed: usize,
+        total: usize,
+    },
+    /// 给定`--fail-if-no-match`时，所有输入中都没有匹配。
+    NoMatch(String),
+    /// `--binary`模式下替换文本中的转义序列无效。
+    InvalidEscape(String),
+    /// `--rename`时第一个文件的新名字与第二个文件冲突，后者是另一个要改名的文件或已经存在的文件。
+    RenameCollision(PathBuf, PathBuf),
+    /// `--rename`替换后得到的名字为空或含有路径分隔符。
+    InvalidName(PathBuf, String),
+    /// `--check`时这些文件替换后会发生变化。
+    WouldChange(Vec<PathBuf>),
+    /// 单文件模式下OUTPUT与INPUT是同一个文件，却没有给定`--in-place`。
+    SameFile(PathBuf),
+    /// 所有输入中共有`total`处匹配，超过了`--max-total`给定的`limit`。
+    TooManyMatches {
+        total: usize,
+        limit: usize,
+    },
+    /// 给定`--prompt-before-overwrite`时没有确认覆盖已经存在的OUTPUT。
+    NotOverwritten(PathBuf),
+    /// 替换后的文本含有无法用文件原来的编码表示的字符。
+    Unencodable(PathBuf, &'static str),
+    /// 给定`--fail-on-binary`时递归模式下遇到了二进制文件。
+    BinaryFile(PathBuf),
+    /// 无法创建`--jobs`所用的线程池。
+    ThreadPool(rayon::ThreadPoolBuildError),
+    /// `--output-dir`下第一个文件的副本与第二个文件冲突，后者是另一个输入文件。
+    OutputCollision(PathBuf, PathBuf),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Regex(e) => write!(f, "替换文本失败: {}", e),
+            Error::NoSuchGroup(group) => {
+                write!(f, "替换文本失败: 正则表达式中不存在第{}个捕获组", group)
+            }
+            Error::NoSuchName(name) => {
+                write!(f, "替换文本失败: 正则表达式中不存在名为'{}'的捕获组", name)
+            }
+            Error::Read(path, e) => write!(f, "读取文件'{}'失败: {:?}", path.display(), e),
+            Error::Write(path, e) => write!(f, "写入文件'{}'失败: {:?}", path.display(), e),
+            Error::Failed { failed, total } => {
+                write!(f, "{}个文件中有{}个处理失败", total, failed)
+            }
+            Error::NoMatch(target) => write!(f, "没有找到与'{}'匹配的文本", target),
+            Error::InvalidEscape(message) => write!(f, "无效的转义序列: {}", message),
+            Error::RenameCollision(path, other) => write!(
+                f,
+                "无法重命名'{}'：新名字与'{}'冲突",
+                path.display(),
+                other.display()
+            ),
+            Error::InvalidName(path, name) => write!(
+                f,
+                "无法将'{}'重命名为'{}'：不是有效的文件名",
+                path.display(),
+                name
+            ),
+            Error::WouldChange(paths) => {
+                write!(f, "{}个文件需要修改:", paths.len())?;
+                for path in paths {
+                    write!(f, "\n    {}", path.display())?;
+                }
+                Ok(())
+            }
+            Error::SameFile(path) => write!(
+                f,
+                "INPUT与OUTPUT是同一个文件'{}'；确实要就地修改时请加上--in-place",
+                path.display()
+            ),
+            Error::TooManyMatches { total, limit } => write!(
+                f,
+                "共有{}处匹配，超过了--max-total的上限{}，正则表达式可能过于宽泛；没有修改任何文件",
+                total, limit
+            ),
+            Error::NotOverwritten(path) => write!(
+                f,
+                "没有覆盖已经存在的'{}'；不是交互运行时请加上--force",
+                path.display()
+            ),
+            Error::Unencodable(path, encoding) => write!(
+                f,
+                "无法写回'{}'：替换后的文本含有{}编码无法表示的字符",
+                path.display(),
+                encoding
+            ),
+            Error::BinaryFile(path) => write!(
+                f,
+                "'{}'是二进制文件（含有NUL字节）；要处理其中的字节请加上--binary",
+                path.display()
+            ),
+            Error::ThreadPool(e) => write!(f, "创建线程池失败: {}", e),
+            Error::OutputCollision(path, other) => write!(
+                f,
+                "无法将'{}'写入--output-dir：与'{}'冲突",
+                path.display(),
+                other.display()
+            ),
+        }
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::Regex(e)
+    }
 }
 
 fn main() {
     let args = parse_args();
+    env_logger::Builder::new()
+        .filter_level(log_level(args.verbosity))
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
 
-    let data = match fs::read_to_string(&args.filename) {
-        Ok(v) => v,
+    match run(&args) {
+        Ok(matches) if args.exit_count => std::process::exit(exit_count(matches)),
+        Ok(_) => {}
         Err(e) => {
-            eprintln!(
-                "{} 读取文件'{}'失败: {:?}",
-                "错误:".red().bold(),
-                args.filename,
-                e
-            );
+            eprintln!("{} {}", "错误:".red().bold(), e);
             std::process::exit(1);
         }
+    }
+}
+
+/// `--exit-count`时的退出状态：匹配的总次数，超过255时为255。
+fn exit_count(matches: usize) -> i32 {
+    matches.min(255) as i32
+}
+
+/// 由`-v`和`-q`得到的日志级别。
+///
+/// 默认只输出跳过的文件和处理结果的汇总；`-q`只输出错误；
+/// `-v`还会输出打开和写入的每个文件以及匹配次数，`-vv`输出全部日志。
+fn log_level(verbosity: i32) -> log::LevelFilter {
+    match verbosity {
+        i32::MIN..=-1 => log::LevelFilter::Error,
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+#[test]
+fn test_log_level() {
+    use log::LevelFilter;
+
+    // -q只输出错误，多次-q与一次相同。
+    assert_eq!(log_level(-1), LevelFilter::Error);
+    assert_eq!(log_level(i32::MIN), LevelFilter::Error);
+    // 默认输出跳过的文件和汇总，-v再加上每个文件的记录，-vv及以上输出全部日志。
+    assert_eq!(log_level(0), LevelFilter::Info);
+    assert_eq!(log_level(1), LevelFilter::Debug);
+    assert_eq!(log_level(2), LevelFilter::Trace);
+    assert_eq!(log_level(3), LevelFilter::Trace);
+}
+
+/// 按`args`执行替换：默认读取INPUT写入OUTPUT，递归模式下就地修改各路径下的所有文件。
+///
+/// 成功替换后在标准输出上报告每个文件的替换次数，`-q`时不报告。
+/// 给定`--fail-if-no-match`且所有输入中都没有匹配时返回`Error::NoMatch`。
+/// 成功时返回所有输入中匹配的总次数。
+fn run(args: &Arguments) -> Result<usize, Error> {
+    let matches = if args.stdin_lines {
+        run_stdin_lines(args, std::io::stdin().lock(), &mut std::io::stdout().lock())?
+    } else if args.count_lines {
+        run_count_lines(args)?
+    } else if args.distinct {
+        run_distinct(args)?
+    } else if args.files_with_matches {
+        run_files_with_matches(args, &mut std::io::stdout().lock())?
+    } else if args.check {
+        run_check(args)?
+    } else if args.dry_run {
+        run_dry(args)?
+    } else if args.rename {
+        let renames = run_rename(&collect_files(args)?, args)?;
+        if args.verbosity >= 0 {
+            for (from, to) in &renames {
+                println!("将'{}'重命名为'{}'", from.display(), to.display());
+            }
+        }
+        renames.len()
+    } else {
+        let counts = if args.recursive {
+            let files = collect_files(args)?;
+            if args.output_dir.is_some() {
+                check_output_paths(&files, args)?;
+            }
+            if let Some(limit) = args.max_total {
+                check_total(&files, args, limit)?;
+            }
+            if args.atomic {
+                run_atomic(&files, args)?
+            } else {
+                run_files(&files, args)?
+            }
+        } else {
+            vec![run_single(args)?]
+        };
+        if args.verbosity >= 0 {
+            write_report(&mut std::io::stdout(), args, &counts)
+                .map_err(|e| Error::Write(PathBuf::from("<stdout>"), e))?;
+        }
+        write_report_json(args, &counts)?;
+        counts.iter().map(|(_, count)| count).sum()
     };
 
-    let _replace_data = match replace(&args.target, &args.replacement, &data) {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("{} 替换文本失败: {:?}", "错误:".red().bold(), e);
-            std::process::exit(1);
+    if args.fail_if_no_match && matches == 0 {
+        return Err(Error::NoMatch(args.target.clone()));
+    }
+    Ok(matches)
+}
+
+#[test]
+fn test_fail_if_no_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, "hello world").unwrap();
+    let mut args = Arguments {
+        target: "world".to_string(),
+        replacement: "rust".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        output: output.to_str().unwrap().to_string(),
+        fail_if_no_match: true,
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "hello rust");
+
+    args.target = "planet".to_string();
+    assert!(matches!(run(&args), Err(Error::NoMatch(target)) if target == "planet"));
+
+    // 不给定该选项时没有匹配不算错误。
+    args.fail_if_no_match = false;
+    run(&args).unwrap();
+}
+
+/// 向`out`报告`counts`中每个文件的替换次数。
+///
+/// 单个文件时只输出一行；递归模式下为每个有替换的文件输出一行，最后再输出总数。
+fn write_report(
+    out: &mut impl std::io::Write,
+    args: &Arguments,
+    counts: &[(PathBuf, usize)],
+) -> std::io::Result<()> {
+    for (path, count) in counts {
+        if !args.recursive || *count > 0 {
+            writeln!(
+                out,
+                "在'{}'中替换了{}处'{}'",
+                path.display(),
+                count,
+                args.target
+            )?;
         }
+    }
+    if args.recursive {
+        let total: usize = counts.iter().map(|(_, count)| count).sum();
+        let files = counts.iter().filter(|(_, count)| *count > 0).count();
+        writeln!(
+            out,
+            "共替换了{}处'{}'，涉及{}个文件",
+            total, args.target, files
+        )?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, "a-b-c-d").unwrap();
+    let mut args = Arguments {
+        target: "-".to_string(),
+        replacement: "+".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        output: dir.path().join("output.txt").to_str().unwrap().to_string(),
+        ..Default::default()
     };
+    let counts = vec![run_single(&args).unwrap()];
+    let mut out = Vec::new();
+    write_report(&mut out, &args, &counts).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!("在'{}'中替换了3处'-'\n", input.display())
+    );
 
-    match fs::write(&args.output, &data) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!(
-                "{} 读取文件'{}'失败: {:?}",
-                "错误:".red().bold(),
-                args.filename,
-                e
-            );
-            std::process::exit(1);
+    // 递归模式下跳过没有替换的文件，并输出总数。
+    let other = dir.path().join("other.txt");
+    fs::write(&other, "none").unwrap();
+    args.recursive = true;
+    let counts = run_files(&[input.clone(), other], &args).unwrap();
+    let mut out = Vec::new();
+    write_report(&mut out, &args, &counts).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "在'{}'中替换了3处'-'\n共替换了3处'-'，涉及1个文件\n",
+            input.display()
+        )
+    );
+}
+
+/// 给定`--report`时把`counts`的JSON汇总写入报告文件。
+fn write_report_json(args: &Arguments, counts: &[(PathBuf, usize)]) -> Result<(), Error> {
+    if let Some(path) = &args.report {
+        fs::write(path, report_json(counts)).map_err(|e| Error::Write(PathBuf::from(path), e))?;
+    }
+    Ok(())
+}
+
+/// `--report`写入的JSON汇总：处理的文件数、有替换的文件数、替换总数和每个文件的替换次数。
+fn report_json(counts: &[(PathBuf, usize)]) -> String {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let changed = counts.iter().filter(|(_, count)| *count > 0).count();
+    let files: Vec<String> = counts
+        .iter()
+        .map(|(path, count)| {
+            format!(
+                r#"{{"file":{},"replacements":{}}}"#,
+                json_string(&path.to_string_lossy()),
+                count
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"files_processed":{},"files_changed":{},"total_replacements":{},"files":[{}]}}"#,
+        counts.len(),
+        changed,
+        total,
+        files.join(",")
+    )
+}
+
+#[test]
+fn test_report_json() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "foo foo foo").unwrap();
+    fs::write(dir.path().join("b \"quoted\".txt"), "foo").unwrap();
+    fs::write(dir.path().join("c.txt"), "bar").unwrap();
+    let report = dir.path().join("report.json");
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "baz".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        report: Some(report.to_str().unwrap().to_string()),
+        verbosity: -1,
+        ..Default::default()
+    };
+    run(&args).unwrap();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(json["files_processed"], 3);
+    assert_eq!(json["files_changed"], 2);
+    let files = json["files"].as_array().unwrap();
+    let sum: u64 = files
+        .iter()
+        .map(|file| file["replacements"].as_u64().unwrap())
+        .sum();
+    assert_eq!(json["total_replacements"], sum);
+    assert_eq!(sum, 4);
+    assert_eq!(
+        files[1]["file"],
+        dir.path().join("b \"quoted\".txt").to_str().unwrap()
+    );
+
+    // 有文件处理失败时，报告中仍然记下成功处理的文件。
+    fs::remove_file(&report).unwrap();
+    let missing = dir.path().join("missing.txt");
+    assert!(matches!(
+        run_files(&[dir.path().join("c.txt"), missing], &args),
+        Err(Error::Failed {
+            failed: 1,
+            total: 2
+        })
+    ));
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(json["files_processed"], 1);
+}
+
+/// 读取INPUT，替换后写入OUTPUT，返回INPUT的路径和匹配的次数。
+///
+/// 给定`--prompt-before-overwrite`且OUTPUT已经存在时，先在标准输入上询问是否覆盖。
+fn run_single(args: &Arguments) -> Result<(PathBuf, usize), Error> {
+    let stdin = std::io::stdin();
+    let answers = stdin.is_terminal().then(|| stdin.lock());
+    run_single_with(args, answers)
+}
+
+/// `run_single`的实现，从`answers`读取是否覆盖OUTPUT的回答。
+///
+/// `answers`为`None`表示不是交互运行（标准输入不是终端），此时除非给定`--force`，
+/// 否则不覆盖已经存在的OUTPUT，返回`Error::NotOverwritten`。
+fn run_single_with(
+    args: &Arguments,
+    answers: Option<impl std::io::BufRead>,
+) -> Result<(PathBuf, usize), Error> {
+    let same_file = same_file(&args.filename, &args.output);
+    if same_file.is_some() && !args.in_place {
+        return Err(Error::SameFile(PathBuf::from(&args.filename)));
+    }
+    if args.prompt_before_overwrite && !args.force && Path::new(&args.output).exists() {
+        let confirmed = match answers {
+            Some(answers) => confirm_overwrite(&args.output, answers, &mut std::io::stderr())
+                .map_err(|e| Error::Read(PathBuf::from("<stdin>"), e))?,
+            None => false,
+        };
+        if !confirmed {
+            return Err(Error::NotOverwritten(PathBuf::from(&args.output)));
         }
     }
+    log::debug!("打开'{}'", args.filename);
+    let read_error = |e| Error::Read(PathBuf::from(&args.filename), e);
+    let bytes = fs::read(&args.filename).map_err(read_error)?;
+    let (replace_data, matches) = if args.binary {
+        replace_bytes(args, &bytes)?
+    } else {
+        let path = Path::new(&args.filename);
+        let (data, encoding) = args.encoding.decode(path, bytes)?;
+        let (replaced, matches) = replace_text(args, &data)?;
+        (encode_text(path, replaced, encoding)?, matches)
+    };
+    log::debug!("已处理'{}'：{}处匹配", args.filename, matches);
+    if let Some(limit) = args.max_total.filter(|&limit| matches > limit) {
+        return Err(Error::TooManyMatches {
+            total: matches,
+            limit,
+        });
+    }
+    log::debug!("写入'{}'", args.output);
+    if let Some(path) = same_file {
+        // 先写入临时文件再改名，不会在写到一半时留下残缺的原文件。
+        let temp = write_temp(&path, &replace_data)?;
+        commit_temp(&temp, &path, args)?;
+    } else {
+        fs::write(&args.output, replace_data)
+            .map_err(|e| Error::Write(PathBuf::from(&args.output), e))?;
+    }
+    Ok((PathBuf::from(&args.filename), matches))
 }
 
-fn print_usage() {
-    eprintln!(
-        "{} - 将一个字符串替换为另一个字符串",
-        "quickreplace".green()
+/// 向`prompt`询问是否覆盖`path`，从`answers`读取一行回答，只有回答`y`时返回`true`。
+fn confirm_overwrite(
+    path: &str,
+    mut answers: impl std::io::BufRead,
+    prompt: &mut impl std::io::Write,
+) -> std::io::Result<bool> {
+    write!(prompt, "是否覆盖'{}'？[y/N] ", path)?;
+    prompt.flush()?;
+    let mut answer = String::new();
+    answers.read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+#[test]
+fn test_prompt_before_overwrite() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let output = dir.path().join("output.txt");
+    fs::write(&input, "hello world").unwrap();
+    fs::write(&output, "keep me").unwrap();
+    let mut args = Arguments {
+        target: "world".to_string(),
+        replacement: "rust".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        output: output.to_str().unwrap().to_string(),
+        prompt_before_overwrite: true,
+        ..Default::default()
+    };
+
+    // 回答n、空行或不是交互运行时都不覆盖。
+    for answers in [Some("n\n".as_bytes()), Some("\n".as_bytes()), None] {
+        assert!(matches!(
+            run_single_with(&args, answers),
+            Err(Error::NotOverwritten(path)) if path == output
+        ));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "keep me");
+    }
+
+    run_single_with(&args, Some("y\n".as_bytes())).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "hello rust");
+
+    // --force时不询问，非交互运行也会覆盖。
+    fs::write(&output, "keep me").unwrap();
+    args.force = true;
+    run_single_with(&args, None::<&[u8]>).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "hello rust");
+
+    let mut prompt = Vec::new();
+    assert!(!confirm_overwrite("out.txt", "yes\n".as_bytes(), &mut prompt).unwrap());
+    assert_eq!(
+        String::from_utf8(prompt).unwrap(),
+        "是否覆盖'out.txt'？[y/N] "
     );
-    eprintln!("Usage: quickreplace <target> <replacement> <INPUT> <OUTPUT>");
 }
 
-fn parse_args() -> Arguments {
-    let args: Vec<String> = env::args().skip(1).collect();
+/// `input`与`output`解析为同一个文件时，返回该文件的规范路径。
+///
+/// `output`尚不存在时不可能与`input`相同。
+fn same_file(input: &str, output: &str) -> Option<PathBuf> {
+    let input = fs::canonicalize(input).ok()?;
+    let output = fs::canonicalize(output).ok()?;
+    (input == output).then_some(input)
+}
 
-    if args.len() != 4 {
-        print_usage();
-        eprintln!(
-            "{} 参数数量不符：需要4个参数，传入了{}个。",
-            "错误:".red().bold(),
-            args.len()
-        );
-        std::process::exit(1);
+#[test]
+fn test_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, "hello world").unwrap();
+    let mut args = Arguments {
+        target: "world".to_string(),
+        replacement: "rust".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        // 写法不同但指向同一个文件。
+        output: dir
+            .path()
+            .join(".")
+            .join("input.txt")
+            .to_str()
+            .unwrap()
+            .to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(run_single(&args), Err(Error::SameFile(path)) if path.ends_with("input.txt")));
+    assert_eq!(fs::read_to_string(&input).unwrap(), "hello world");
+
+    args.in_place = true;
+    assert_eq!(run_single(&args).unwrap().1, 1);
+    assert_eq!(fs::read_to_string(&input).unwrap(), "hello rust");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+/// 就地替换`files`中的每个文件。
+///
+/// 每个文件都先写入临时文件再改名覆盖，写到一半失败时不会留下残缺的文件。
+/// 默认在某个文件读写失败时打印错误并继续处理其余文件，最后返回`Error::Failed`汇总；
+/// 给定`--abort-on-error`时在第一个失败处停止并返回该错误。
+/// 正则表达式本身的错误与具体文件无关，总是立即返回。成功时返回每个文件中匹配的次数。
+///
+/// 给定`--jobs N`时先在N个线程中处理全部文件，再按`files`的顺序汇总结果，
+/// 因此报告的错误和返回的次数与逐个处理时相同。`--abort-on-error`时一旦有文件失败，
+/// 各线程就不再开始处理新的文件，但已经开始处理的文件仍会完成，失败之后的文件可能已经被修改。
+fn run_files(files: &[PathBuf], args: &Arguments) -> Result<Vec<(PathBuf, usize)>, Error> {
+    let mut parallel = if args.jobs > 1 {
+        Some(replace_files_parallel(files, args)?.into_iter())
+    } else {
+        None
+    };
+    let mut failed = 0;
+    let mut modified = 0;
+    let mut counts = Vec::new();
+    for path in files {
+        let result = match &mut parallel {
+            Some(results) => match results.next().unwrap() {
+                Some(result) => result,
+                // 其他文件出错后没有处理的文件。
+                None => continue,
+            },
+            None => replace_file_in_place(path, args),
+        };
+        match result {
+            Ok((count, changed)) => {
+                counts.push((path.clone(), count));
+                modified += changed as usize;
+            }
+            Err(e) if !aborts(&e, args) => {
+                log::error!("{}", e);
+                failed += 1;
+            }
+            Err(e) => return Err(e),
+        }
     }
+    log::info!("处理了{}个文件，修改了{}个", files.len(), modified);
 
-    Arguments {
-        target: args[0].clone(),
-        replacement: args[1].clone(),
-        filename: args[2].clone(),
-        output: args[3].clone(),
+    if failed > 0 {
+        // 部分文件失败时，其余文件已经修改，报告仍然要写出来。
+        write_report_json(args, &counts)?;
+        return Err(Error::Failed {
+            failed,
+            total: files.len(),
+        });
     }
+    Ok(counts)
 }
 
-fn replace(target: &str, replacement: &str, text: &str) -> Result<String, regex::Error> {
-    let regex = Regex::new(target)?;
-    Ok(regex.replace_all(text, replacement).to_string())
+/// 就地替换单个文件`path`，返回其中匹配的次数以及文件是否被修改。
+///
+/// 给定`--output-dir`时不修改`path`，而是把结果写入`mirror_path`给出的位置，没有变化的文件原样复制。
+fn replace_file_in_place(path: &Path, args: &Arguments) -> Result<(usize, bool), Error> {
+    let (replaced, count) = replace_file(path, args)?;
+    if let Some(target) = mirror_path(path, args) {
+        write_mirror(path, &target, replaced.as_deref())?;
+        return Ok((count, replaced.is_some()));
+    }
+    let Some(replaced) = replaced else {
+        return Ok((count, false));
+    };
+    log::debug!("写入'{}'", path.display());
+    // 每个文件都先写入临时文件再改名；符号链接要替换的是它指向的文件。
+    let target = fs::canonicalize(path).map_err(|e| Error::Write(path.to_path_buf(), e))?;
+    let temp = write_temp(&target, &replaced)?;
+    commit_temp(&temp, &target, args)?;
+    Ok((count, true))
+}
+
+/// `run_files`遇到错误`e`时是否立即返回，而不是记下后继续处理其余的文件。
+fn aborts(e: &Error, args: &Arguments) -> bool {
+    args.abort_on_error || !matches!(e, Error::Read(..) | Error::Write(..))
+}
+
+/// `replace_file_in_place`处理一个文件的结果。
+type FileResult = Result<(usize, bool), Error>;
+
+/// 在`args.jobs`个线程中对`files`逐个调用`replace_file_in_place`，按`files`的顺序返回结果。
+///
+/// 某个文件的错误会让`run_files`立即返回时，其余线程不再开始处理新的文件，
+/// 这些没有处理的文件的结果为`None`。
+fn replace_files_parallel(
+    files: &[PathBuf],
+    args: &Arguments,
+) -> Result<Vec<Option<FileResult>>, Error> {
+    use rayon::prelude::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let stop = AtomicBool::new(false);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs)
+        .build()
+        .map_err(Error::ThreadPool)?;
+    Ok(pool.install(|| {
+        files
+            .par_iter()
+            .map(|path| {
+                if stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let result = replace_file_in_place(path, args);
+                if matches!(&result, Err(e) if aborts(e, args)) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                Some(result)
+            })
+            .collect()
+    }))
+}
+
+/// `path`在`--output-dir`下对应的位置：相对于它所在的命令行路径的部分接在输出目录之后。
+///
+/// 命令行路径本身是文件时只保留文件名，因此绝对路径的输入也会写在输出目录之内。
+/// 没有给定`--output-dir`时返回`None`。
+fn mirror_path(path: &Path, args: &Arguments) -> Option<PathBuf> {
+    let dir = args.output_dir.as_ref()?;
+    let root = args
+        .paths
+        .iter()
+        .map(Path::new)
+        .find(|root| path.starts_with(root))?;
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        _ => Path::new(path.file_name()?),
+    };
+    Some(Path::new(dir).join(relative))
+}
+
+/// 在写入任何文件之前检查`files`在`--output-dir`下的位置：两个文件对应同一个位置，
+/// 或者某个位置就是某个输入文件时，返回`Error::OutputCollision`。
+fn check_output_paths(files: &[PathBuf], args: &Arguments) -> Result<(), Error> {
+    let inputs: HashMap<PathBuf, &PathBuf> = files
+        .iter()
+        .filter_map(|path| Some((fs::canonicalize(path).ok()?, path)))
+        .collect();
+    let mut targets: HashMap<PathBuf, &PathBuf> = HashMap::new();
+    for path in files {
+        let Some(target) = mirror_path(path, args) else {
+            continue;
+        };
+        if let Some(other) = targets.get(&target) {
+            return Err(Error::OutputCollision(path.clone(), (*other).clone()));
+        }
+        if let Some(input) = fs::canonicalize(&target).ok().and_then(|t| inputs.get(&t)) {
+            return Err(Error::OutputCollision(path.clone(), (*input).clone()));
+        }
+        targets.insert(target, path);
+    }
+    Ok(())
+}
+
+/// 把`path`替换后的内容`replaced`写入`target`，按需创建上级目录；`replaced`为`None`时复制原文件。
+fn write_mirror(path: &Path, target: &Path, replaced: Option<&[u8]>) -> Result<(), Error> {
+    let write_error = |e| Error::Write(target.to_path_buf(), e);
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir).map_err(write_error)?;
+    }
+    log::debug!("写入'{}'", target.display());
+    match replaced {
+        Some(replaced) => fs::write(target, replaced).map_err(write_error),
+        None => fs::copy(path, target).map(drop).map_err(write_error),
+    }
+}
+
+#[test]
+fn test_output_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("in");
+    let output = dir.path().join("out");
+    fs::create_dir_all(input.join("sub").join("deep")).unwrap();
+    fs::write(input.join("a.txt"), "foo").unwrap();
+    fs::write(input.join("sub").join("b.txt"), "foo foo").unwrap();
+    fs::write(input.join("sub").join("deep").join("c.txt"), "none").unwrap();
+
+    let mut args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![input.to_str().unwrap().to_string()],
+        output_dir: Some(output.to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    assert_eq!(run(&args).unwrap(), 3);
+    assert_eq!(fs::read_to_string(output.join("a.txt")).unwrap(), "bar");
+    assert_eq!(
+        fs::read_to_string(output.join("sub").join("b.txt")).unwrap(),
+        "bar bar"
+    );
+    // 没有匹配的文件原样复制，镜像目录树是完整的。
+    assert_eq!(
+        fs::read_to_string(output.join("sub").join("deep").join("c.txt")).unwrap(),
+        "none"
+    );
+    // 原文件不变。
+    assert_eq!(fs::read_to_string(input.join("a.txt")).unwrap(), "foo");
+    assert_eq!(
+        fs::read_to_string(input.join("sub").join("b.txt")).unwrap(),
+        "foo foo"
+    );
+
+    // 两个命令行路径下的同名文件会写到同一个位置，不写入任何文件。
+    let other = dir.path().join("other");
+    fs::create_dir(&other).unwrap();
+    fs::write(other.join("a.txt"), "foo").unwrap();
+    args.paths.push(other.to_str().unwrap().to_string());
+    args.output_dir = Some(dir.path().join("out2").to_str().unwrap().to_string());
+    match run(&args) {
+        Err(Error::OutputCollision(path, first)) => {
+            assert_eq!(path, other.join("a.txt"));
+            assert_eq!(first, input.join("a.txt"));
+        }
+        other => panic!("意外的结果: {:?}", other),
+    }
+    assert!(!dir.path().join("out2").exists());
+
+    // 输出目录就是输入目录时，结果会覆盖原文件。
+    args.paths.pop();
+    args.output_dir = Some(input.to_str().unwrap().to_string());
+    assert!(matches!(run(&args), Err(Error::OutputCollision(..))));
+    assert_eq!(fs::read_to_string(input.join("a.txt")).unwrap(), "foo");
+}
+
+#[test]
+fn test_jobs() {
+    let run_in = |jobs: usize| {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..200 {
+            let sub = dir.path().join(format!("d{}", i % 7));
+            fs::create_dir_all(&sub).unwrap();
+            fs::write(sub.join(format!("{}.txt", i)), "foo ".repeat(i % 5)).unwrap();
+        }
+        let args = Arguments {
+            target: "foo".to_string(),
+            replacement: "bar".to_string(),
+            recursive: true,
+            paths: vec![dir.path().to_str().unwrap().to_string()],
+            jobs,
+            ..Default::default()
+        };
+        let files = collect_files(&args).unwrap();
+        let relative = |path: &Path| path.strip_prefix(dir.path()).unwrap().to_path_buf();
+        let counts: Vec<(PathBuf, usize)> = run_files(&files, &args)
+            .unwrap()
+            .into_iter()
+            .map(|(path, count)| (relative(&path), count))
+            .collect();
+        let contents: Vec<(PathBuf, String)> = files
+            .iter()
+            .map(|path| (relative(path), fs::read_to_string(path).unwrap()))
+            .collect();
+
+        // 不是合法的UTF-8，读取时出错；其余文件照常处理，最后汇总。
+        fs::write(dir.path().join("d3").join("bad.txt"), b"foo \xff").unwrap();
+        let files = collect_files(&args).unwrap();
+        let failed = match run_files(&files, &args) {
+            Err(Error::Failed { failed, total }) => (failed, total),
+            other => panic!("unexpected result: {:?}", other),
+        };
+        (counts, contents, failed)
+    };
+
+    let serial = run_in(1);
+    let parallel = run_in(8);
+    assert_eq!(parallel, serial);
+    assert_eq!(serial.0.len(), 200);
+    assert_eq!(serial.0.iter().map(|(_, count)| count).sum::<usize>(), 400);
+    assert!(serial.1.iter().all(|(_, text)| !text.contains("foo")));
+    assert_eq!(serial.2, (1, 201));
+}
+
+#[test]
+fn test_abort_on_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    let reset = || {
+        fs::write(&files[0], "foo").unwrap();
+        // 不是合法的UTF-8，读取时出错。
+        fs::write(&files[1], b"foo \xff").unwrap();
+        fs::write(&files[2], "foo").unwrap();
+    };
+    let mut args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+
+    // 默认继续处理其余的文件，最后汇总。
+    reset();
+    assert!(matches!(
+        run(&args),
+        Err(Error::Failed {
+            failed: 1,
+            total: 3
+        })
+    ));
+    assert_eq!(fs::read_to_string(&files[0]).unwrap(), "bar");
+    assert_eq!(fs::read_to_string(&files[2]).unwrap(), "bar");
+
+    // 遇到第一个错误就停止，之后的文件保持不变。
+    reset();
+    args.abort_on_error = true;
+    match run(&args) {
+        Err(Error::Read(path, _)) => assert_eq!(path, files[1]),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(fs::read_to_string(&files[0]).unwrap(), "bar");
+    assert_eq!(fs::read_to_string(&files[2]).unwrap(), "foo");
+
+    // 并行处理时出错后也不再开始处理新的文件；只有一个线程时按顺序处理。
+    reset();
+    let results = replace_files_parallel(&files, &args).unwrap();
+    assert!(matches!(results[0], Some(Ok((1, true)))));
+    assert!(matches!(results[1], Some(Err(Error::Read(..)))));
+    assert!(results[2].is_none());
+    assert_eq!(fs::read_to_string(&files[2]).unwrap(), "foo");
+
+    args.jobs = 4;
+    reset();
+    match run_files(&files, &args) {
+        Err(Error::Read(path, _)) => assert_eq!(path, files[1]),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+/// 按`args`替换`files`中每个文件的名字（不含所在目录），返回实际进行的重命名。
+///
+/// 先算出所有的新名字再统一改名：两个文件的新名字相同，或新名字已经被某个文件占用时，
+/// 返回`Error::RenameCollision`且不改动任何文件。名字不是UTF-8或替换后不变的文件保持原样。
+fn run_rename(files: &[PathBuf], args: &Arguments) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut renames = Vec::new();
+    let mut targets: HashMap<PathBuf, &PathBuf> = HashMap::new();
+    for path in files {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let (new_name, _) = replace_text(args, name)?;
+        if new_name == name {
+            continue;
+        }
+        if new_name.is_empty() || new_name.contains(std::path::is_separator) {
+            return Err(Error::InvalidName(path.clone(), new_name));
+        }
+        let target = path.with_file_name(&new_name);
+        if let Some(other) = targets.get(&target) {
+            return Err(Error::RenameCollision(path.clone(), (*other).clone()));
+        }
+        if target.symlink_metadata().is_ok() {
+            return Err(Error::RenameCollision(path.clone(), target));
+        }
+        targets.insert(target.clone(), path);
+        renames.push((path.clone(), target));
+    }
+
+    for (from, to) in &renames {
+        log::debug!("将'{}'重命名为'{}'", from.display(), to.display());
+        fs::rename(from, to).map_err(|e| Error::Write(from.clone(), e))?;
+    }
+    log::info!("处理了{}个文件，重命名了{}个", files.len(), renames.len());
+    Ok(renames)
+}
+
+#[test]
+fn test_rename() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["foo1.txt", "foo2.txt", "other.txt"] {
+        fs::write(dir.path().join(name), "foo").unwrap();
+    }
+    let mut args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        rename: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    let mut names: Vec<String> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["bar1.txt", "bar2.txt", "other.txt"]);
+    // 只改名字，不改内容。
+    assert_eq!(
+        fs::read_to_string(dir.path().join("bar1.txt")).unwrap(),
+        "foo"
+    );
+
+    // 两个文件都会变成baz.txt，报错且不改动任何文件。
+    args.target = r"bar\d".to_string();
+    args.replacement = "baz".to_string();
+    let files = collect_files(&args).unwrap();
+    match run_rename(&files, &args) {
+        Err(Error::RenameCollision(path, other)) => {
+            assert_eq!(path, dir.path().join("bar2.txt"));
+            assert_eq!(other, dir.path().join("bar1.txt"));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(dir.path().join("bar1.txt").exists() && dir.path().join("bar2.txt").exists());
+
+    // 新名字已被其他文件占用。
+    args.target = "bar1".to_string();
+    args.replacement = "other".to_string();
+    assert!(matches!(
+        run_rename(&files, &args),
+        Err(Error::RenameCollision(_, other)) if other == dir.path().join("other.txt")
+    ));
+
+    args.target = r"\.txt".to_string();
+    args.replacement = "/x".to_string();
+    assert!(matches!(
+        run_rename(&files, &args),
+        Err(Error::InvalidName(_, name)) if name == "bar1/x"
+    ));
+}
+
+/// 收集`args.paths`中的文件，目录会被递归展开，结果按路径排序。
+///
+/// 与`args.exclude`中任何一个模式匹配的文件会被跳过，匹配的目录则整个不再展开。
+///
+/// 命令行上直接给出的路径即使是符号链接也总会被跟随。遍历中遇到的符号链接默认这样处理：
+/// 指向目录的不进入；指向文件的只在目标位于所在的目录树（即该命令行路径）之内时处理。
+/// 给定`--follow-symlinks`时跟随所有符号链接，已经访问过的目录不会再次进入，因此链接成环也能结束。
+/// 无论哪种方式，经由不同路径到达的同一个文件只处理一次，保留排序后的第一个路径。
+fn collect_files(args: &Arguments) -> Result<Vec<PathBuf>, Error> {
+    struct Walk<'a> {
+        args: &'a Arguments,
+        /// 当前命令行路径解析后的绝对路径。
+        root: PathBuf,
+        visited: HashSet<PathBuf>,
+        /// 找到的文件及其解析后的绝对路径。
+        files: Vec<(PathBuf, PathBuf)>,
+    }
+
+    impl Walk<'_> {
+        fn walk(&mut self, path: &Path, is_root: bool) -> Result<(), Error> {
+            let read_error = |e| Error::Read(path.to_path_buf(), e);
+            if self.args.exclude.is_match(path) {
+                log::debug!("已排除'{}'", path.display());
+                return Ok(());
+            }
+            let link = fs::symlink_metadata(path).map_err(read_error)?;
+            let is_link = link.file_type().is_symlink() && !is_root;
+            let (metadata, canonical) = match (fs::metadata(path), fs::canonicalize(path)) {
+                (Ok(metadata), Ok(canonical)) => (metadata, canonical),
+                _ if is_link => {
+                    log::debug!("已跳过'{}'（无效的符号链接）", path.display());
+                    return Ok(());
+                }
+                (Err(e), _) | (_, Err(e)) => return Err(read_error(e)),
+            };
+            if is_link && !self.args.follow_symlinks {
+                if metadata.is_dir() {
+                    log::debug!("已跳过'{}'（指向目录的符号链接）", path.display());
+                    return Ok(());
+                }
+                if !canonical.starts_with(&self.root) {
+                    log::info!("已跳过'{}'（符号链接指向目录树之外）", path.display());
+                    return Ok(());
+                }
+            }
+
+            if metadata.is_dir() {
+                if !self.visited.insert(canonical) {
+                    log::debug!("已跳过'{}'（目录已访问过）", path.display());
+                    return Ok(());
+                }
+                for entry in fs::read_dir(path).map_err(read_error)? {
+                    let entry = entry.map_err(read_error)?;
+                    self.walk(&entry.path(), false)?;
+                }
+            } else if metadata.is_file() {
+                if let Some(cutoff) = self.args.since {
+                    if metadata.modified().map_err(read_error)? < cutoff {
+                        log::debug!("已跳过'{}'（修改时间早于--since）", path.display());
+                        return Ok(());
+                    }
+                }
+                self.files.push((path.to_path_buf(), canonical));
+            }
+            Ok(())
+        }
+    }
+
+    let mut walk = Walk {
+        args,
+        root: PathBuf::new(),
+        visited: HashSet::new(),
+        files: Vec::new(),
+    };
+    for path in &args.paths {
+        let path = Path::new(path);
+        walk.root = fs::canonicalize(path).map_err(|e| Error::Read(path.to_path_buf(), e))?;
+        walk.walk(path, true)?;
+    }
+
+    let mut files = walk.files;
+    files.sort();
+    let mut seen = HashSet::new();
+    Ok(files
+        .into_iter()
+        .filter(|(_, canonical)| seen.insert(canonical.clone()))
+        .map(|(path, _)| path)
+        .collect())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let sub = dir.path().join("a");
+    fs::create_dir(&sub).unwrap();
+    fs::write(sub.join("file.txt"), "x").unwrap();
+    fs::write(outside.path().join("other.txt"), "x").unwrap();
+    // 指向父目录的链接形成环；另外两个链接分别指向树内和树外的文件。
+    symlink(dir.path(), sub.join("loop")).unwrap();
+    symlink(sub.join("file.txt"), dir.path().join("link.txt")).unwrap();
+    symlink(outside.path().join("other.txt"), dir.path().join("out.txt")).unwrap();
+
+    let mut args = Arguments {
+        target: "x".to_string(),
+        replacement: "xx".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+    assert_eq!(collect_files(&args).unwrap(), vec![sub.join("file.txt")]);
+
+    // 跟随链接时环只走一次，树外的文件也会被处理，但每个文件只替换一次。
+    args.follow_symlinks = true;
+    assert_eq!(
+        collect_files(&args).unwrap(),
+        vec![sub.join("file.txt"), dir.path().join("out.txt")]
+    );
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(sub.join("file.txt")).unwrap(), "xx");
+    assert_eq!(
+        fs::read_to_string(outside.path().join("other.txt")).unwrap(),
+        "xx"
+    );
+}
+
+#[test]
+fn test_exclude() {
+    let dir = tempfile::tempdir().unwrap();
+    let vendor = dir.path().join("vendor");
+    fs::create_dir(&vendor).unwrap();
+    fs::write(vendor.join("lib.rs"), "foo").unwrap();
+    fs::write(dir.path().join("main.rs"), "foo").unwrap();
+    fs::write(dir.path().join("notes.txt"), "foo").unwrap();
+
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        exclude: parse_exclude(&["**/vendor/**".to_string(), "*.txt".to_string()]).unwrap(),
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(vendor.join("lib.rs")).unwrap(), "foo");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("notes.txt")).unwrap(),
+        "foo"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("main.rs")).unwrap(),
+        "bar"
+    );
+
+    assert!(parse_exclude(&["[".to_string()]).is_err());
+}
+
+/// 解析`--since`的时长，如`30s`、`15m`、`2h`、`3d`或`1w`，单位不能省略。
+fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = s.split_at(split);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let number: u64 = number.parse().ok()?;
+    Some(Duration::from_secs(number.checked_mul(seconds)?))
+}
+
+#[test]
+fn test_since() {
+    assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+    assert_eq!(parse_duration("3d"), Some(Duration::from_secs(3 * 86400)));
+    assert_eq!(parse_duration("45s"), Some(Duration::from_secs(45)));
+    assert_eq!(parse_duration("10"), None);
+    assert_eq!(parse_duration("h"), None);
+    assert_eq!(parse_duration("2y"), None);
+
+    let dir = tempfile::tempdir().unwrap();
+    let recent = dir.path().join("recent.txt");
+    let old = dir.path().join("sub").join("old.txt");
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(&recent, "foo").unwrap();
+    fs::write(&old, "foo").unwrap();
+    let week_ago = SystemTime::now() - Duration::from_secs(7 * 86400);
+    fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(week_ago)
+        .unwrap();
+
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        since: Some(SystemTime::now() - parse_duration("3d").unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(collect_files(&args).unwrap(), vec![recent.clone()]);
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&recent).unwrap(), "bar");
+    assert_eq!(fs::read_to_string(&old).unwrap(), "foo");
+}
+
+/// 将`--exclude`给出的各个glob模式组合成一个`GlobSet`。
+fn parse_exclude(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    builder.build()
+}
+
+/// 先把所有修改写入临时文件，全部成功后再逐个重命名覆盖原文件。
+///
+/// 任何一个文件读取、替换或写入失败时，删除已写好的临时文件并返回错误，原文件都不会被修改。
+/// 与`replace_file_in_place`一样，符号链接要替换的是它指向的文件，临时文件也写在那个文件旁边。
+/// 成功时返回每个文件中匹配的次数。
+fn run_atomic(files: &[PathBuf], args: &Arguments) -> Result<Vec<(PathBuf, usize)>, Error> {
+    let mut pending = Vec::new();
+    let mut counts = Vec::new();
+    for path in files {
+        let prepared = replace_file(path, args).and_then(|(replaced, count)| {
+            counts.push((path.clone(), count));
+            let Some(replaced) = replaced else {
+                return Ok(None);
+            };
+            let target = fs::canonicalize(path).map_err(|e| Error::Write(path.clone(), e))?;
+            let temp = write_temp(&target, &replaced)?;
+            Ok(Some((target, temp)))
+        });
+        match prepared {
+            Ok(Some(prepared)) => pending.push(prepared),
+            Ok(None) => {}
+            Err(e) => {
+                for (_, temp) in &pending {
+                    let _ = fs::remove_file(temp);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    let modified = pending.len();
+    for (target, temp) in pending {
+        log::debug!("写入'{}'", target.display());
+        commit_temp(&temp, &target, args)?;
+    }
+    log::info!("处理了{}个文件，修改了{}个", files.len(), modified);
+    Ok(counts)
+}
+
+/// 在写入任何文件之前统计`files`中的匹配总数，超过`limit`时返回`Error::TooManyMatches`。
+///
+/// 每个文件都按`replace_file`的方式计数，`--once`、`--before`等限制同样生效。
+/// 读取失败的文件不计入总数，留给之后真正替换时按`--abort-on-error`的规则处理。
+fn check_total(files: &[PathBuf], args: &Arguments, limit: usize) -> Result<(), Error> {
+    let mut total = 0;
+    for path in files {
+        match replace_file(path, args).map(|(_, count)| count) {
+            Ok(count) => total += count,
+            Err(Error::Read(..)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    log::debug!("共有{}处匹配，上限为{}", total, limit);
+    if total > limit {
+        return Err(Error::TooManyMatches { total, limit });
+    }
+    Ok(())
+}
+
+#[test]
+fn test_max_total() {
+    let dir = tempfile::tempdir().unwrap();
+    let broad = dir.path().join("broad.txt");
+    let small = dir.path().join("small.txt");
+    let text = "a".repeat(5000);
+    fs::write(&broad, &text).unwrap();
+    fs::write(&small, "a").unwrap();
+    let mut args = Arguments {
+        target: ".".to_string(),
+        replacement: "b".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        max_total: Some(100),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run(&args),
+        Err(Error::TooManyMatches {
+            total: 5001,
+            limit: 100
+        })
+    ));
+    assert_eq!(fs::read_to_string(&broad).unwrap(), text);
+    assert_eq!(fs::read_to_string(&small).unwrap(), "a");
+
+    // 单文件模式同样先检查再写入。
+    let output = dir.path().join("output.txt");
+    args.recursive = false;
+    args.filename = broad.to_str().unwrap().to_string();
+    args.output = output.to_str().unwrap().to_string();
+    assert!(matches!(
+        run(&args),
+        Err(Error::TooManyMatches { total: 5000, .. })
+    ));
+    assert!(!output.exists());
+
+    args.max_total = Some(5000);
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "b".repeat(5000));
+
+    // 递归模式下只计入真正会替换的匹配。
+    let words = dir.path().join("words.txt");
+    fs::remove_file(&broad).unwrap();
+    fs::remove_file(&small).unwrap();
+    fs::remove_file(&output).unwrap();
+    fs::write(&words, "foo foo foo").unwrap();
+    let mut args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        once: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        max_total: Some(1),
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&words).unwrap(), "bar foo foo");
+
+    fs::write(&words, "cat cats cat cat").unwrap();
+    args.target = "cat".to_string();
+    args.replacement = "dog".to_string();
+    args.once = false;
+    args.after = Some("s".to_string());
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&words).unwrap(), "cat dogs cat cat");
+
+    fs::write(&words, "cat scat cat").unwrap();
+    args.after = None;
+    args.before = Some("s".to_string());
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&words).unwrap(), "cat sdog cat");
+}
+
+/// 将`contents`写入与`path`同目录的临时文件，并沿用`path`的权限，返回临时文件的路径。
+///
+/// 临时文件与原文件位于同一目录，之后的`fs::rename`才能原子地覆盖原文件。
+fn write_temp(path: &Path, contents: &[u8]) -> Result<PathBuf, Error> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.quickreplace-tmp", name));
+    let write = || {
+        fs::write(&temp, contents)?;
+        fs::set_permissions(&temp, fs::metadata(path)?.permissions())
+    };
+    write().map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        Error::Write(temp.clone(), e)
+    })?;
+    Ok(temp)
+}
+
+/// 用`write_temp`写好的临时文件`temp`改名覆盖`path`，失败时删除临时文件。
+///
+/// 给定`--sync`时，改名前先把临时文件刷到磁盘，改名后再刷新所在的目录，
+/// 这样断电后`path`要么是原来的内容，要么是完整的新内容，不会是空文件。默认不刷新，速度更快。
+fn commit_temp(temp: &Path, path: &Path, args: &Arguments) -> Result<(), Error> {
+    commit_temp_with(temp, path, args.sync.then_some(sync_path))
+}
+
+/// `commit_temp`的实现，`sync`不为`None`时用它把临时文件和目录刷到磁盘。
+fn commit_temp_with(
+    temp: &Path,
+    path: &Path,
+    mut sync: Option<impl FnMut(&Path) -> std::io::Result<()>>,
+) -> Result<(), Error> {
+    let mut rename = || {
+        if let Some(sync) = &mut sync {
+            sync(temp)?;
+        }
+        fs::rename(temp, path)
+    };
+    rename().map_err(|e| {
+        let _ = fs::remove_file(temp);
+        Error::Write(path.to_path_buf(), e)
+    })?;
+
+    // 只有Unix上可以打开目录并刷新。
+    if let Some(sync) = sync.as_mut().filter(|_| cfg!(unix)) {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        sync(dir).map_err(|e| Error::Write(dir.to_path_buf(), e))?;
+    }
+    Ok(())
+}
+
+/// 把文件或目录`path`的内容和元数据刷到磁盘。
+fn sync_path(path: &Path) -> std::io::Result<()> {
+    fs::File::open(path)?.sync_all()
+}
+
+#[test]
+fn test_sync() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    fs::write(&path, "foo").unwrap();
+
+    // 给定--sync时先刷新临时文件，改名后再刷新目录。
+    let mut synced = Vec::new();
+    let temp = write_temp(&path, b"bar").unwrap();
+    let record = |p: &Path| {
+        synced.push(p.to_path_buf());
+        sync_path(p)
+    };
+    commit_temp_with(&temp, &path, Some(record)).unwrap();
+    let mut expected = vec![temp.clone()];
+    if cfg!(unix) {
+        expected.push(dir.path().to_path_buf());
+    }
+    assert_eq!(synced, expected);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bar");
+
+    // 无论是否刷新，递归模式下的输出都相同，且不留下临时文件。
+    for sync in [false, true] {
+        fs::write(&path, "foo").unwrap();
+        let args = Arguments {
+            target: "foo".to_string(),
+            replacement: "baz".to_string(),
+            recursive: true,
+            paths: vec![dir.path().to_str().unwrap().to_string()],
+            sync,
+            ..Default::default()
+        };
+        run(&args).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "baz");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
+
+#[test]
+fn test_atomic_rollback() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    for file in &files {
+        fs::write(file, "foo").unwrap();
+    }
+    // 在第二个文件的临时文件位置放一个目录，使写入临时文件失败。
+    fs::create_dir(dir.path().join(".b.txt.quickreplace-tmp")).unwrap();
+
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: files
+            .iter()
+            .map(|f| f.to_str().unwrap().to_string())
+            .collect(),
+        atomic: true,
+        ..Default::default()
+    };
+    assert!(matches!(run(&args), Err(Error::Write(..))));
+    for file in &files {
+        assert_eq!(fs::read_to_string(file).unwrap(), "foo");
+    }
+    assert!(!dir.path().join(".a.txt.quickreplace-tmp").exists());
+
+    // 去掉障碍后，所有文件一起被修改。
+    fs::remove_dir(dir.path().join(".b.txt.quickreplace-tmp")).unwrap();
+    run(&args).unwrap();
+    for file in &files {
+        assert_eq!(fs::read_to_string(file).unwrap(), "bar");
+    }
+
+    // 跟随符号链接时替换的是链接指向的文件，链接本身保持不变。
+    #[cfg(unix)]
+    {
+        let outside = tempfile::tempdir().unwrap();
+        let real = outside.path().join("real.txt");
+        fs::write(&real, "foo").unwrap();
+        let link = dir.path().join("link.txt");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let args = Arguments {
+            target: "foo".to_string(),
+            replacement: "bar".to_string(),
+            recursive: true,
+            paths: vec![dir.path().to_str().unwrap().to_string()],
+            follow_symlinks: true,
+            atomic: true,
+            ..Default::default()
+        };
+        run(&args).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&real).unwrap(), "bar");
+        assert!(!outside.path().join(".real.txt.quickreplace-tmp").exists());
+    }
+}
+
+/// 读取并替换递归模式下的单个文件，内容有变化时返回替换后的文本，否则为`None`。
+///
+/// 含有NUL字节的文件被视为二进制文件，直接跳过；
+/// 超过`--max-file-size`的文件在读取之前就会被跳过，并打印提示。
+///
+/// 同时返回文件中匹配的次数，跳过的文件为0。`--binary`模式下不跳过二进制文件。
+fn replace_file(path: &Path, args: &Arguments) -> Result<(Option<Vec<u8>>, usize), Error> {
+    let (changed, matches) = if args.binary {
+        let data = match read_file(path, args)? {
+            Some(data) => data,
+            None => return Ok((None, 0)),
+        };
+        if let Some(guard) = &args.if_contains {
+            if !guard.is_match(&data) {
+                log::debug!("已跳过'{}'（内容不匹配--if-contains）", path.display());
+                return Ok((None, 0));
+            }
+        }
+        let (replaced, matches) = replace_bytes(args, &data)?;
+        ((replaced != data).then_some(replaced), matches)
+    } else {
+        let (data, encoding) = match read_text_file(path, args)? {
+            Some(text) => text,
+            None => return Ok((None, 0)),
+        };
+        let (replaced, matches) = replace_text(args, &data)?;
+        let changed = if replaced != data {
+            Some(encode_text(path, replaced, encoding)?)
+        } else {
+            None
+        };
+        (changed, matches)
+    };
+    log::debug!("已处理'{}'：{}处匹配", path.display(), matches);
+    Ok((changed, matches))
+}
+
+/// 读取递归模式下要处理的文件`path`，过大的文件、二进制文件和内容不匹配`--if-contains`的文件返回`None`。
+///
+/// 给定`--fail-on-binary`时二进制文件返回`Error::BinaryFile`。
+///
+/// 按`--encoding`解码，同时返回写回时应使用的编码。
+fn read_text_file(
+    path: &Path,
+    args: &Arguments,
+) -> Result<Option<(String, &'static Encoding)>, Error> {
+    let bytes = match read_file(path, args)? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    if bytes.contains(&0) {
+        if args.fail_on_binary {
+            return Err(Error::BinaryFile(path.to_path_buf()));
+        }
+        log::trace!("已跳过'{}'（二进制文件）", path.display());
+        return Ok(None);
+    }
+    let (text, encoding) = args.encoding.decode(path, bytes)?;
+    if let Some(guard) = &args.if_contains {
+        if !guard.is_match(text.as_bytes()) {
+            log::debug!("已跳过'{}'（内容不匹配--if-contains）", path.display());
+            return Ok(None);
+        }
+    }
+    Ok(Some((text, encoding)))
+}
+
+/// 读取递归模式下要处理的文件`path`的全部字节，过大的文件返回`None`。
+fn read_file(path: &Path, args: &Arguments) -> Result<Option<Vec<u8>>, Error> {
+    if let Some(max) = args.max_file_size {
+        let len = fs::metadata(path)
+            .map_err(|e| Error::Read(path.to_path_buf(), e))?
+            .len();
+        if len > max {
+            log::info!("已跳过'{}'（文件过大：{}字节）", path.display(), len);
+            return Ok(None);
+        }
+    }
+
+    log::debug!("打开'{}'", path.display());
+    let bytes = fs::read(path).map_err(|e| Error::Read(path.to_path_buf(), e))?;
+    Ok(Some(bytes))
+}
+
+#[test]
+fn test_fail_on_binary() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = dir.path().join("a.txt");
+    let binary = dir.path().join("b.bin");
+    fs::write(&text, "foo").unwrap();
+    fs::write(&binary, b"foo\0foo").unwrap();
+
+    let mut args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+    // 默认静默跳过二进制文件。
+    run(&args).unwrap();
+    assert_eq!(fs::read_to_string(&text).unwrap(), "bar");
+    assert_eq!(fs::read(&binary).unwrap(), b"foo\0foo");
+
+    // 给定--fail-on-binary时报错，错误信息中有该文件的路径。
+    args.fail_on_binary = true;
+    match run(&args) {
+        Err(e @ Error::BinaryFile(_)) => {
+            assert!(e.to_string().contains(&binary.display().to_string()))
+        }
+        other => panic!("意外的结果: {:?}", other),
+    }
+    assert_eq!(fs::read(&binary).unwrap(), b"foo\0foo");
+}
+
+#[test]
+fn test_if_contains() {
+    let dir = tempfile::tempdir().unwrap();
+    let generated = dir.path().join("generated.rs");
+    let handwritten = dir.path().join("handwritten.rs");
+    fs::write(&generated, "// @generated\nfoo();\n").unwrap();
+    fs::write(&handwritten, "foo();\n").unwrap();
+
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        if_contains: Some(regex::bytes::Regex::new("@generated").unwrap()),
+        ..Default::default()
+    };
+    // 跳过的文件不计入匹配数。
+    assert_eq!(run(&args).unwrap(), 1);
+    assert_eq!(
+        fs::read_to_string(&generated).unwrap(),
+        "// @generated\nbar();\n"
+    );
+    assert_eq!(fs::read_to_string(&handwritten).unwrap(), "foo();\n");
+}
+
+#[test]
+fn test_max_file_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let small = dir.path().join("small.txt");
+    let large = dir.path().join("sub").join("large.txt");
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(&small, "foo").unwrap();
+    fs::write(&large, "foo ".repeat(100)).unwrap();
+    fs::write(dir.path().join("binary.bin"), b"foo\0foo").unwrap();
+
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        max_file_size: Some(100),
+        ..Default::default()
+    };
+    run(&args).unwrap();
+
+    assert_eq!(fs::read_to_string(&small).unwrap(), "bar");
+    assert_eq!(fs::read_to_string(&large).unwrap(), "foo ".repeat(100));
+    assert_eq!(
+        fs::read(dir.path().join("binary.bin")).unwrap(),
+        b"foo\0foo"
+    );
+}
+
+fn print_usage() {
+    eprintln!(
+        "{} - 将一个字符串替换为另一个字符串",
+        "quickreplace".green()
+    );
+    eprintln!("Usage: quickreplace [OPTIONS] <target> <replacement> <INPUT> <OUTPUT>");
+    eprintln!("       quickreplace [OPTIONS] --transform NAME <target> <INPUT> <OUTPUT>");
+    eprintln!("       quickreplace -r [OPTIONS] <target> <replacement> <PATH>...");
+    eprintln!("       quickreplace [-r] --count-lines <target> <INPUT|PATH>...");
+    eprintln!("       quickreplace [-r] [OPTIONS] --replacement-env VAR <target> <INPUT> <OUTPUT>|<PATH>...");
+    eprintln!("       quickreplace [-r] --distinct <target> <INPUT|PATH>...");
+    eprintln!("       quickreplace [-r] -l <target> <INPUT|PATH>...");
+    eprintln!("       quickreplace [-r] --check <target> <replacement> <INPUT|PATH>...");
+    eprintln!("       quickreplace [-r] [OPTIONS] --pair <target>=<replacement>... <INPUT> <OUTPUT>|<PATH>...");
+    eprintln!("       quickreplace --stdin-lines [OPTIONS] <target> <replacement>");
+    eprintln!("       quickreplace [-r] [OPTIONS] --strip-trailing-ws <INPUT> <OUTPUT>|<PATH>...");
+    eprintln!("Options:");
+    eprintln!(
+        "    --group N                    只替换第N个捕获组所匹配的部分，匹配的其余文本保持不变"
+    );
+    eprintln!(
+        "    --replacement-env VAR        从环境变量VAR读取替换模板，代替命令行上的<replacement>"
+    );
+    eprintln!(
+        "    --interpret-escapes          将替换模板中的\\n、\\r、\\t、\\0和\\\\换成对应的字符，$引用不受影响"
+    );
+    eprintln!(
+        "    --plain                      将<target>当作普通字符串，其中的元字符不再有特殊含义"
+    );
+    eprintln!(
+        "    --pair T=R                   将T替换为字面的R，可重复使用，所有目标在一遍中同时替换"
+    );
+    eprintln!(
+        "    --strip-trailing-ws          去掉每行末尾的空格和制表符，代替<target>和<replacement>"
+    );
+    eprintln!(
+        "    --transform upper|lower|trim 将匹配的文本转为大写、小写或去掉两端空白，代替替换模板"
+    );
+    eprintln!(
+        "    -r, --recursive              就地修改各PATH（目录会被递归展开）下的文件，跳过二进制文件"
+    );
+    eprintln!(
+        "    --fail-on-binary             递归模式下遇到二进制文件时报错并以非零状态退出，而不是跳过"
+    );
+    eprintln!(
+        "    --follow-symlinks            递归模式下跟随指向目录和目录树之外的符号链接，默认跳过"
+    );
+    eprintln!(
+        "    --exclude PATTERN            递归模式下跳过与glob模式PATTERN匹配的路径，可重复使用"
+    );
+    eprintln!("    --rename                     递归模式下替换文件名而不是文件内容，新名字冲突时不改动任何文件");
+    eprintln!("    --max-file-size BYTES        递归模式下跳过大于BYTES字节的文件");
+    eprintln!("    --since DURATION             递归模式下只处理最近DURATION内修改过的文件，如30m、2h、3d或1w");
+    eprintln!("    --if-contains PATTERN        递归模式下只修改内容与正则表达式PATTERN匹配的文件，其余文件跳过");
+    eprintln!("    --max-total N                所有文件的匹配总数超过N时不写入任何文件并报错");
+    eprintln!("    --output-dir DIR             递归模式下不修改原文件，把结果按相对于PATH的路径写入DIR下，没有变化的文件原样复制");
+    eprintln!("    --report FILE                替换完成后将处理和修改的文件数、替换总数及每个文件的替换次数以JSON写入FILE");
+    eprintln!("    --jobs N                     递归模式下用N个线程同时处理文件，错误和汇总仍按路径顺序报告");
+    eprintln!("    --abort-on-error             递归模式下遇到第一个处理失败的文件就停止，默认继续并在最后汇总");
+    eprintln!(
+        "    -v, --verbose                输出打开和写入的每个文件及匹配次数，重复两次输出全部日志"
+    );
+    eprintln!("    -q, --quiet                  只输出错误");
+    eprintln!("    --fail-if-no-match           所有输入中都没有匹配时以非零状态退出");
+    eprintln!("    --dry-run                    只打印将要进行的替换，不写入任何文件");
+    eprintln!(
+        "    --exit-count                 --dry-run时以匹配的总次数作为退出状态，超过255时为255"
+    );
+    eprintln!("    --check                      不写入任何文件，有文件需要修改时列出这些文件并以非零状态退出");
+    eprintln!("    --count-lines                对每个有匹配的行打印“行号: 匹配次数”，不做替换");
+    eprintln!("    --distinct                   打印匹配到的每个不同的字符串及其出现次数，按次数从多到少排列，不做替换");
+    eprintln!(
+        "    -l, --files-with-matches     只打印至少有一处匹配的文件的路径，每行一个，不做替换"
+    );
+    eprintln!("    --stdin-lines                从标准输入逐行读取，每行替换后立即写到标准输出，匹配不会跨行");
+    eprintln!("    --before TEXT                只替换紧接在TEXT之后的匹配，TEXT本身不被替换");
+    eprintln!("    --after TEXT                 只替换后面紧跟着TEXT的匹配，TEXT本身不被替换");
+    eprintln!("    --skip-comments LANG         （实验性）跳过注释中的匹配：c、cpp、rust、java、js为//和/* */，shell、python、ruby为#");
+    eprintln!("    --line-mode                  逐行替换，`^`和`$`对应每行的开头和结尾，匹配不会跨行；默认整个文件一起替换");
+    eprintln!("    --per-line-limit N           逐行替换，每行最多替换前N处匹配，匹配不会跨行");
+    eprintln!("    --once                       每个文件只替换第一处匹配，找到后不再继续查找");
+    eprintln!("    --encoding auto|latin1|...   按给定的编码读取文本文件并按原编码写回，auto时不是UTF-8的文件按Latin-1处理");
+    eprintln!("    --binary                     按字节处理文件，<target>中的\\xHH匹配单个字节，<replacement>可用\\xHH等转义");
+    eprintln!(
+        "    --summary-only               与--dry-run一起使用，只打印每个文件的匹配次数和总数"
+    );
+    eprintln!("    -C, --context N              与--dry-run一起使用，在每处替换前后显示N行上下文");
+    eprintln!("    --json                       与--dry-run一起使用，以JSON数组输出每处替换");
+    eprintln!(
+        "    --atomic                     递归模式下所有文件都成功后才一起写入，否则不修改任何文件"
+    );
+    eprintln!("    --sync                       用临时文件替换原文件时，改名前后把文件和目录刷到磁盘，防止断电后留下空文件");
+    eprintln!("    --in-place                   允许OUTPUT与INPUT是同一个文件，先写入临时文件再替换原文件");
+    eprintln!("    --prompt-before-overwrite    OUTPUT已经存在时询问是否覆盖，只有回答y时才写入；不是交互运行时不覆盖");
+    eprintln!(
+        "    --force                      与--prompt-before-overwrite一起使用，不询问直接覆盖"
+    );
+    eprintln!("<target>为-时从标准输入读取正则表达式，末尾的换行符会被去掉");
+    eprintln!("<replacement>中可用$1或${{name}}引用捕获组，${{1:-默认值}}在捕获组为空时使用默认值");
+}
+
+fn parse_args() -> Arguments {
+    let mut group = None;
+    let mut transform = None;
+    let mut recursive = false;
+    let mut max_file_size = None;
+    let mut since = None;
+    let mut max_total = None;
+    let mut report = None;
+    let mut output_dir = None;
+    let mut atomic = false;
+    let mut sync = false;
+    let mut in_place = false;
+    let mut prompt_before_overwrite = false;
+    let mut force = false;
+    let mut dry_run = false;
+    let mut exit_count = false;
+    let mut json = false;
+    let mut abort_on_error = false;
+    let mut jobs = 1;
+    let mut verbosity = 0;
+    let mut fail_if_no_match = false;
+    let mut exclude = Vec::new();
+    let mut follow_symlinks = false;
+    let mut fail_on_binary = false;
+    let mut if_contains = None;
+    let mut count_lines = false;
+    let mut distinct = false;
+    let mut files_with_matches = false;
+    let mut check = false;
+    let mut stdin_lines = false;
+    let mut context = 0;
+    let mut per_line_limit = None;
+    let mut once = false;
+    let mut line_mode = false;
+    let mut before = None;
+    let mut after = None;
+    let mut skip_comments = None;
+    let mut summary_only = false;
+    let mut binary = false;
+    let mut encoding = TextEncoding::Utf8;
+    let mut rename = false;
+    let mut pairs = Vec::new();
+    let mut strip_trailing_ws = false;
+    let mut replacement_env = None;
+    let mut plain = false;
+    let mut interpret_escapes = false;
+    let mut args: Vec<String> = Vec::new();
+
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--group" => group = Some(parse_flag_value(&mut iter, "--group")),
+            "--transform" => transform = Some(parse_flag_value(&mut iter, "--transform")),
+            "-r" | "--recursive" => recursive = true,
+            "--atomic" => atomic = true,
+            "--sync" => sync = true,
+            "--in-place" => in_place = true,
+            "--prompt-before-overwrite" => prompt_before_overwrite = true,
+            "--force" => force = true,
+            "--dry-run" => dry_run = true,
+            "--exit-count" => exit_count = true,
+            "--json" => json = true,
+            "--abort-on-error" => abort_on_error = true,
+            "--jobs" => jobs = parse_flag_value(&mut iter, "--jobs"),
+            "--fail-if-no-match" => fail_if_no_match = true,
+            "--count-lines" => count_lines = true,
+            "--distinct" => distinct = true,
+            "-l" | "--files-with-matches" => files_with_matches = true,
+            "--check" => check = true,
+            "--stdin-lines" => stdin_lines = true,
+            "--summary-only" => summary_only = true,
+            "--binary" => binary = true,
+            "--encoding" => encoding = parse_flag_value(&mut iter, "--encoding"),
+            "--rename" => rename = true,
+            "--plain" => plain = true,
+            "--interpret-escapes" => interpret_escapes = true,
+            "--strip-trailing-ws" => strip_trailing_ws = true,
+            "--replacement-env" => {
+                replacement_env = Some(parse_flag_value::<String>(&mut iter, "--replacement-env"))
+            }
+            "--pair" => {
+                let value: String = parse_flag_value(&mut iter, "--pair");
+                match split_pair(&value) {
+                    Some(pair) => pairs.push(pair),
+                    None => {
+                        print_usage();
+                        eprintln!("{} --pair的值'{}'中没有=", "错误:".red().bold(), value);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "-C" | "--context" => context = parse_flag_value(&mut iter, "--context"),
+            "--before" => before = Some(parse_flag_value(&mut iter, "--before")),
+            "--after" => after = Some(parse_flag_value(&mut iter, "--after")),
+            "--skip-comments" => {
+                skip_comments = Some(parse_flag_value(&mut iter, "--skip-comments"))
+            }
+            "--line-mode" => line_mode = true,
+            "--once" => once = true,
+            "--per-line-limit" => {
+                per_line_limit = Some(parse_flag_value(&mut iter, "--per-line-limit"))
+            }
+            "-v" | "--verbose" => verbosity += 1,
+            "-vv" => verbosity += 2,
+            "-q" | "--quiet" => verbosity = -1,
+            "--follow-symlinks" => follow_symlinks = true,
+            "--fail-on-binary" => fail_on_binary = true,
+            "--if-contains" => if_contains = Some(parse_flag_value(&mut iter, "--if-contains")),
+            "--exclude" => exclude.push(parse_flag_value(&mut iter, "--exclude")),
+            "--max-file-size" => {
+                max_file_size = Some(parse_flag_value(&mut iter, "--max-file-size"))
+            }
+            "--since" => {
+                let value: String = parse_flag_value(&mut iter, "--since");
+                match parse_duration(&value) {
+                    Some(duration) => since = SystemTime::now().checked_sub(duration),
+                    None => {
+                        print_usage();
+                        eprintln!(
+                            "{} --since的值'{}'无效，应为数字加上s、m、h、d或w",
+                            "错误:".red().bold(),
+                            value
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--max-total" => max_total = Some(parse_flag_value(&mut iter, "--max-total")),
+            "--report" => report = Some(parse_flag_value(&mut iter, "--report")),
+            "--output-dir" => output_dir = Some(parse_flag_value(&mut iter, "--output-dir")),
+            _ => args.push(arg),
+        }
+    }
+
+    // 使用内置变换或只统计行时不需要替换模板，只统计行或只检查时也不需要OUTPUT。
+    // 用--pair给出目标和替换文本或使用--strip-trailing-ws时两者都不需要，
+    // 替换模板来自环境变量时不需要替换模板。
+    let leading = if !pairs.is_empty() || strip_trailing_ws {
+        0
+    } else if transform.is_some()
+        || count_lines
+        || distinct
+        || files_with_matches
+        || replacement_env.is_some()
+    {
+        1
+    } else {
+        2
+    };
+    // 逐行处理标准输入时没有INPUT和OUTPUT。
+    let expected = leading
+        + if stdin_lines {
+            0
+        } else if count_lines || distinct || files_with_matches || check {
+            1
+        } else {
+            2
+        };
+    if (recursive && args.len() < leading + 1) || (!recursive && args.len() != expected) {
+        print_usage();
+        let required = if recursive {
+            format!("至少{}", leading + 1)
+        } else {
+            expected.to_string()
+        };
+        eprintln!(
+            "{} 参数数量不符：需要{}个参数，传入了{}个。",
+            "错误:".red().bold(),
+            required,
+            args.len()
+        );
+        std::process::exit(1);
+    }
+
+    // 逐行替换与捕获组替换、以及按整个文本计算的预览无法对应，不允许一起使用。
+    if per_line_limit == Some(0) || (per_line_limit.is_some() && (group.is_some() || dry_run)) {
+        eprintln!(
+            "{} --per-line-limit至少为1，且不能与--group或--dry-run同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 预览和统计各自查找所有匹配，与只替换第一处的结果对不上。
+    if once
+        && (group.is_some()
+            || per_line_limit.is_some()
+            || line_mode
+            || before.is_some()
+            || after.is_some()
+            || skip_comments.is_some()
+            || dry_run
+            || count_lines
+            || distinct
+            || files_with_matches
+            || stdin_lines
+            || binary
+            || rename)
+    {
+        eprintln!(
+            "{} --once不能与--group、--per-line-limit、--line-mode、--before、--after、--skip-comments、--dry-run、--count-lines、--distinct、-l、--stdin-lines、--binary或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if line_mode && (group.is_some() || dry_run || binary || before.is_some() || after.is_some()) {
+        eprintln!(
+            "{} --line-mode不能与--group、--dry-run、--binary、--before或--after同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 预览、统计和改名各自查找匹配，不检查两侧的文本。
+    if skip_comments.is_some()
+        && (group.is_some()
+            || per_line_limit.is_some()
+            || line_mode
+            || dry_run
+            || count_lines
+            || distinct
+            || binary
+            || rename)
+    {
+        eprintln!(
+            "{} --skip-comments不能与--group、--per-line-limit、--line-mode、--dry-run、--count-lines、--distinct、--binary或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if (before.is_some() || after.is_some())
+        && (group.is_some()
+            || per_line_limit.is_some()
+            || dry_run
+            || count_lines
+            || binary
+            || rename)
+    {
+        eprintln!(
+            "{} --before和--after不能与--group、--per-line-limit、--dry-run、--count-lines、--binary或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 字节模式是一条独立的替换路径，只支持最基本的替换。
+    if binary
+        && (group.is_some()
+            || transform.is_some()
+            || per_line_limit.is_some()
+            || dry_run
+            || count_lines)
+    {
+        eprintln!(
+            "{} --binary不能与--group、--transform、--per-line-limit、--dry-run或--count-lines同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if binary && encoding != TextEncoding::Utf8 {
+        eprintln!("{} --encoding不能与--binary同时使用", "错误:".red().bold());
+        std::process::exit(1);
+    }
+
+    // 改名时不读取文件内容，与处理内容的选项无关。
+    if rename && (!recursive || dry_run || binary || count_lines || atomic) {
+        eprintln!(
+            "{} --rename只能用于递归模式，且不能与--dry-run、--binary、--count-lines或--atomic同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if replacement_env.is_some() && (transform.is_some() || count_lines || !pairs.is_empty()) {
+        eprintln!(
+            "{} --replacement-env不能与--transform、--count-lines或--pair同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if !pairs.is_empty() && (group.is_some() || transform.is_some() || binary || plain) {
+        eprintln!(
+            "{} --pair不能与--group、--transform、--binary或--plain同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+    // 内置规则有固定的目标和替换文本。
+    if strip_trailing_ws
+        && (!pairs.is_empty()
+            || transform.is_some()
+            || replacement_env.is_some()
+            || group.is_some()
+            || plain
+            || count_lines
+            || distinct
+            || before.is_some()
+            || after.is_some())
+    {
+        eprintln!(
+            "{} --strip-trailing-ws不能与--pair、--transform、--replacement-env、--group、--plain、--count-lines、--distinct、--before或--after同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 没有替换模板，或者替换模板已经按字节模式的规则解析转义序列。
+    if interpret_escapes
+        && (binary
+            || !pairs.is_empty()
+            || transform.is_some()
+            || count_lines
+            || distinct
+            || strip_trailing_ws)
+    {
+        eprintln!(
+            "{} --interpret-escapes不能与--binary、--pair、--transform、--count-lines、--distinct或--strip-trailing-ws同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    let pairs = if pairs.is_empty() {
+        None
+    } else {
+        match Pairs::new(&pairs) {
+            Ok(pairs) => Some(pairs),
+            Err(e) => {
+                eprintln!("{} 无效的--pair目标: {}", "错误:".red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    // 递归模式下总是就地修改，没有可询问的OUTPUT。
+    if prompt_before_overwrite && (recursive || dry_run || check || count_lines) {
+        eprintln!(
+            "{} --prompt-before-overwrite只能用于单文件模式，且不能与--dry-run、--check或--count-lines同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if stdin_lines
+        && (recursive
+            || dry_run
+            || check
+            || count_lines
+            || binary
+            || rename
+            || in_place
+            || prompt_before_overwrite
+            || report.is_some()
+            || max_total.is_some())
+    {
+        eprintln!(
+            "{} --stdin-lines不能与-r、--dry-run、--check、--count-lines、--binary、--rename、--in-place、--prompt-before-overwrite、--report或--max-total同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 只有递归模式下直接替换的文件会并行处理。
+    if jobs == 0
+        || (jobs > 1
+            && (!recursive || atomic || rename || dry_run || check || count_lines || distinct))
+    {
+        eprintln!(
+            "{} --jobs至少为1，且只能用于递归模式，不能与--atomic、--rename、--dry-run、--check、--count-lines或--distinct同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if exit_count && !dry_run {
+        eprintln!(
+            "{} --exit-count只能与--dry-run一起使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 只有递归模式会跳过二进制文件。
+    if fail_on_binary && (!recursive || binary || rename) {
+        eprintln!(
+            "{} --fail-on-binary只能用于递归模式，且不能与--binary或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if since.is_some() && !recursive {
+        eprintln!("{} --since只能用于递归模式", "错误:".red().bold());
+        std::process::exit(1);
+    }
+
+    if if_contains.is_some() && (!recursive || rename) {
+        eprintln!(
+            "{} --if-contains只能用于递归模式，且不能与--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if output_dir.is_some()
+        && (!recursive || atomic || rename || dry_run || check || count_lines || distinct)
+    {
+        eprintln!(
+            "{} --output-dir只能用于递归模式，且不能与--atomic、--rename、--dry-run、--check、--count-lines或--distinct同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if report.is_some() && (dry_run || check || count_lines || rename) {
+        eprintln!(
+            "{} --report不能与--dry-run、--check、--count-lines或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 上限只作用于会写入文件的替换。
+    if max_total.is_some() && (dry_run || check || count_lines || rename) {
+        eprintln!(
+            "{} --max-total不能与--dry-run、--check、--count-lines或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 只统计匹配到的字符串，与替换相关的选项都没有意义。
+    if distinct
+        && (count_lines
+            || dry_run
+            || check
+            || rename
+            || binary
+            || stdin_lines
+            || transform.is_some()
+            || pairs.is_some()
+            || replacement_env.is_some()
+            || report.is_some()
+            || max_total.is_some()
+            || prompt_before_overwrite)
+    {
+        eprintln!(
+            "{} --distinct不能与--count-lines、--dry-run、--check、--rename、--binary、--stdin-lines、--transform、--pair、--replacement-env、--report、--max-total或--prompt-before-overwrite同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    // 只判断文件中有没有匹配，同样与替换相关的选项都没有意义。
+    if files_with_matches
+        && (count_lines
+            || distinct
+            || dry_run
+            || check
+            || rename
+            || binary
+            || stdin_lines
+            || transform.is_some()
+            || pairs.is_some()
+            || replacement_env.is_some()
+            || report.is_some()
+            || max_total.is_some()
+            || output_dir.is_some()
+            || jobs > 1
+            || skip_comments.is_some()
+            || before.is_some()
+            || after.is_some()
+            || prompt_before_overwrite)
+    {
+        eprintln!(
+            "{} -l不能与--count-lines、--distinct、--dry-run、--check、--rename、--binary、--stdin-lines、--transform、--pair、--replacement-env、--report、--max-total、--output-dir、--jobs、--skip-comments、--before、--after或--prompt-before-overwrite同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if check && (dry_run || count_lines || binary || rename) {
+        eprintln!(
+            "{} --check不能与--dry-run、--count-lines、--binary或--rename同时使用",
+            "错误:".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    let exclude = match parse_exclude(&exclude) {
+        Ok(exclude) => exclude,
+        Err(e) => {
+            eprintln!("{} 无效的--exclude模式: {}", "错误:".red().bold(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let if_contains = match if_contains.map(|guard: String| regex::bytes::Regex::new(&guard)) {
+        Some(Ok(guard)) => Some(guard),
+        Some(Err(e)) => {
+            eprintln!(
+                "{} 无效的--if-contains正则表达式: {}",
+                "错误:".red().bold(),
+                e
+            );
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let mut args = args.into_iter();
+    let mut target = match &pairs {
+        Some(pairs) => pairs.target.clone(),
+        None if strip_trailing_ws => TRAILING_WHITESPACE.to_string(),
+        None => args.next().unwrap(),
+    };
+    if pairs.is_none() && target == "-" {
+        target = match read_pattern(std::io::stdin().lock()) {
+            Ok(pattern) if !pattern.is_empty() => pattern,
+            Ok(_) => {
+                eprintln!("{} 从标准输入读到的正则表达式为空", "错误:".red().bold());
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} 从标准输入读取正则表达式失败: {}",
+                    "错误:".red().bold(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        };
+    }
+    if plain {
+        target = regex::escape(&target);
+    }
+    let replacement = if let Some(name) = &replacement_env {
+        match env_replacement(name) {
+            Ok(replacement) => replacement,
+            Err(e) => {
+                eprintln!("{} {}", "错误:".red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+    } else if transform.is_some()
+        || count_lines
+        || distinct
+        || files_with_matches
+        || pairs.is_some()
+        || strip_trailing_ws
+    {
+        String::new()
+    } else {
+        args.next().unwrap()
+    };
+    let replacement = if interpret_escapes {
+        match unescape_text(&replacement) {
+            Ok(replacement) => replacement,
+            Err(e) => {
+                eprintln!("{} {}", "错误:".red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        replacement
+    };
+    // 替换模板只在这里检查一次，引用不存在的组名时在处理任何文件之前报错。
+    let template = if transform.is_none() && pairs.is_none() && !binary {
+        match Template::checked(&replacement, &target) {
+            Ok(template) => Some(template),
+            Err(e) => {
+                eprintln!("{} {}", "错误:".red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if recursive {
+        return Arguments {
+            target,
+            replacement,
+            group,
+            transform,
+            recursive,
+            paths: args.collect(),
+            exclude,
+            follow_symlinks,
+            max_file_size,
+            since,
+            fail_on_binary,
+            if_contains,
+            max_total,
+            report,
+            output_dir,
+            atomic,
+            sync,
+            dry_run,
+            exit_count,
+            json,
+            abort_on_error,
+            jobs,
+            verbosity,
+            fail_if_no_match,
+            count_lines,
+            distinct,
+            files_with_matches,
+            check,
+            context,
+            per_line_limit,
+            line_mode,
+            once,
+            before,
+            after,
+            skip_comments,
+            summary_only,
+            binary,
+            encoding,
+            rename,
+            pairs,
+            template,
+            ..Default::default()
+        };
+    }
+    Arguments {
+        target,
+        replacement,
+        filename: args.next().unwrap_or_default(),
+        output: args.next().unwrap_or_default(),
+        in_place,
+        prompt_before_overwrite,
+        force,
+        group,
+        transform,
+        recursive,
+        paths: Vec::new(),
+        exclude,
+        follow_symlinks,
+        max_file_size,
+        since,
+        fail_on_binary,
+        if_contains,
+        max_total,
+        report,
+        output_dir,
+        atomic,
+        sync,
+        dry_run,
+        exit_count,
+        json,
+        abort_on_error,
+        jobs,
+        verbosity,
+        fail_if_no_match,
+        count_lines,
+        distinct,
+        files_with_matches,
+        check,
+        stdin_lines,
+        context,
+        per_line_limit,
+        line_mode,
+        once,
+        before,
+        after,
+        skip_comments,
+        summary_only,
+        binary,
+        encoding,
+        rename,
+        pairs,
+        template,
+    }
+}
+
+/// `--strip-trailing-ws`使用的目标：每行末尾的空格和制表符。
+///
+/// `R`标志让`$`也能匹配`\r\n`之前的位置，CRLF换行的文件中的`\r`不会被当作行尾的空白。
+const TRAILING_WHITESPACE: &str = r"(?mR)[ \t]+$";
+
+#[test]
+fn test_strip_trailing_ws() {
+    let args = Arguments {
+        target: TRAILING_WHITESPACE.to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "a b  \nc\t\t\n\t d \t\nlast ").unwrap(),
+        ("a b\nc\n\t d\nlast".to_string(), 4)
+    );
+    // 行中间和行首的空白保留，CRLF换行也保留。
+    assert_eq!(
+        replace_text(&args, "  x  y \r\n\tz\r\n").unwrap(),
+        ("  x  y\r\n\tz\r\n".to_string(), 1)
+    );
+}
+
+/// 从`reader`读取`<target>`为`-`时使用的正则表达式，去掉末尾的一个换行符。
+///
+/// 正则表达式中间的换行符原样保留，这样才能匹配跨行的文本。
+fn read_pattern(mut reader: impl std::io::Read) -> std::io::Result<String> {
+    let mut pattern = String::new();
+    reader.read_to_string(&mut pattern)?;
+    if pattern.ends_with('\n') {
+        pattern.pop();
+        if pattern.ends_with('\r') {
+            pattern.pop();
+        }
+    }
+    Ok(pattern)
+}
+
+#[test]
+fn test_read_pattern() {
+    // 含有引号、`$`、反引号、`;`和`|`等在shell中需要转义的字符。
+    let input = r#""\$\((?P<cmd>\w+) -rf \*\)"; *`[^`]*` *\|\| *'(?P<n>\d+)'"#;
+    let pattern = read_pattern(format!("{}\r\n", input).as_bytes()).unwrap();
+    assert_eq!(pattern, input);
+
+    let args = Arguments {
+        target: pattern,
+        replacement: "[$cmd $n]".to_string(),
+        ..Default::default()
+    };
+    let text = r#"echo "$(rm -rf *)"; `date` || '42' done"#;
+    assert_eq!(
+        replace_text(&args, text).unwrap(),
+        ("echo [rm 42] done".to_string(), 1)
+    );
+
+    assert_eq!(read_pattern("a\nb\n\n".as_bytes()).unwrap(), "a\nb\n");
+}
+
+/// 读取`--replacement-env`指定的环境变量`name`作为替换模板。
+///
+/// 变量的值与命令行上的`<replacement>`一样解释，可以使用`$1`等引用。
+/// 变量未设置或不是有效的Unicode时返回说明原因的错误信息。
+fn env_replacement(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|e| match e {
+        env::VarError::NotPresent => format!("环境变量'{}'未设置", name),
+        env::VarError::NotUnicode(_) => format!("环境变量'{}'的值不是有效的Unicode", name),
+    })
+}
+
+#[test]
+fn test_env_replacement() {
+    let name = "QUICKREPLACE_TEST_BUILD_NUMBER";
+    env::set_var(name, "build-$1.42");
+    let args = Arguments {
+        target: r"build-(\w+)\.0".to_string(),
+        replacement: env_replacement(name).unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "version: build-nightly.0").unwrap(),
+        ("version: build-nightly.42".to_string(), 1)
+    );
+
+    env::remove_var(name);
+    assert_eq!(
+        env_replacement(name),
+        Err(format!("环境变量'{}'未设置", name))
+    );
+}
+
+/// 读取选项`flag`后紧跟的值并解析为`T`，缺失或无法解析时打印用法并退出。
+fn parse_flag_value<T: std::str::FromStr>(
+    iter: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> T {
+    match iter.next().map(|v| v.parse()) {
+        Some(Ok(v)) => v,
+        _ => {
+            print_usage();
+            eprintln!("{} 选项{}需要一个有效的值。", "错误:".red().bold(), flag);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 按`args`中的替换模板或内置变换，以及捕获组设置替换`text`。
+///
+/// 同时返回替换的次数，即匹配的次数。`text`开头的UTF-8 BOM不参与匹配，
+/// 替换后原样保留在结果的开头，这样`^`匹配的是BOM之后真正的第一个字符。
+fn replace_text(args: &Arguments, text: &str) -> Result<(String, usize), Error> {
+    let (bom, text) = split_bom(text);
+    // 目标是普通字符串且替换文本中没有`$`引用时，不需要正则表达式。
+    let literal = match (args.transform, &args.pairs) {
+        (None, None)
+            if args.group.is_none()
+                && args.before.is_none()
+                && args.after.is_none()
+                && args.skip_comments.is_none()
+                && !args.replacement.contains('$') =>
+        {
+            literal_target(&args.target)
+        }
+        _ => None,
+    };
+    let (replaced, count) = match literal {
+        Some(literal) => replace_literal(args, &literal, text),
+        None => {
+            let regex = Regex::new(&args.target)?;
+            replace_counted(args, &regex, &Substitution::new(args)?, text)?
+        }
+    };
+    Ok((format!("{}{}", bom, replaced), count))
+}
+
+/// `args`选用的替换方式：`--pair`、`--transform`或替换模板。
+///
+/// 只需按`args`构造一次，就可以用于多段文本，如`--stdin-lines`的每一行。
+enum Substitution<'a> {
+    Pairs(&'a Pairs),
+    /// 变换及其作用的捕获组。
+    Transform(Transform, usize),
+    Template(Cow<'a, Template>),
+}
+
+impl<'a> Substitution<'a> {
+    fn new(args: &'a Arguments) -> Result<Substitution<'a>, Error> {
+        Ok(match (args.transform, &args.pairs) {
+            (_, Some(pairs)) => Substitution::Pairs(pairs),
+            (Some(transform), None) => Substitution::Transform(transform, args.group.unwrap_or(0)),
+            (None, None) => Substitution::Template(args.template()?),
+        })
+    }
+}
+
+impl Replacer for &Substitution<'_> {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        match **self {
+            Substitution::Pairs(mut pairs) => pairs.replace_append(caps, dst),
+            Substitution::Transform(transform, group) => {
+                dst.push_str(&transform.apply(&caps[group]))
+            }
+            Substitution::Template(ref template) => {
+                let mut template = template.as_ref();
+                template.replace_append(caps, dst)
+            }
+        }
+    }
+}
+
+/// `target`只匹配字面文本时，返回它所匹配的字符串，否则返回`None`。
+///
+/// `target`中不能有任何未转义的元字符；`\.`等转义的元字符（包括`--plain`用`regex::escape`
+/// 转义后的目标）还原为字符本身，其余的转义序列如`\d`、`\n`视为正则表达式。
+fn literal_target(target: &str) -> Option<String> {
+    const META: &str = r"\.+*?()|[]{}^$#&-~";
+    let mut literal = String::with_capacity(target.len());
+    let mut chars = target.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if META.contains(escaped) => literal.push(escaped),
+                _ => return None,
+            },
+            '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => {
+                return None
+            }
+            _ => literal.push(c),
+        }
+    }
+    Some(literal)
+}
+
+/// 逐行替换时每行最多替换的次数，`--line-mode`时不限次数，整个文本一起替换时为`None`。
+fn line_limit(args: &Arguments) -> Option<usize> {
+    args.per_line_limit.or(args.line_mode.then_some(usize::MAX))
+}
+
+#[test]
+fn test_line_mode() {
+    let whole = Arguments {
+        target: r"a\nb".to_string(),
+        replacement: "-".to_string(),
+        ..Default::default()
+    };
+    let lines = Arguments {
+        line_mode: true,
+        ..Default::default()
+    };
+
+    // 跨越换行符的匹配只在整个文本一起替换时存在。
+    let text = "a\nb\na";
+    assert_eq!(replace_text(&whole, text).unwrap(), ("-\na".to_string(), 1));
+    let args = Arguments {
+        target: whole.target.clone(),
+        replacement: whole.replacement.clone(),
+        ..lines
+    };
+    assert_eq!(replace_text(&args, text).unwrap(), (text.to_string(), 0));
+
+    // `^`和`$`在逐行替换时对应每行的开头和结尾，否则对应整个文本的开头和结尾。
+    let whole = Arguments {
+        target: "^a|b$".to_string(),
+        ..whole
+    };
+    assert_eq!(
+        replace_text(&whole, "ab\nab\nab").unwrap(),
+        ("-b\nab\na-".to_string(), 2)
+    );
+    let lines = Arguments {
+        target: whole.target.clone(),
+        replacement: whole.replacement.clone(),
+        ..args
+    };
+    assert_eq!(
+        replace_text(&lines, "ab\nab\nab").unwrap(),
+        ("--\n--\n--".to_string(), 6)
+    );
+
+    // 字面目标走快速路径时同样逐行替换，并且不限制每行的次数。
+    let lines = Arguments {
+        target: "a".to_string(),
+        ..lines
+    };
+    assert_eq!(
+        replace_text(&lines, "aaa\na").unwrap(),
+        ("---\n-".to_string(), 4)
+    );
+}
+
+/// `replace_text`的快速路径：用`str::replace`把字面文本`literal`替换为`args.replacement`。
+///
+/// 与正则表达式的结果完全相同，包括`--per-line-limit`时每行最多替换的次数。
+fn replace_literal(args: &Arguments, literal: &str, text: &str) -> (String, usize) {
+    let Some(limit) = line_limit(args) else {
+        if args.once {
+            let count = text.contains(literal) as usize;
+            return (text.replacen(literal, &args.replacement, 1), count);
+        }
+        let count = text.matches(literal).count();
+        return (text.replace(literal, &args.replacement), count);
+    };
+    let mut result = String::with_capacity(text.len());
+    let mut count = 0;
+    for line in text.split_inclusive('\n') {
+        let (content, ending) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        count += content.matches(literal).count().min(limit);
+        result.push_str(&content.replacen(literal, &args.replacement, limit));
+        result.push_str(ending);
+    }
+    (result, count)
+}
+
+#[test]
+fn test_plain() {
+    assert_eq!(literal_target("foo bar"), Some("foo bar".to_string()));
+    assert_eq!(
+        literal_target(&regex::escape("a.b*(c)")),
+        Some("a.b*(c)".to_string())
+    );
+    assert_eq!(literal_target("a.b"), None);
+    assert_eq!(literal_target(r"\d+"), None);
+
+    // 快速路径与正则表达式路径的结果完全相同。
+    let text = "foo foofoo\nfo foo\n\nfoo.foo";
+    for target in ["foo", "o", "oo", "\n", "foo.", "missing"] {
+        for per_line_limit in [None, Some(1), Some(2)] {
+            let args = Arguments {
+                target: regex::escape(target),
+                replacement: "<x>".to_string(),
+                per_line_limit,
+                ..Default::default()
+            };
+            assert_eq!(
+                replace_text(&args, text).unwrap(),
+                replace_counted(
+                    &args,
+                    &Regex::new(&args.target).unwrap(),
+                    &Template::new(&args.replacement),
+                    text
+                )
+                .unwrap(),
+                "{:?} {:?}",
+                target,
+                per_line_limit
+            );
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_plain() {
+    let text = "lorem ipsum dolor sit amet, consectetur adipiscing elit\n".repeat(20_000);
+    let args = Arguments {
+        target: "dolor".to_string(),
+        replacement: "pain".to_string(),
+        ..Default::default()
+    };
+    let start = std::time::Instant::now();
+    let plain = replace_text(&args, &text).unwrap();
+    let plain_elapsed = start.elapsed();
+    let start = std::time::Instant::now();
+    let target = Regex::new(&args.target).unwrap();
+    let regex = replace_counted(&args, &target, &Template::new(&args.replacement), &text).unwrap();
+    let regex_elapsed = start.elapsed();
+    assert_eq!(plain, regex);
+    println!(
+        "普通字符串: {:?}，正则表达式: {:?}",
+        plain_elapsed, regex_elapsed
+    );
+}
+
+/// 将`text`分为开头的UTF-8 BOM（没有时为空）和其余的内容。
+fn split_bom(text: &str) -> (&str, &str) {
+    match text.strip_prefix('\u{feff}') {
+        Some(rest) => (&text[..text.len() - rest.len()], rest),
+        None => ("", text),
+    }
+}
+
+#[test]
+fn test_bom() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bom.txt");
+    fs::write(&path, "\u{feff}foo\nfoo\n").unwrap();
+    let args = Arguments {
+        target: "(?m)^foo".to_string(),
+        replacement: "bar".to_string(),
+        recursive: true,
+        paths: vec![path.to_str().unwrap().to_string()],
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    // 第一行紧跟在BOM之后的foo也被替换，BOM保持不变。
+    assert_eq!(fs::read(&path).unwrap(), b"\xef\xbb\xbfbar\nbar\n");
+    assert_eq!(split_bom("plain"), ("", "plain"));
+}
+
+/// `--binary`模式下的`replace_text`：把`data`当作任意字节替换，同时返回替换的次数。
+///
+/// 正则表达式关闭了Unicode，`\xff`等转义匹配的是单个字节；
+/// 替换文本先按`unescape_bytes`解析转义序列，仍然可以用`$1`等引用捕获组。
+fn replace_bytes(args: &Arguments, data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+    let regex = regex::bytes::RegexBuilder::new(&args.target)
+        .unicode(false)
+        .build()?;
+    let replacement = unescape_bytes(&args.replacement)?;
+    let mut count = 0;
+    let replaced = regex.replace_all(data, |caps: &regex::bytes::Captures| {
+        count += 1;
+        let mut expanded = Vec::new();
+        caps.expand(&replacement, &mut expanded);
+        expanded
+    });
+    Ok((replaced.into_owned(), count))
+}
+
+/// 解析替换文本中的`\xHH`、`\0`、`\n`、`\r`、`\t`和`\\`转义序列，返回对应的字节。
+fn unescape_bytes(text: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => byte,
+                    _ => return Err(Error::InvalidEscape(format!("\\x{}", hex))),
+                }
+            }
+            Some('0') => 0,
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('\\') => b'\\',
+            Some(other) => return Err(Error::InvalidEscape(format!("\\{}", other))),
+            None => return Err(Error::InvalidEscape("\\".to_string())),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+/// 解析`--interpret-escapes`时替换模板中的`\n`、`\r`、`\t`、`\0`和`\\`转义序列。
+///
+/// `$`不是转义字符，`$1`、`$$`等照常交给替换模板处理。
+fn unescape_text(text: &str) -> Result<String, Error> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some(other) => return Err(Error::InvalidEscape(format!("\\{}", other))),
+            None => return Err(Error::InvalidEscape("\\".to_string())),
+        });
+    }
+    Ok(unescaped)
+}
+
+#[test]
+fn test_interpret_escapes() {
+    assert_eq!(unescape_text(r"a\nb").unwrap(), "a\nb");
+    assert_eq!(unescape_text(r"\t|\r\0|\\n").unwrap(), "\t|\r\0|\\n");
+    assert!(unescape_text(r"\q").is_err() && unescape_text("x\\").is_err());
+
+    let args = Arguments {
+        target: ", ".to_string(),
+        replacement: unescape_text(r"a\nb").unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "x, y").unwrap(),
+        ("xa\nby".to_string(), 1)
+    );
+    // 转义之后的`$`引用仍然有效。
+    let args = Arguments {
+        target: r"(\w+)=(\w+)".to_string(),
+        replacement: unescape_text(r"$1:\t$2\\$$").unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "k=v").unwrap(),
+        ("k:\tv\\$".to_string(), 1)
+    );
+}
+
+#[test]
+fn test_binary() {
+    assert_eq!(unescape_bytes(r"a\x00\xFF\n\\").unwrap(), b"a\x00\xff\n\\");
+    assert!(unescape_bytes(r"\x4").is_err() && unescape_bytes(r"\q").is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blob.bin");
+    fs::write(&path, b"\x7fELF\x00\x01\x02\xff\x00\x01\x02").unwrap();
+    let args = Arguments {
+        target: r"\x00\x01(\x02)".to_string(),
+        replacement: r"\xaa${1}\x00".to_string(),
+        recursive: true,
+        paths: vec![path.to_str().unwrap().to_string()],
+        binary: true,
+        ..Default::default()
+    };
+    run(&args).unwrap();
+    assert_eq!(
+        fs::read(&path).unwrap(),
+        b"\x7fELF\xaa\x02\x00\xff\xaa\x02\x00"
+    );
+}
+
+/// 按`args`中的捕获组设置用`replacement`替换`text`中`regex`的匹配，并数出替换的次数。
+///
+/// `regex`是编译好的`args.target`。
+fn replace_counted<R: Replacer>(
+    args: &Arguments,
+    regex: &Regex,
+    replacement: R,
+    text: &str,
+) -> Result<(String, usize), Error> {
+    let mut counted = Counted {
+        replacer: replacement,
+        count: 0,
+    };
+    if args.before.is_some() || args.after.is_some() || args.skip_comments.is_some() {
+        let (before, after) = (args.before.as_deref(), args.after.as_deref());
+        let comments = args
+            .skip_comments
+            .map_or_else(Vec::new, |style| style.comments(text));
+        let keep = |span: Range<usize>| {
+            anchored(text, &span, before, after)
+                && !comments.iter().any(|comment| {
+                    span.start < comment.end && comment.start < span.end.max(span.start + 1)
+                })
+        };
+        let replaced = replace_filtered(regex, keep, counted.by_ref(), text);
+        return Ok((replaced, counted.count));
+    }
+    let replaced = match (args.group, line_limit(args)) {
+        (Some(group), _) => replace_group(regex, group, counted.by_ref(), text)?,
+        (None, Some(limit)) => replace_per_line(regex, limit, counted.by_ref(), text),
+        (None, None) if args.once => regex.replacen(text, 1, counted.by_ref()).into_owned(),
+        (None, None) => regex.replace_all(text, counted.by_ref()).into_owned(),
+    };
+    Ok((replaced, counted.count))
+}
+
+/// 逐行替换`text`，每行最多替换前`limit`处匹配。
+///
+/// 每行单独匹配（不含行尾的换行符），因此匹配不会跨越多行，`^`和`$`也总是对应行首和行尾。
+fn replace_per_line<R: Replacer>(
+    regex: &Regex,
+    limit: usize,
+    mut replacement: R,
+    text: &str,
+) -> String {
+    let mut result = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (content, ending) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        result.push_str(&regex.replacen(content, limit, replacement.by_ref()));
+        result.push_str(ending);
+    }
+    result
+}
+
+#[test]
+fn test_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let files = [
+        ("a.txt", "version 1\nversion 1\n", "version 2\nversion 1\n"),
+        ("b.txt", "x version 1 version 1", "x version 2 version 1"),
+        ("c.txt", "none", "none"),
+    ];
+    // 字面目标走快速路径，正则表达式目标逐个匹配，两者结果相同。
+    for target in ["version 1", r"version \d"] {
+        for (name, text, _) in files {
+            fs::write(dir.path().join(name), text).unwrap();
+        }
+        let args = Arguments {
+            target: target.to_string(),
+            replacement: "version 2".to_string(),
+            recursive: true,
+            paths: vec![dir.path().to_str().unwrap().to_string()],
+            once: true,
+            ..Default::default()
+        };
+        let counts: Vec<usize> = run_files(&collect_files(&args).unwrap(), &args)
+            .unwrap()
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect();
+        assert_eq!(counts, [1, 1, 0]);
+        for (name, _, expected) in files {
+            assert_eq!(fs::read_to_string(dir.path().join(name)).unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn test_per_line_limit() {
+    let args = Arguments {
+        target: "a".to_string(),
+        replacement: "b".to_string(),
+        per_line_limit: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "aaa\nxa\n\nno\naa").unwrap(),
+        ("baa\nxb\n\nno\nba".to_string(), 3)
+    );
+
+    // 不足N处匹配的行全部替换；`^`匹配每一行的开头。
+    let args = Arguments {
+        target: "^a".to_string(),
+        per_line_limit: Some(5),
+        ..args
+    };
+    assert_eq!(
+        replace_text(&args, "aa\nba\na").unwrap(),
+        ("ba\nba\nb".to_string(), 2)
+    );
+}
+
+/// 记录`replace_append`被调用次数的`Replacer`，每调用一次就是一处替换。
+struct Counted<R> {
+    replacer: R,
+    count: usize,
+}
+
+impl<R: Replacer> Replacer for Counted<R> {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        self.count += 1;
+        self.replacer.replace_append(caps, dst);
+    }
+}
+
+#[test]
+fn test_replace_text_transform() {
+    let args = Arguments {
+        target: r"\w+".to_string(),
+        transform: Some(Transform::Upper),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "hello world").unwrap(),
+        ("HELLO WORLD".to_string(), 2)
+    );
+
+    let args = Arguments {
+        target: r"\[( *\w+ *)\]".to_string(),
+        group: Some(1),
+        transform: Some(Transform::Trim),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "[ a ] [b  ]").unwrap(),
+        ("[a] [b]".to_string(), 2)
+    );
+}
+
+/// 判断`text`中`span`处的匹配是否紧接在`before`之后、并且后面紧跟着`after`，
+/// 为`None`时不检查这一侧。
+fn anchored(text: &str, span: &Range<usize>, before: Option<&str>, after: Option<&str>) -> bool {
+    before.is_none_or(|before| text[..span.start].ends_with(before))
+        && after.is_none_or(|after| text[span.end..].starts_with(after))
+}
+
+/// 与`replace`类似，但只替换`keep`对其范围返回`true`的匹配，用于`--before`、`--after`
+/// 和`--skip-comments`。
+///
+/// `regex`库不支持环视，因此先照常查找每处匹配，再检查匹配两侧的文本。
+/// 被跳过的匹配仍然占据它所覆盖的文本，与它重叠的位置不会再被尝试匹配。
+fn replace_filtered<R: Replacer>(
+    regex: &Regex,
+    mut keep: impl FnMut(Range<usize>) -> bool,
+    mut replacement: R,
+    text: &str,
+) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for caps in regex.captures_iter(text) {
+        let span = caps.get(0).unwrap();
+        if keep(span.range()) {
+            result.push_str(&text[last..span.start()]);
+            replacement.replace_append(&caps, &mut result);
+            last = span.end();
+        }
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+#[test]
+fn test_replace_anchored() {
+    let args = Arguments {
+        target: "cat".to_string(),
+        replacement: "X".to_string(),
+        after: Some("s".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "cat cats").unwrap(),
+        ("cat Xs".to_string(), 1)
+    );
+
+    let args = Arguments {
+        target: r"\d+".to_string(),
+        replacement: "[$0]".to_string(),
+        before: Some("v".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "v1 2 v34").unwrap(),
+        ("v[1] 2 v[34]".to_string(), 2)
+    );
+
+    // 两侧都给定时两侧都要满足。
+    let args = Arguments {
+        target: "b".to_string(),
+        replacement: "-".to_string(),
+        before: Some("a".to_string()),
+        after: Some("c".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "abc abd xbc").unwrap(),
+        ("a-c abd xbc".to_string(), 1)
+    );
+}
+
+#[test]
+fn test_skip_comments() {
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "bar".to_string(),
+        skip_comments: Some(CommentStyle::C),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "foo(); // call foo\n").unwrap(),
+        ("bar(); // call foo\n".to_string(), 1)
+    );
+    assert_eq!(
+        replace_text(&args, "/* foo */ let s = \"// foo\"; foo").unwrap(),
+        ("/* foo */ let s = \"// bar\"; bar".to_string(), 2)
+    );
+
+    // 与注释部分重叠的匹配也被跳过，与--after一起使用时两个条件都要满足。
+    let args = Arguments {
+        target: r"x\s*/".to_string(),
+        replacement: "-".to_string(),
+        skip_comments: Some(CommentStyle::C),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "x / 2; x // y").unwrap(),
+        ("- 2; x // y".to_string(), 1)
+    );
+    let args = Arguments {
+        target: "a".to_string(),
+        replacement: "b".to_string(),
+        after: Some("1".to_string()),
+        skip_comments: Some(CommentStyle::Hash),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_text(&args, "a1 a2 # a1").unwrap(),
+        ("b1 a2 # a1".to_string(), 1)
+    );
+}
+
+/// 与`replace`类似，但只替换每个匹配中第`group`个捕获组所覆盖的文本。
+///
+/// 匹配中捕获组以外的部分原样保留，`replacement`为模板时仍可以使用`$1`等捕获引用。
+/// 某次匹配中该捕获组未参与匹配时，这次匹配保持不变。
+fn replace_group<R: Replacer>(
+    regex: &Regex,
+    group: usize,
+    mut replacement: R,
+    text: &str,
+) -> Result<String, Error> {
+    if group >= regex.captures_len() {
+        return Err(Error::NoSuchGroup(group));
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for caps in regex.captures_iter(text) {
+        if let Some(span) = caps.get(group) {
+            result.push_str(&text[last..span.start()]);
+            replacement.replace_append(&caps, &mut result);
+            last = span.end();
+        }
+    }
+    result.push_str(&text[last..]);
+
+    Ok(result)
+}
+
+#[test]
+fn test_replace_group() {
+    let version = Regex::new(r"v(\d+)").unwrap();
+    assert_eq!(replace_group(&version, 1, "9", "v1 v2").unwrap(), "v9 v9");
+    let quoted = Regex::new(r#"version = "(\d+)""#).unwrap();
+    assert_eq!(
+        replace_group(&quoted, 1, "${1}0", r#"version = "3""#).unwrap(),
+        r#"version = "30""#
+    );
+    assert_eq!(replace_group(&version, 0, "x", "v1 v2").unwrap(), "x x");
+    assert!(matches!(
+        replace_group(&version, 2, "9", "v1"),
+        Err(Error::NoSuchGroup(2))
+    ));
+}
+
+/// 一处将要进行的替换，位置以从1开始的行号和列号（按字符计）表示。
+#[derive(Debug, PartialEq)]
+struct Change {
+    line: usize,
+    column: usize,
+    old: String,
+    new: String,
+}
+
+/// 按`args`找出`text`中将要进行的每处替换，与`replace_text`的结果一一对应。
+fn find_changes(args: &Arguments, text: &str) -> Result<Vec<Change>, Error> {
+    let group = args.group.unwrap_or(0);
+    match (args.transform, &args.pairs) {
+        (_, Some(pairs)) => collect_changes(&pairs.target, 0, pairs, text),
+        (Some(transform), None) => {
+            let replacer = |caps: &Captures| transform.apply(&caps[group]);
+            collect_changes(&args.target, group, replacer, text)
+        }
+        (None, None) => {
+            let template = args.template()?;
+            collect_changes(&args.target, group, template.as_ref(), text)
+        }
+    }
+}
+
+/// 对`target`的每个匹配，记下第`group`个捕获组的位置、原文本和替换后的文本。
+fn collect_changes<R: Replacer>(
+    target: &str,
+    group: usize,
+    mut replacement: R,
+    text: &str,
+) -> Result<Vec<Change>, Error> {
+    let regex = Regex::new(target)?;
+    if group >= regex.captures_len() {
+        return Err(Error::NoSuchGroup(group));
+    }
+
+    let mut changes = Vec::new();
+    // 从上一处匹配继续数行号和列号，避免每次都从头扫描。
+    let (mut offset, mut line, mut line_start) = (0, 1, 0);
+    for caps in regex.captures_iter(text) {
+        let span = match caps.get(group) {
+            Some(span) => span,
+            None => continue,
+        };
+        for (i, _) in text[offset..span.start()].match_indices('\n') {
+            line += 1;
+            line_start = offset + i + 1;
+        }
+        offset = span.start();
+
+        let mut new = String::new();
+        replacement.replace_append(&caps, &mut new);
+        changes.push(Change {
+            line,
+            column: text[line_start..span.start()].chars().count() + 1,
+            old: span.as_str().to_string(),
+            new,
+        });
+    }
+    Ok(changes)
+}
+
+#[test]
+fn test_find_changes() {
+    let args = Arguments {
+        target: r"(\w+)@example\.com".to_string(),
+        replacement: "$1@example.org".to_string(),
+        ..Default::default()
+    };
+    let text = "联系方式：\n  写信给 bob@example.com 即可\n";
+    assert_eq!(
+        find_changes(&args, text).unwrap(),
+        vec![Change {
+            line: 2,
+            column: 7,
+            old: "bob@example.com".to_string(),
+            new: "bob@example.org".to_string(),
+        }]
+    );
+    assert_eq!(
+        change_json(Path::new("a.txt"), &find_changes(&args, text).unwrap()[0]),
+        r#"{"file":"a.txt","line":2,"column":7,"old":"bob@example.com","new":"bob@example.org"}"#
+    );
+
+    // `--pair`的预览与实际替换一致。
+    let pairs = Pairs::new(&[split_pair("a=b").unwrap(), split_pair("b=a").unwrap()]).unwrap();
+    let args = Arguments {
+        target: pairs.target.clone(),
+        pairs: Some(pairs),
+        ..Default::default()
+    };
+    let changes = find_changes(&args, "ab\nb").unwrap();
+    assert_eq!(
+        changes
+            .iter()
+            .map(|change| (change.line, change.column, change.new.as_str()))
+            .collect::<Vec<_>>(),
+        [(1, 1, "b"), (1, 2, "a"), (2, 1, "a")]
+    );
+}
+
+/// 读取只报告、不修改文件的各模式的输入：递归模式下为各路径下的文本文件，否则为INPUT。
+fn read_inputs(args: &Arguments) -> Result<Vec<(PathBuf, String)>, Error> {
+    let mut inputs = Vec::new();
+    if args.recursive {
+        for path in collect_files(args)? {
+            if let Some((data, _)) = read_text_file(&path, args)? {
+                inputs.push((path, data));
+            }
+        }
+    } else {
+        let path = PathBuf::from(&args.filename);
+        let bytes = fs::read(&path).map_err(|e| Error::Read(path.clone(), e))?;
+        let (data, _) = args.encoding.decode(&path, bytes)?;
+        inputs.push((path, data));
+    }
+    Ok(inputs)
+}
+
+/// 执行`--check`：替换每个输入但不写入，有文件会因此改变时返回列出这些文件的`Error::WouldChange`。
+///
+/// 有匹配但替换后与原文相同的文件不算需要修改。没有文件需要修改时返回匹配的总次数。
+fn run_check(args: &Arguments) -> Result<usize, Error> {
+    let mut matches = 0;
+    let mut changed = Vec::new();
+    for (path, data) in read_inputs(args)? {
+        let (replaced, count) = replace_text(args, &data)?;
+        log::debug!("已检查'{}'：{}处匹配", path.display(), count);
+        matches += count;
+        if replaced != data {
+            changed.push(path);
+        }
+    }
+    if !changed.is_empty() {
+        return Err(Error::WouldChange(changed));
+    }
+    Ok(matches)
+}
+
+#[test]
+fn test_check() {
+    let dir = tempfile::tempdir().unwrap();
+    let done = dir.path().join("done.txt");
+    let pending = dir.path().join("pending.txt");
+    fs::write(&done, "color").unwrap();
+    fs::write(&pending, "colour").unwrap();
+    let mut args = Arguments {
+        target: "colou?r".to_string(),
+        replacement: "color".to_string(),
+        filename: done.to_str().unwrap().to_string(),
+        check: true,
+        ..Default::default()
+    };
+    // 已经是想要的结果：有匹配，但替换后不变。
+    run(&args).unwrap();
+
+    args.recursive = true;
+    args.paths = vec![dir.path().to_str().unwrap().to_string()];
+    let error = run(&args).unwrap_err();
+    assert!(matches!(&error, Error::WouldChange(paths) if *paths == [pending.clone()]));
+    assert!(error
+        .to_string()
+        .ends_with(&format!("1个文件需要修改:\n    {}", pending.display())));
+    // 只检查，不写入。
+    assert_eq!(fs::read_to_string(&pending).unwrap(), "colour");
+}
+
+/// 执行`--dry-run`：打印每个文件中将要进行的替换，不写入任何文件。
+///
+/// 给定`--summary-only`时只打印每个文件的匹配次数和总数。返回所有输入中匹配的总次数。
+fn run_dry(args: &Arguments) -> Result<usize, Error> {
+    let inputs = read_inputs(args)?;
+    if args.summary_only {
+        let mut counts = Vec::new();
+        for (path, data) in &inputs {
+            counts.push((path.clone(), count_matches(args, split_bom(data).1)?));
+        }
+        write_summary(&mut std::io::stdout(), &counts)
+            .map_err(|e| Error::Write(PathBuf::from("<stdout>"), e))?;
+        return Ok(counts.iter().map(|(_, count)| count).sum());
+    }
+
+    let width = terminal_size::terminal_size()
+        .map(|(terminal_size::Width(w), _)| w as usize)
+        .unwrap_or(80);
+    let mut entries = Vec::new();
+    let mut matches = 0;
+    for (path, data) in &inputs {
+        let (_, data) = split_bom(data);
+        let lines: Vec<&str> = data.lines().collect();
+        let changes = find_changes(args, data)?;
+        matches += changes.len();
+        if args.json {
+            entries.extend(changes.iter().map(|change| change_json(path, change)));
+            continue;
+        }
+
+        // 原文本以删除线标红，紧跟着绿色的替换文本。跨行的匹配只显示第一行。
+        let preview = |change: &Change| {
+            let prefix = format!("{}:{}:{}: ", path.display(), change.line, change.column);
+            let old = change.old.lines().next().unwrap_or("");
+            let (before, after) = truncate_around(
+                lines.get(change.line - 1).copied().unwrap_or(""),
+                change.column - 1,
+                old.chars().count(),
+                change.new.chars().count(),
+                width.saturating_sub(prefix.chars().count()),
+            );
+            format!(
+                "{}{}{}{}{}",
+                prefix,
+                before,
+                old.red().strikethrough(),
+                change.new.green(),
+                after
+            )
+        };
+        if args.context == 0 {
+            for change in &changes {
+                println!("{}", preview(change));
+            }
+            continue;
+        }
+
+        // 与grep一样，上下文行以`-`分隔行号，重叠的上下文合并为一组，各组之间以`--`隔开。
+        let match_lines: Vec<usize> = changes.iter().map(|change| change.line).collect();
+        let ranges = context_ranges(&match_lines, args.context, lines.len());
+        for (i, range) in ranges.into_iter().enumerate() {
+            if i > 0 {
+                println!("--");
+            }
+            for line in range {
+                let mut on_line = changes
+                    .iter()
+                    .filter(|change| change.line == line)
+                    .peekable();
+                if on_line.peek().is_none() {
+                    let text = lines.get(line - 1).copied().unwrap_or("");
+                    println!("{}-{}- {}", path.display(), line, text.dimmed());
+                }
+                for change in on_line {
+                    println!("{}", preview(change));
+                }
+            }
+        }
+    }
+    if args.json {
+        println!("[{}]", entries.join(","));
+    }
+    Ok(matches)
+}
+
+/// 求出在`match_lines`中的每一行（从1开始）前后各显示`context`行时要显示的行范围。
+///
+/// 范围限制在`1..=line_count`之内，重叠或首尾相接的范围会被合并，因此每行最多显示一次。
+/// `match_lines`须按升序排列。
+fn context_ranges(
+    match_lines: &[usize],
+    context: usize,
+    line_count: usize,
+) -> Vec<RangeInclusive<usize>> {
+    let mut ranges: Vec<RangeInclusive<usize>> = Vec::new();
+    for &line in match_lines {
+        let start = line.saturating_sub(context).max(1);
+        let end = (line + context).min(line_count.max(line));
+        match ranges.last_mut() {
+            Some(last) if start <= *last.end() + 1 => *last = *last.start()..=end.max(*last.end()),
+            _ => ranges.push(start..=end),
+        }
+    }
+    ranges
+}
+
+#[test]
+fn test_context_ranges() {
+    assert_eq!(context_ranges(&[5], 2, 10), vec![3..=7]);
+    // 相邻的两处匹配共用上下文，第3到8行各显示一次。
+    assert_eq!(context_ranges(&[5, 5, 6], 2, 10), vec![3..=8]);
+    // 首尾相接的范围也会合并，范围在文件两端截断。
+    assert_eq!(context_ranges(&[1, 6, 10], 2, 10), vec![1..=10]);
+    assert_eq!(context_ranges(&[2, 9], 1, 10), vec![1..=3, 8..=10]);
+    assert_eq!(context_ranges(&[], 2, 10), vec![]);
+}
+
+/// 数出`text`中目标（给定`--group`时为该分组）的全部匹配个数，不生成替换后的文本。
+///
+/// 不考虑`--once`、`--before`、`--after`等限制，所以可能多于`replace_text`返回的次数。
+fn count_matches(args: &Arguments, text: &str) -> Result<usize, Error> {
+    let regex = Regex::new(&args.target)?;
+    let group = args.group.unwrap_or(0);
+    if group >= regex.captures_len() {
+        return Err(Error::NoSuchGroup(group));
+    }
+    Ok(regex
+        .captures_iter(text)
+        .filter(|caps| caps.get(group).is_some())
+        .count())
+}
+
+/// 向`out`输出`--summary-only`的结果：每个有匹配的文件一行，最后是总数。
+fn write_summary(
+    out: &mut impl std::io::Write,
+    counts: &[(PathBuf, usize)],
+) -> std::io::Result<()> {
+    let mut total = 0;
+    let mut files = 0;
+    for (path, count) in counts.iter().filter(|(_, count)| *count > 0) {
+        writeln!(out, "{}: {}处匹配", path.display(), count)?;
+        total += count;
+        files += 1;
+    }
+    writeln!(out, "共{}处匹配，涉及{}个文件", total, files)
+}
+
+#[test]
+fn test_exit_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, "foo foo\nbar foo\n").unwrap();
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "baz".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        dry_run: true,
+        summary_only: true,
+        exit_count: true,
+        ..Default::default()
+    };
+    assert_eq!(exit_count(run(&args).unwrap()), 3);
+    assert_eq!(fs::read_to_string(&input).unwrap(), "foo foo\nbar foo\n");
+
+    assert_eq!(exit_count(0), 0);
+    assert_eq!(exit_count(1000), 255);
+}
+
+#[test]
+fn test_summary_only() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, text) in [("a.txt", "foo foo"), ("b.txt", "bar"), ("c.txt", "foo")] {
+        fs::write(dir.path().join(name), text).unwrap();
+    }
+    let args = Arguments {
+        target: "foo".to_string(),
+        replacement: "baz".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        dry_run: true,
+        summary_only: true,
+        ..Default::default()
+    };
+    let mut counts = Vec::new();
+    for (path, data) in read_inputs(&args).unwrap() {
+        counts.push((path, count_matches(&args, &data).unwrap()));
+    }
+    let mut out = Vec::new();
+    write_summary(&mut out, &counts).unwrap();
+    let a = dir.path().join("a.txt");
+    let c = dir.path().join("c.txt");
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "{}: 2处匹配\n{}: 1处匹配\n共3处匹配，涉及2个文件\n",
+            a.display(),
+            c.display()
+        )
+    );
+    assert_eq!(run_dry(&args).unwrap(), 3);
+    // 只是预览，文件保持不变。
+    assert_eq!(fs::read_to_string(&a).unwrap(), "foo foo");
+}
+
+/// 从`reader`逐行读取，对每行分别替换后写到`writer`，返回匹配总数。
+///
+/// 每写完一行就刷新`writer`，因此可以作为管道中的过滤器处理没有尽头的输入。
+/// 每行单独匹配，行尾的换行符不参与匹配，所以匹配不会跨行。
+fn run_stdin_lines(
+    args: &Arguments,
+    mut reader: impl std::io::BufRead,
+    writer: &mut impl std::io::Write,
+) -> Result<usize, Error> {
+    let read_error = |e| Error::Read(PathBuf::from("<stdin>"), e);
+    let write_error = |e| Error::Write(PathBuf::from("<stdout>"), e);
+
+    // 正则表达式和替换方式只编译一次，每行直接使用。
+    let regex = Regex::new(&args.target)?;
+    let substitution = Substitution::new(args)?;
+    let mut matches = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(read_error)? == 0 {
+            break;
+        }
+        let content = line.strip_suffix('\n').unwrap_or(&line);
+        let (bom, text) = split_bom(content);
+        let (replaced, count) = replace_counted(args, &regex, &substitution, text)?;
+        matches += count;
+        writer
+            .write_all(bom.as_bytes())
+            .and_then(|_| writer.write_all(replaced.as_bytes()))
+            .and_then(|_| writer.write_all(&line.as_bytes()[content.len()..]))
+            .and_then(|_| writer.flush())
+            .map_err(write_error)?;
+    }
+    Ok(matches)
+}
+
+#[test]
+fn test_stdin_lines() {
+    let args = Arguments {
+        target: "o+".to_string(),
+        replacement: "0".to_string(),
+        stdin_lines: true,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    let matches = run_stdin_lines(&args, "foo\nbar\n\nboo boo".as_bytes(), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "f0\nbar\n\nb0 b0");
+    assert_eq!(matches, 3);
+
+    // 匹配不会跨行。
+    let args = Arguments {
+        target: "a\nb".to_string(),
+        replacement: "x".to_string(),
+        stdin_lines: true,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    let matches = run_stdin_lines(&args, "a\nb\n".as_bytes(), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "a\nb\n");
+    assert_eq!(matches, 0);
+}
+
+/// 按`args`读取输入，对每个至少有一处匹配的行打印`行号: 次数`，返回匹配的总次数。
+///
+/// 递归模式下每行之前还会加上文件路径。
+fn run_count_lines(args: &Arguments) -> Result<usize, Error> {
+    let regex = Regex::new(&args.target)?;
+    let inputs = read_inputs(args)?;
+
+    let mut matches = 0;
+    for (path, data) in &inputs {
+        for (line, count) in count_lines(&regex, split_bom(data).1) {
+            matches += count;
+            if args.recursive {
+                println!("{}:{}: {}", path.display(), line, count);
+            } else {
+                println!("{}: {}", line, count);
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// 逐行统计`regex`在`text`中的匹配次数，返回至少有一处匹配的行的行号（从1开始）和次数。
+///
+/// 每行单独匹配，因此跨行的匹配不会被计入。
+fn count_lines(regex: &Regex, text: &str) -> Vec<(usize, usize)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, regex.find_iter(line).count()))
+        .filter(|&(_, count)| count > 0)
+        .collect()
+}
+
+#[test]
+fn test_count_lines() {
+    let regex = Regex::new("fo+").unwrap();
+    let text = "foo and foo\nbar\nfoo fo f\nbaz\n";
+    assert_eq!(count_lines(&regex, text), vec![(1, 2), (3, 2)]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, text).unwrap();
+    let args = Arguments {
+        target: "fo+".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        count_lines: true,
+        ..Default::default()
+    };
+    assert_eq!(run_count_lines(&args).unwrap(), 4);
+    // 只报告，不修改输入。
+    assert_eq!(fs::read_to_string(&input).unwrap(), text);
+}
+
+/// 统计所有输入中`args.target`匹配到的每个不同的字符串，按“字符串: 次数”逐行打印，返回匹配总数。
+///
+/// 递归模式下所有文件的结果合在一起统计，与`sort | uniq -c`类似。
+fn run_distinct(args: &Arguments) -> Result<usize, Error> {
+    let regex = Regex::new(&args.target)?;
+    let inputs = read_inputs(args)?;
+
+    let distinct = count_distinct(&regex, inputs.iter().map(|(_, data)| split_bom(data).1));
+    for (text, count) in &distinct {
+        println!("{}: {}", text, count);
+    }
+    Ok(distinct.iter().map(|(_, count)| count).sum())
+}
+
+/// 执行`-l`：把至少有一处匹配的输入的路径逐行写到`writer`，返回这样的文件数。
+///
+/// 每个文件找到第一处匹配就不再继续查找。
+fn run_files_with_matches(
+    args: &Arguments,
+    writer: &mut impl std::io::Write,
+) -> Result<usize, Error> {
+    let regex = Regex::new(&args.target)?;
+    let mut matched = 0;
+    for (path, data) in read_inputs(args)? {
+        if regex.is_match(split_bom(&data).1) {
+            matched += 1;
+            writeln!(writer, "{}", path.display())
+                .map_err(|e| Error::Write(PathBuf::from("<stdout>"), e))?;
+        }
+    }
+    Ok(matched)
+}
+
+#[test]
+fn test_files_with_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("a.txt"), "foo").unwrap();
+    fs::write(dir.path().join("b.txt"), "bar").unwrap();
+    fs::write(dir.path().join("sub").join("c.txt"), "bar\nfoo foo").unwrap();
+    fs::write(dir.path().join("sub").join("d.bin"), b"foo\0").unwrap();
+
+    let args = Arguments {
+        target: "fo+".to_string(),
+        recursive: true,
+        paths: vec![dir.path().to_str().unwrap().to_string()],
+        files_with_matches: true,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    assert_eq!(run_files_with_matches(&args, &mut out).unwrap(), 2);
+    // 只列出有匹配的文本文件，每行一个，按路径排列。
+    let expected = format!(
+        "{}\n{}\n",
+        dir.path().join("a.txt").display(),
+        dir.path().join("sub").join("c.txt").display()
+    );
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+    // 只报告，不修改输入。
+    assert_eq!(
+        fs::read_to_string(dir.path().join("sub").join("c.txt")).unwrap(),
+        "bar\nfoo foo"
+    );
+}
+
+/// 统计`regex`在各段文本`texts`中匹配到的每个不同的字符串的出现次数。
+///
+/// 结果按次数从多到少排列，次数相同时按字符串排列。
+fn count_distinct<'a>(
+    regex: &Regex,
+    texts: impl IntoIterator<Item = &'a str>,
+) -> Vec<(String, usize)> {
+    let mut counts = HashMap::new();
+    for text in texts {
+        for m in regex.find_iter(text) {
+            *counts.entry(m.as_str().to_string()).or_insert(0) += 1;
+        }
+    }
+    let mut distinct: Vec<(String, usize)> = counts.into_iter().collect();
+    distinct.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+    distinct
+}
+
+#[test]
+fn test_distinct() {
+    let regex = Regex::new(r"\w+").unwrap();
+    assert_eq!(
+        count_distinct(&regex, ["a a b"]),
+        vec![("a".to_string(), 2), ("b".to_string(), 1)]
+    );
+    // 多段文本合在一起统计，次数相同时按字符串排列。
+    assert_eq!(
+        count_distinct(&regex, ["c b", "b a c"]),
+        vec![
+            ("b".to_string(), 2),
+            ("c".to_string(), 2),
+            ("a".to_string(), 1)
+        ]
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, "a a b").unwrap();
+    let args = Arguments {
+        target: r"\w+".to_string(),
+        filename: input.to_str().unwrap().to_string(),
+        distinct: true,
+        ..Default::default()
+    };
+    assert_eq!(run_distinct(&args).unwrap(), 3);
+    // 只报告，不修改输入。
+    assert_eq!(fs::read_to_string(&input).unwrap(), "a a b");
+}
+
+/// 为了在`width`个字符内显示`line`中从第`start`个字符开始、长`len`个字符的匹配，
+/// 截取匹配前后的上下文，返回匹配之前和之后要显示的文本。
+///
+/// 匹配之后还会显示`extra`个字符的替换文本，同样计入宽度。行太长时匹配居于中间，
+/// 被截掉的一侧以`…`表示；匹配本身放不下时两侧的上下文都会被省略。
+fn truncate_around(
+    line: &str,
+    start: usize,
+    len: usize,
+    extra: usize,
+    width: usize,
+) -> (String, String) {
+    let chars: Vec<char> = line.chars().collect();
+    let start = start.min(chars.len());
+    let end = (start + len).min(chars.len());
+    let (head, tail) = (&chars[..start], &chars[end..]);
+    let budget = width.saturating_sub(end - start + extra);
+    if head.len() + tail.len() <= budget {
+        return (head.iter().collect(), tail.iter().collect());
+    }
+
+    // 每个被截掉的一侧都要留出一个字符给`…`；一侧用不完的宽度让给另一侧。
+    let half = budget / 2;
+    let (left, right) = if tail.len() <= half {
+        (budget.saturating_sub(tail.len() + 1), tail.len())
+    } else if head.len() <= half {
+        (head.len(), budget.saturating_sub(head.len() + 1))
+    } else {
+        let context = budget.saturating_sub(2);
+        (context / 2, context - context / 2)
+    };
+    let before = if left < head.len() {
+        std::iter::once('…')
+            .chain(head[head.len() - left..].iter().copied())
+            .collect()
+    } else {
+        head.iter().collect()
+    };
+    let after = if right < tail.len() {
+        tail[..right].iter().chain(std::iter::once(&'…')).collect()
+    } else {
+        tail.iter().collect()
+    };
+    (before, after)
+}
+
+#[test]
+fn test_truncate_around() {
+    // 短行原样显示。
+    assert_eq!(
+        truncate_around("a foo b", 2, 3, 3, 80),
+        ("a ".to_string(), " b".to_string())
+    );
+
+    // 长行中靠近末尾的匹配仍然完整可见，左侧被截掉。
+    let line = format!("{}needle tail", "x".repeat(200));
+    let (before, after) = truncate_around(&line, 200, 6, 3, 40);
+    assert!(before.starts_with('…') && before.ends_with('x'));
+    assert_eq!(after, " tail");
+    assert_eq!(before.chars().count() + 6 + 3 + after.chars().count(), 40);
+
+    // 匹配位于中间时两侧都被截掉，上下文大致相等。
+    let line = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+    let (before, after) = truncate_around(&line, 100, 6, 0, 26);
+    assert_eq!(before, format!("…{}", "a".repeat(9)));
+    assert_eq!(after, format!("{}…", "b".repeat(9)));
+}
+
+/// 将`path`中的一处替换格式化为JSON对象。
+fn change_json(path: &Path, change: &Change) -> String {
+    format!(
+        r#"{{"file":{},"line":{},"column":{},"old":{},"new":{}}}"#,
+        json_string(&path.to_string_lossy()),
+        change.line,
+        change.column,
+        json_string(&change.old),
+        json_string(&change.new)
+    )
+}
+
+/// 将`s`格式化为带引号的JSON字符串。
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[test]
+fn test_json_string() {
+    assert_eq!(json_string("a\"b\\c\n\u{1}"), r#""a\"b\\c\n\u0001""#);
 }